@@ -1,12 +1,32 @@
-use std::pin::Pin;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsString,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    pin::Pin,
+    rc::Rc,
+    time::Duration,
+};
 
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
 use clap::{ArgAction, Parser};
+use color_eyre::eyre::eyre;
 use counter::Counter;
 use fred::{
+    clients::Pipeline,
     prelude::*,
-    types::scan::{ScanResult, Scanner},
+    types::{
+        ClusterHash, CustomCommand, InfoKind, SlowlogEntry,
+        client::ClientKillFilter,
+        cluster::ClusterRouting,
+        scan::{ScanResult, ScanType, Scanner},
+    },
 };
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use regex::bytes::Regex;
+use serde_json_path::JsonPath;
 use tokio_stream::{Stream, StreamExt};
 
 /// A collection of useful commands to work with Redis / Valkey
@@ -33,18 +53,65 @@ struct RedisInfo {
 #[derive(Debug, clap::Subcommand)]
 enum Commands {
     /// List all the keys matching a pattern
-    ScanKeys(ScanOptions),
+    ScanKeys {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+        output: OutputFormat,
+
+        /// Terminate each key with a NUL byte instead of a newline, like `find -print0`
+        #[arg(short = '0', long, action, conflicts_with = "output")]
+        print0: bool,
+
+        /// Show the remaining time to live of each key, in seconds
+        #[arg(long, action)]
+        with_ttl: bool,
+
+        /// Show the type of each key
+        #[arg(long, action)]
+        with_type: bool,
+
+        /// Show the memory used by each key
+        #[arg(long, action)]
+        with_size: bool,
+
+        /// Show sizes in bytes instead of a human readable format
+        #[arg(long, action, requires = "with_size")]
+        bytes: bool,
+
+        /// Show the cluster node owning each key, or count the keys of each node with --count
+        #[arg(long, action)]
+        by_node: bool,
+
+        /// Only print the number of matching keys
+        #[arg(long, action, conflicts_with_all = ["output", "print0", "with_ttl", "with_type", "with_size"])]
+        count: bool,
+
+        /// Also print the scan rate when counting
+        #[arg(short, long, action, requires = "count")]
+        verbose: bool,
+
+        /// Count again at this interval (e.g. "10s"), showing the change since the previous count
+        #[arg(long, requires = "count", value_parser = humantime::parse_duration)]
+        watch: Option<Duration>,
+
+        /// Write the keys to this file instead of stdout, `-` meaning stdout
+        #[arg(long, conflicts_with = "count")]
+        out: Option<PathBuf>,
+    },
 
     /// Get all the values for keys matching a pattern
     ///
-    /// Only work with string values
+    /// Work with strings, hashes (printed as field=value pairs), lists, sets and sorted sets
+    /// (printed one element per line, prefixed by the key). Other keys are skipped.
     AllItems {
         #[command(flatten)]
         scan_options: ScanOptions,
 
-        /// Limit the number of items returned
-        #[arg(short, long)]
-        limit: Option<usize>,
+        #[command(flatten)]
+        item_options: ItemOptions,
     },
 
     /// Delete all the keys matching a pattern
@@ -52,9 +119,59 @@ enum Commands {
         #[command(flatten)]
         scan_options: ScanOptions,
 
-        /// Execute the deletion
-        #[arg(long = "no-dry-run", default_value_t=true, action = ArgAction::SetFalse)]
-        dry_run: bool,
+        #[command(flatten)]
+        delete_options: DeleteOptions,
+    },
+
+    /// Set a TTL on all the keys matching a pattern
+    ExpirePattern {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        #[command(flatten)]
+        expiry_options: ExpiryOptions,
+    },
+
+    /// Update the last access time of all the keys matching a pattern, with TOUCH
+    TouchPattern {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        #[command(flatten)]
+        batch_options: BatchOptions,
+    },
+
+    /// Copy all the keys matching a pattern to new names, keeping their TTL
+    ///
+    /// Keys are copied with COPY on Redis 6.2 and later, and with DUMP and RESTORE otherwise or
+    /// when both names don't belong to the same cluster slot.
+    CopyPattern {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        #[command(flatten)]
+        copy_options: CopyOptions,
+    },
+
+    /// Rename all the keys matching a pattern, keeping their TTL
+    ///
+    /// Keys are renamed with RENAME, or moved with DUMP, RESTORE and DEL when both names don't
+    /// belong to the same cluster slot.
+    RenamePattern {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        #[command(flatten)]
+        rename_options: RenameOptions,
+    },
+
+    /// Remove the TTL of all the keys matching a pattern
+    PersistPattern {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        #[command(flatten)]
+        batch_options: BatchOptions,
     },
 
     /// Count the numbers of keys with a common key structure
@@ -62,17 +179,8 @@ enum Commands {
         #[command(flatten)]
         scan_options: ScanOptions,
 
-        /// Delimiter used by each group
-        ///
-        /// For instance, the key "abc:123:456" will belong to group "abc"
-        #[arg(short, long, default_value = ":")]
-        delimiter: String,
-
-        /// Common prefix to remove before computing the stats
-        ///
-        /// For instance, with the prefix "abc:", key "abc:123:456" will belong to group "123"
-        #[arg(long)]
-        prefix: Option<String>,
+        #[command(flatten)]
+        stats_options: StatsOptions,
     },
 
     /// Find the keys using the most memory
@@ -84,217 +192,10808 @@ enum Commands {
         #[arg(short, long, default_value = "10")]
         top_n: usize,
     },
-}
 
-#[tokio::main]
-async fn main() -> color_eyre::Result<()> {
-    color_eyre::install()?;
-    let args = Args::parse();
-    let client = setup_client(&args.redis).await?;
-    match args.command {
-        Commands::ScanKeys(scan_options) => {
-            let keys = scan(&client, &scan_options).await?;
-            keys.iter().for_each(|key| println!("{key}"));
-        }
-        Commands::AllItems {
-            scan_options,
-            limit,
-        } => {
-            all_items(&client, &scan_options, limit).await?;
-        }
-        Commands::DelPattern {
-            scan_options,
-            dry_run,
-        } => {
-            del_pattern(&client, &scan_options, dry_run).await?;
-        }
-        Commands::ComputeStats {
-            scan_options,
-            delimiter,
-            prefix,
-        } => {
-            compute_stats(&client, &scan_options, &delimiter, prefix).await?;
-        }
-        Commands::FindLargestKeys {
-            scan_options,
-            top_n,
-        } => {
-            find_largest_keys(&client, &scan_options, top_n).await?;
-        }
-    }
-    Ok(())
-}
+    /// Show how soon the keys matching a pattern expire
+    TtlReport {
+        #[command(flatten)]
+        scan_options: ScanOptions,
 
-#[derive(Debug, clap::Args)]
-struct ScanOptions {
-    /// Pattern to scan
-    #[arg(default_value = "*")]
-    pattern: String,
+        /// Output format
+        ///
+        /// JSON output is an object with the "buckets", the "total" and the "earliest_expiry" and
+        /// "latest_expiry" times, while JSON lines output has a record per bucket followed by a
+        /// summary record. CSV and TSV have a bucket,count header row.
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+        output: OutputFormat,
+    },
 
-    /// Sort the results
-    #[arg(short, long, action)]
-    sorted: bool,
+    /// Find the biggest keys matching a pattern, with totals per type
+    Bigkeys {
+        #[command(flatten)]
+        scan_options: ScanOptions,
 
-    /// Reverse the results
-    #[arg(short, long, action)]
-    reversed: bool,
-}
+        /// How many keys to return
+        #[arg(long, default_value_t = 20)]
+        top: usize,
 
-async fn setup_client(info: &RedisInfo) -> color_eyre::Result<Client> {
-    let config = if info.cluster {
-        Config::from_url_clustered(&info.url)?
-    } else {
-        Config::from_url(&info.url)?
-    };
-    let client = Client::new(config, None, None, None);
-    client.init().await?;
-    Ok(client)
-}
+        /// Show the cluster node owning each key
+        #[arg(long, action)]
+        by_node: bool,
+    },
 
-fn scan_stream<'a>(
-    client: &'a Client,
-    pattern: &'a str,
-) -> color_eyre::Result<Pin<Box<dyn Stream<Item = FredResult<ScanResult>> + 'a>>> {
-    if client.is_clustered() {
-        Ok(Box::pin(client.scan_cluster(pattern, Some(10_000), None)))
-    } else {
-        Ok(Box::pin(client.scan(pattern, Some(10_000), None)))
-    }
-}
+    /// Find the most frequently accessed keys matching a pattern
+    ///
+    /// Access frequencies are only tracked with an LFU maxmemory-policy.
+    Hotkeys {
+        #[command(flatten)]
+        scan_options: ScanOptions,
 
-async fn scan(client: &Client, options: &ScanOptions) -> color_eyre::Result<Vec<String>> {
-    let mut stream = scan_stream(client, &options.pattern)?;
-    let mut result = Vec::new();
-    while let Some(page) = stream.next().await {
-        let mut page = page?;
-        let keys = page.take_results().unwrap_or_default();
-        result.extend(keys.into_iter().flat_map(|key| key.into_string()));
-    }
-    if options.sorted {
-        result.sort();
-    }
-    if options.reversed {
-        result.reverse();
-    }
-    Ok(result)
-}
+        /// How many keys to return
+        #[arg(long, default_value_t = 20)]
+        top: usize,
 
-async fn all_items(
-    client: &Client,
-    scan_options: &ScanOptions,
-    limit: Option<usize>,
-) -> color_eyre::Result<()> {
-    let mut keys = scan(client, scan_options).await?;
-    if let Some(limit) = limit {
-        keys = keys.into_iter().take(limit).collect();
-    }
-    for chunk in keys.chunks(1000) {
-        let pipe = client.pipeline();
-        for key in chunk {
-            let _: () = pipe.get(key).await?;
-        }
-        let result: Vec<String> = pipe.all().await?;
-        for item in result {
-            println!("{item}");
-        }
-    }
-    Ok(())
-}
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+        output: OutputFormat,
+    },
 
-async fn del_pattern(
-    client: &Client,
-    scan_options: &ScanOptions,
-    dry_run: bool,
-) -> color_eyre::Result<()> {
-    let keys = scan(client, scan_options).await?;
-    if dry_run {
-        println!("{} keys to delete", keys.len());
-        return Ok(());
-    }
-    let pb = ProgressBar::new(keys.len() as u64).with_style(ProgressStyle::with_template(
-        "[{elapsed_precise}/{eta_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
-    )?);
-    pb.set_message(format!(
-        "Deleting keys from pattern {}",
-        scan_options.pattern
-    ));
+    /// Show the most idle keys matching a pattern and how much memory idle keys hold
+    ///
+    /// Idle times are not tracked with an LFU maxmemory-policy.
+    IdleReport {
+        #[command(flatten)]
+        scan_options: ScanOptions,
 
-    for chunk in keys.chunks(1000) {
-        let _: () = client.del(keys.to_vec()).await?;
-        tokio::time::sleep(std::time::Duration::from_millis(1000)).await;
-        pb.inc(chunk.len() as u64);
-    }
+        /// How many keys to return
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+    },
 
-    Ok(())
-}
+    /// Write the DUMP payload, TTL and name of all the keys matching a pattern to a file
+    ///
+    /// The file can be replayed with the import command. DUMP payloads are only readable by the
+    /// same or a later Redis version, so the version of the server is recorded in the file.
+    Export {
+        #[command(flatten)]
+        scan_options: ScanOptions,
 
-async fn compute_stats(
-    client: &Client,
-    scan_options: &ScanOptions,
-    delimiter: &str,
-    prefix: Option<String>,
-) -> color_eyre::Result<()> {
-    let keys = scan(client, scan_options).await?;
-    let mut counter = Counter::<String>::new();
-    let prefix = &prefix.unwrap_or_default();
-    let mut other = 0;
-    for key in keys {
-        let Some(key) = key.strip_prefix(prefix) else {
-            other += 1;
-            continue;
-        };
-        if let Some((group, _)) = key.split_once(delimiter) {
-            counter[&group.to_owned()] += 1;
-        } else {
-            counter[&"other".to_owned()] += 1;
-        }
-    }
-    let mut b = tabled::builder::Builder::with_capacity(counter.len() + 1, 2);
-    b.push_record(["prefix", "count"]);
-    for (group, count) in counter.most_common() {
-        b.push_record([&format!("{prefix}{group}"), &count.to_string()]);
-    }
-    let mut table = b.build();
-    table.with(tabled::settings::Style::psql());
-    println!("{table}");
-    if other > 0 {
-        println!("Keys not matching prefix \"{prefix}\": {other}");
-    }
-    Ok(())
-}
+        /// Format of the file
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Binary)]
+        format: ExportFormat,
 
-async fn find_largest_keys(
-    client: &Client,
-    scan_options: &ScanOptions,
-    top_n: usize,
-) -> color_eyre::Result<()> {
-    let keys = scan(client, scan_options).await?;
-    let mut counter = Counter::<String>::new();
+        /// File to write, `-` meaning stdout
+        #[arg(long)]
+        out: PathBuf,
+    },
 
-    for chunk in keys.chunks(1000) {
-        let pipe = client.pipeline();
-        for key in chunk {
-            let _: () = pipe.memory_usage(key, None).await?;
-        }
-        let result: Vec<usize> = pipe.all().await?;
-        for (key, size) in chunk.iter().zip(result) {
-            counter[key] = size;
-        }
-        let cutoff = counter
-            .k_most_common_ordered(top_n)
-            .last()
-            .map(|(_, size)| *size)
-            .unwrap_or_default();
-        counter.retain(|_, size| *size >= cutoff);
-    }
+    /// Restore the keys of a file written by the export command
+    Import {
+        /// File to read, `-` meaning stdin
+        #[arg(long)]
+        input: PathBuf,
 
-    let mut b = tabled::builder::Builder::with_capacity(counter.len() + 1, 2);
-    b.push_record(["key", "size"]);
-    for (key, size) in counter.k_most_common_ordered(top_n) {
-        b.push_record([&key, &size.to_string()]);
-    }
-    let mut table = b.build();
-    table.with(tabled::settings::Style::psql());
-    println!("{table}");
-    Ok(())
+        #[command(flatten)]
+        import_options: ImportOptions,
+    },
+
+    /// Move all the keys matching a pattern to another instance, keeping their TTL
+    ///
+    /// Keys are read with DUMP and written with RESTORE, which works between clusters and
+    /// instances with different credentials, unlike MIGRATE.
+    Migrate {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        #[command(flatten)]
+        migrate_options: MigrateOptions,
+    },
+
+    /// Compare the keys matching a pattern in two instances, ignoring the global connection URL
+    Compare {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        #[command(flatten)]
+        compare_options: CompareOptions,
+    },
+
+    /// Show random keys matching a pattern
+    Sample {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        /// Number of keys to return
+        #[arg(short = 'n', long, default_value_t = 10)]
+        size: usize,
+
+        /// How keys are drawn
+        #[arg(long, value_enum, default_value_t = SampleStrategy::Auto)]
+        strategy: SampleStrategy,
+
+        /// Also show the type, TTL and value of each key, tab separated
+        ///
+        /// Values are written as JSON, keys without an expiry get "none" as TTL.
+        #[arg(long, action)]
+        with_values: bool,
+
+        /// Read at most this many elements of each list, set and sorted set with --with-values
+        #[arg(long, default_value_t = 20, requires = "with_values", value_parser = clap::value_parser!(u64).range(1..))]
+        max_elements: u64,
+    },
+
+    /// Print the commands run by the server, as seen by MONITOR
+    Monitor {
+        #[command(flatten)]
+        monitor_options: MonitorOptions,
+    },
+
+    /// Print the messages published on pub/sub channels
+    Subscribe {
+        /// Channels to subscribe to, those containing glob characters being subscribed to as
+        /// patterns
+        ///
+        /// In a cluster, channels without glob characters are also subscribed to as shard
+        /// channels, to receive the messages sent with SPUBLISH.
+        #[arg(required = true)]
+        channels: Vec<String>,
+
+        /// Stop after receiving this many messages
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        count: Option<u64>,
+
+        /// Stop after this long (e.g. "30s")
+        #[arg(long, value_parser = humantime::parse_duration)]
+        duration: Option<Duration>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+        output: OutputFormat,
+    },
+
+    /// Publish messages read from stdin or a file, one per line
+    ///
+    /// In a cluster, receivers are only counted on the node the message was published to.
+    Publish {
+        /// Channel to publish to, JSONL records being able to name their own
+        channel: Option<String>,
+
+        /// File to read the messages from, instead of stdin
+        #[arg(long)]
+        file: Option<PathBuf>,
+
+        /// Read JSONL records with "payload" and optional "channel" fields rather than raw lines
+        #[arg(long, action)]
+        jsonl: bool,
+
+        /// Encoding of the payloads of the JSONL records
+        #[arg(long, value_enum, default_value_t = Encoding::Utf8, requires = "jsonl")]
+        encoding: Encoding,
+
+        /// Publish at most this many messages per second
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        rate: Option<u64>,
+    },
+
+    /// Count the keys matching a pattern per type, with their average number of elements
+    TypeStats {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        /// Add the total and average memory used by the keys of each type, with MEMORY USAGE
+        #[arg(long)]
+        memory: bool,
+
+        /// Fraction of the keys to measure with --memory, extrapolating to all the keys of the type
+        #[arg(long, default_value_t = 1.0, requires = "memory", value_parser = parse_sample_rate)]
+        sample_rate: f64,
+    },
+
+    /// Show the slow log, of every primary in a cluster
+    Slowlog {
+        /// How many entries to fetch from each node, the server default being 10
+        #[arg(short = 'n', long, value_parser = clap::value_parser!(i64).range(1..))]
+        count: Option<i64>,
+
+        /// Only show the commands that took at least this long (e.g. "10ms")
+        #[arg(long, value_parser = humantime::parse_duration)]
+        min_duration: Option<Duration>,
+
+        /// Clear the slow log after printing it
+        #[arg(long, action)]
+        reset: bool,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+        output: OutputFormat,
+    },
+
+    /// Print the keyspace notifications of the keys matching a pattern
+    ///
+    /// Runs until interrupted, or for --duration.
+    WatchEvents {
+        /// Glob-style pattern of the keys to watch
+        #[arg(short, long, default_value = "*")]
+        pattern: String,
+
+        /// Only print these events (e.g. "expired,del,set"), rather than all of them
+        #[arg(long, value_delimiter = ',')]
+        events: Vec<String>,
+
+        /// Enable the notifications needed in notify-keyspace-events, restoring its value on exit
+        #[arg(long, action)]
+        enable_notifications: bool,
+
+        /// Stop after this long (e.g. "30s")
+        #[arg(long, value_parser = humantime::parse_duration)]
+        duration: Option<Duration>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+        output: OutputFormat,
+    },
+
+    /// Summarize the health of the server, with one row per primary in a cluster
+    Info {
+        /// Only show the fields of these INFO sections
+        #[arg(long, value_enum, value_delimiter = ',')]
+        section: Vec<InfoSection>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+        output: OutputFormat,
+    },
+
+    /// Print the fields of a hash matching a pattern with their values, or delete them
+    HscanFields {
+        #[command(flatten)]
+        field_options: FieldScanOptions,
+
+        /// Only print the fields, without their values
+        #[arg(long, action)]
+        no_values: bool,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+        output: OutputFormat,
+
+        /// Delete the matched fields with HDEL, in batches
+        #[arg(long, action)]
+        delete: bool,
+
+        #[command(flatten)]
+        batch: BatchOptions,
+    },
+
+    /// Find the collections matching a pattern with the most elements
+    ///
+    /// Counts the elements of hashes, lists, sets, sorted sets and streams, other keys being
+    /// skipped.
+    CollectionSizes {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        /// How many keys to return
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+
+        /// Ignore the collections with fewer elements than this
+        #[arg(long, default_value_t = 0)]
+        min_size: u64,
+    },
+
+    /// Describe the streams matching a pattern and their consumer groups
+    ///
+    /// Other keys are skipped, whatever --type says. Group lag is only reported by Redis 7.0
+    /// and later.
+    StreamInfo {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        /// Only show the consumer groups, with their lag
+        #[arg(long, action)]
+        lag_only: bool,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+        output: OutputFormat,
+    },
+
+    /// Fill the database with generated keys, for benchmarks and reproductions
+    Seed {
+        #[command(flatten)]
+        seed_options: SeedOptions,
+    },
+
+    /// Check which of the keys listed in a file exist
+    CheckExists {
+        #[command(flatten)]
+        key_file: KeyFileOptions,
+
+        /// Print the keys in this category instead of counting them
+        #[arg(long, value_enum)]
+        print: Option<Existence>,
+    },
+
+    /// Delete the keys listed in a file
+    DelFromFile {
+        #[command(flatten)]
+        key_file: KeyFileOptions,
+
+        #[command(flatten)]
+        batch: BatchOptions,
+
+        /// Use UNLINK rather than DEL, freeing memory in the background on the server
+        ///
+        /// Falls back to DEL on servers older than 4.0
+        #[arg(long, action)]
+        unlink: bool,
+    },
+
+    /// Write string keys from a file of keys, values and optional TTLs
+    SetFromFile {
+        #[command(flatten)]
+        set_options: SetFromFileOptions,
+    },
+
+    /// Show how the hash slots and keys are spread over the primaries of a cluster
+    ClusterSlots {
+        /// Also count the keys matching this pattern on each node
+        #[arg(long)]
+        pattern: Option<String>,
+    },
+
+    /// Measure the round-trip latency to the server with PING, to each primary in a cluster
+    Latency {
+        /// Pause between two pings
+        #[arg(long, default_value = "100ms", value_parser = humantime::parse_duration)]
+        interval: Duration,
+
+        /// How long to measure for
+        #[arg(long, default_value = "10s", value_parser = humantime::parse_duration)]
+        duration: Duration,
+
+        /// Also show the latency events recorded by the server, with LATENCY LATEST and DOCTOR
+        ///
+        /// Events are only recorded when latency-monitor-threshold is set.
+        #[arg(long, action)]
+        server_events: bool,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+        output: OutputFormat,
+    },
+
+    /// Count the keys matching a pattern per type and internal encoding
+    ///
+    /// Shows how many keys use the compact encodings (listpack, intset, embstr...) and how many
+    /// were converted to the regular ones, with their average memory from MEMORY USAGE.
+    EncodingStats {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        /// Fraction of the keys to measure with MEMORY USAGE, or 0 to skip measuring
+        #[arg(long, default_value_t = 0.1, value_parser = parse_encoding_sample_rate)]
+        sample_rate: f64,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+        output: OutputFormat,
+    },
+
+    /// Run a command on each key matching a pattern
+    ///
+    /// The command follows "--", with "{}" replaced by the key, e.g. `foreach "user:*" -- EXPIRE {}
+    /// 3600`. In a cluster, each command is sent to the node owning the argument with the first
+    /// "{}".
+    Foreach {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        #[command(flatten)]
+        batch_options: BatchOptions,
+
+        /// Allow commands that affect the whole server or block it, such as FLUSHALL or KEYS
+        #[arg(long, action)]
+        allow_dangerous: bool,
+
+        /// Command to run, with "{}" standing for the key
+        #[arg(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+
+    /// Count the keys matching a pattern per hash tag, with the slot and node of the top tags
+    ///
+    /// The hash tag is the part between the first "{" and the next "}", or the whole key when
+    /// there is none, as used by Redis Cluster to pick the slot of a key.
+    HashTagStats {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        /// How many tags to return
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+        output: OutputFormat,
+    },
+
+    /// Count the keys of each logical database of a standalone server
+    ///
+    /// The databases are those of the "databases" setting, or else the non-empty ones listed by
+    /// INFO keyspace when CONFIG is not available.
+    DbsizeAll {
+        /// Also show the empty databases
+        #[arg(long, action)]
+        all: bool,
+
+        /// Also count the keys matching this pattern in each non-empty database
+        #[arg(long)]
+        pattern: Option<String>,
+    },
+
+    /// Check the string values of the keys matching a pattern, listing those failing the check
+    ///
+    /// Each failing key is printed with the reason, followed by a summary on stderr. Keys of other
+    /// types are counted and skipped.
+    VerifyValues {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        #[command(flatten)]
+        check: ValueCheck,
+
+        /// Stop once this many failing keys have been found
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        limit_failures: Option<u64>,
+    },
+
+    /// Find the string keys of a pattern holding identical values
+    ///
+    /// Values are compared by their 128 bits XXH3 hash, so only the hashes and key names are kept
+    /// in memory. Groups are ordered by their number of keys times the value size, i.e. the bytes
+    /// their values use.
+    FindDuplicates {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        /// Ignore the values smaller than this many bytes
+        #[arg(long, default_value_t = 1)]
+        min_size: u64,
+
+        /// How many groups of duplicates to return
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+
+        /// Output format
+        ///
+        /// Plain output shows the first keys of each group, while the other formats list all of
+        /// them.
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+        output: OutputFormat,
+    },
+
+    /// List the string keys of a pattern whose value contains a substring or matches a regex
+    ///
+    /// Values are matched as bytes, so binary values and other encodings than UTF-8 are searched
+    /// too.
+    GrepValues {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        #[command(flatten)]
+        search: ValueSearch,
+
+        /// Also print the line of the value around the first match
+        #[arg(long, action)]
+        show_match: bool,
+
+        /// Skip the values larger than this many bytes, which are not fetched
+        #[arg(long)]
+        max_value_size: Option<u64>,
+
+        /// Stop after this many matching keys
+        ///
+        /// While --limit caps the keys scanned, whether their value matches or not, this caps the
+        /// hits.
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        limit_matches: Option<u64>,
+    },
+
+    /// List the client connections, of every primary in a cluster, or kill them
+    Clients {
+        #[command(flatten)]
+        client_options: ClientOptions,
+    },
+
+    /// List the keys matching a pattern that have no TTL, or count them per group
+    NoTtl {
+        #[command(flatten)]
+        scan_options: ScanOptions,
+
+        #[command(flatten)]
+        no_ttl_options: NoTtlOptions,
+    },
+}
+
+#[tokio::main]
+async fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+    let args = Args::parse();
+    if let Commands::Compare {
+        scan_options,
+        compare_options,
+    } = &args.command
+    {
+        return compare(scan_options, compare_options).await;
+    }
+    // Previewing generated keys doesn't need a server
+    if let Commands::Seed { seed_options } = &args.command
+        && seed_options.dry_run
+    {
+        preview_seed(seed_options);
+        return Ok(());
+    }
+    let client = setup_client(&args.redis).await?;
+    match args.command {
+        Commands::ScanKeys {
+            scan_options,
+            count: true,
+            verbose,
+            by_node,
+            watch,
+            ..
+        } => {
+            count_keys(&client, &scan_options, verbose, by_node, watch).await?;
+        }
+        Commands::ScanKeys {
+            scan_options,
+            output,
+            print0,
+            with_ttl,
+            with_type,
+            with_size,
+            bytes,
+            by_node,
+            count: false,
+            out,
+            ..
+        } => {
+            let details = KeyDetails {
+                key_type: with_type,
+                ttl: with_ttl,
+                size: with_size,
+                node: by_node,
+            };
+            scan_keys(
+                &client,
+                &scan_options,
+                output,
+                print0,
+                bytes,
+                details,
+                out.as_deref(),
+            )
+            .await?;
+        }
+        Commands::AllItems {
+            scan_options,
+            item_options,
+        } => {
+            all_items(&client, &scan_options, &item_options).await?;
+        }
+        Commands::DelPattern {
+            scan_options,
+            delete_options,
+        } => {
+            del_pattern(&client, &scan_options, &delete_options).await?;
+        }
+        Commands::ExpirePattern {
+            scan_options,
+            expiry_options,
+        } => {
+            expire_pattern(&client, &scan_options, &expiry_options).await?;
+        }
+        Commands::TouchPattern {
+            scan_options,
+            batch_options,
+        } => {
+            touch_pattern(&client, &scan_options, &batch_options).await?;
+        }
+        Commands::CopyPattern {
+            scan_options,
+            copy_options,
+        } => {
+            copy_pattern(&client, &scan_options, &copy_options).await?;
+        }
+        Commands::RenamePattern {
+            scan_options,
+            rename_options,
+        } => {
+            rename_pattern(&client, &scan_options, &rename_options).await?;
+        }
+        Commands::PersistPattern {
+            scan_options,
+            batch_options,
+        } => {
+            persist_pattern(&client, &scan_options, &batch_options).await?;
+        }
+        Commands::ComputeStats {
+            scan_options,
+            stats_options,
+        } => {
+            compute_stats(&client, &scan_options, &stats_options).await?;
+        }
+        Commands::FindLargestKeys {
+            scan_options,
+            top_n,
+        } => {
+            find_largest_keys(&client, &scan_options, top_n).await?;
+        }
+        Commands::TtlReport {
+            scan_options,
+            output,
+        } => {
+            ttl_report(&client, &scan_options, output).await?;
+        }
+        Commands::Bigkeys {
+            scan_options,
+            top,
+            by_node,
+        } => {
+            bigkeys(&client, &scan_options, top, by_node).await?;
+        }
+        Commands::Hotkeys {
+            scan_options,
+            top,
+            output,
+        } => {
+            hotkeys(&client, &scan_options, top, output).await?;
+        }
+        Commands::IdleReport { scan_options, top } => {
+            idle_report(&client, &scan_options, top).await?;
+        }
+        Commands::Export {
+            scan_options,
+            format,
+            out,
+        } => {
+            export(&client, &scan_options, format, &out).await?;
+        }
+        Commands::Import {
+            input,
+            import_options,
+        } => {
+            import(&client, &input, &import_options).await?;
+        }
+        Commands::Migrate {
+            scan_options,
+            migrate_options,
+        } => {
+            migrate(&client, &scan_options, &migrate_options).await?;
+        }
+        Commands::Compare { .. } => unreachable!("compare connects to its own instances"),
+        Commands::Sample {
+            scan_options,
+            size,
+            strategy,
+            with_values,
+            max_elements,
+        } => {
+            let keys = sample(&client, &scan_options, size, strategy).await?;
+            if with_values {
+                print_sample_values(&client, &keys, max_elements).await?;
+            } else {
+                let mut out = std::io::stdout().lock();
+                write_keys(&mut out, &keys)?;
+            }
+        }
+        Commands::Monitor { monitor_options } => {
+            monitor(&args.redis, &monitor_options).await?;
+        }
+        Commands::Subscribe {
+            channels,
+            count,
+            duration,
+            output,
+        } => {
+            subscribe(&client, &channels, count, duration, output).await?;
+        }
+        Commands::Slowlog {
+            count,
+            min_duration,
+            reset,
+            output,
+        } => {
+            slowlog(&client, count, min_duration, reset, output).await?;
+        }
+        Commands::WatchEvents {
+            pattern,
+            events,
+            enable_notifications,
+            duration,
+            output,
+        } => {
+            watch_events(
+                &client,
+                &pattern,
+                &events,
+                enable_notifications,
+                duration,
+                output,
+            )
+            .await?;
+        }
+        Commands::Info { section, output } => {
+            info(&client, &section, output).await?;
+        }
+        Commands::Publish {
+            channel,
+            file,
+            jsonl,
+            encoding,
+            rate,
+        } => {
+            let encoding = jsonl.then_some(encoding);
+            publish(&client, channel.as_deref(), file.as_deref(), encoding, rate).await?;
+        }
+        Commands::TypeStats {
+            scan_options,
+            memory,
+            sample_rate,
+        } => {
+            let sample_rate = memory.then_some(sample_rate);
+            type_stats(&client, &scan_options, sample_rate).await?;
+        }
+        Commands::HscanFields {
+            field_options,
+            no_values,
+            output,
+            delete,
+            batch,
+        } => {
+            if delete {
+                delete_fields(&client, &field_options, &batch).await?;
+            } else {
+                hscan_fields(&client, &field_options, !no_values, output).await?;
+            }
+        }
+        Commands::CollectionSizes {
+            scan_options,
+            top,
+            min_size,
+        } => {
+            collection_sizes(&client, &scan_options, top, min_size).await?;
+        }
+        Commands::StreamInfo {
+            mut scan_options,
+            lag_only,
+            output,
+        } => {
+            scan_options.types = vec![KeyType::Stream];
+            stream_info(&client, &scan_options, lag_only, output).await?;
+        }
+        Commands::Seed { seed_options } => {
+            seed(&client, &seed_options).await?;
+        }
+        Commands::CheckExists { key_file, print } => {
+            check_exists(&client, &key_file, print).await?;
+        }
+        Commands::DelFromFile {
+            key_file,
+            batch,
+            unlink,
+        } => {
+            del_from_file(&client, &key_file, &batch, unlink).await?;
+        }
+        Commands::SetFromFile { set_options } => {
+            set_from_file(&client, &set_options).await?;
+        }
+        Commands::ClusterSlots { pattern } => {
+            cluster_slots(&client, pattern.as_deref()).await?;
+        }
+        Commands::Latency {
+            interval,
+            duration,
+            server_events,
+            output,
+        } => {
+            latency(&client, interval, duration, server_events, output).await?;
+        }
+        Commands::EncodingStats {
+            scan_options,
+            sample_rate,
+            output,
+        } => {
+            encoding_stats(&client, &scan_options, sample_rate, output).await?;
+        }
+        Commands::Foreach {
+            scan_options,
+            batch_options,
+            allow_dangerous,
+            command,
+        } => {
+            let template = CommandTemplate::new(command, allow_dangerous)?;
+            foreach(&client, &scan_options, &batch_options, &template).await?;
+        }
+        Commands::HashTagStats {
+            scan_options,
+            top,
+            output,
+        } => {
+            hash_tag_stats(&client, &scan_options, top, output).await?;
+        }
+        Commands::DbsizeAll { all, pattern } => {
+            dbsize_all(&client, all, pattern.as_deref()).await?;
+        }
+        Commands::VerifyValues {
+            scan_options,
+            check,
+            limit_failures,
+        } => {
+            verify_values(&client, &scan_options, &check, limit_failures).await?;
+        }
+        Commands::FindDuplicates {
+            scan_options,
+            min_size,
+            top,
+            output,
+        } => {
+            find_duplicates(&client, &scan_options, min_size, top, output).await?;
+        }
+        Commands::GrepValues {
+            scan_options,
+            search,
+            show_match,
+            max_value_size,
+            limit_matches,
+        } => {
+            let options = GrepOptions {
+                show_match,
+                max_value_size,
+                limit_matches,
+            };
+            grep_values(&client, &scan_options, &search.regex()?, &options).await?;
+        }
+        Commands::Clients { client_options } => {
+            clients(&client, &client_options).await?;
+        }
+        Commands::NoTtl {
+            mut scan_options,
+            no_ttl_options,
+        } => {
+            scan_options.no_ttl = true;
+            no_ttl(&client, &scan_options, &no_ttl_options).await?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, clap::Args)]
+struct ScanOptions {
+    /// Pattern to scan
+    #[arg(default_value = "*")]
+    pattern: String,
+
+    /// Sort the results
+    #[arg(short, long, action)]
+    sorted: bool,
+
+    /// Reverse the results
+    #[arg(short, long, action)]
+    reversed: bool,
+
+    /// Stop scanning once this many keys have been found
+    ///
+    /// When combined with --sorted, only the collected keys are sorted
+    #[arg(short, long)]
+    limit: Option<usize>,
+
+    /// Number of keys requested to the server per SCAN call
+    ///
+    /// Lower values reduce the latency impact on busy instances
+    #[arg(long, default_value_t = 10_000, value_parser = clap::value_parser!(u32).range(1..))]
+    scan_count: u32,
+
+    /// Skip keys matching this pattern
+    ///
+    /// Uses the same glob syntax as the scan pattern and can be repeated
+    #[arg(short, long)]
+    exclude: Vec<String>,
+
+    /// Only keep keys matching this regular expression
+    ///
+    /// Applied client side, after the server side pattern
+    #[arg(long)]
+    regex: Option<Regex>,
+
+    /// Only keep keys holding a value of this type
+    ///
+    /// Can be repeated to accept several types. A single type is filtered server side with
+    /// SCAN TYPE on Redis 6 and later, otherwise TYPE is checked for each page of keys.
+    #[arg(long = "type", value_enum)]
+    types: Vec<KeyType>,
+
+    /// Only keep keys expiring in less than this duration (e.g. "12h")
+    #[arg(long, value_parser = humantime::parse_duration)]
+    ttl_lt: Option<Duration>,
+
+    /// Only keep keys expiring in more than this duration (e.g. "90d")
+    ///
+    /// Keys without a TTL never expire, so they are kept as well
+    #[arg(long, value_parser = humantime::parse_duration)]
+    ttl_gt: Option<Duration>,
+
+    /// Only keep keys without a TTL
+    #[arg(long, alias = "no-ttl-only", action, conflicts_with_all = ["ttl_lt", "ttl_gt"])]
+    no_ttl: bool,
+
+    /// Only keep keys with a TTL
+    #[arg(long, alias = "expiring-only", action, conflicts_with = "no_ttl")]
+    expiring: bool,
+
+    /// Only keep keys idle for more than this duration (e.g. "30d")
+    ///
+    /// Not available when the server uses an LFU eviction policy
+    #[arg(long, value_parser = humantime::parse_duration)]
+    idle_gt: Option<Duration>,
+
+    /// Only keep keys idle for less than this duration (e.g. "1h")
+    ///
+    /// Not available when the server uses an LFU eviction policy
+    #[arg(long, value_parser = humantime::parse_duration)]
+    idle_lt: Option<Duration>,
+}
+
+impl ScanOptions {
+    /// The filters narrowing the keys of the pattern, saved in checkpoints so that a deletion is
+    /// only resumed for the same keys
+    fn filters(&self) -> serde_json::Value {
+        let millis =
+            |duration: Option<Duration>| duration.map(|duration| duration.as_millis() as u64);
+        let types: Vec<&str> = self.types.iter().map(KeyType::as_str).collect();
+        serde_json::json!({
+            "exclude": self.exclude,
+            "regex": self.regex.as_ref().map(Regex::as_str),
+            "types": types,
+            "ttl_lt_ms": millis(self.ttl_lt),
+            "ttl_gt_ms": millis(self.ttl_gt),
+            "no_ttl": self.no_ttl,
+            "expiring": self.expiring,
+            "idle_gt_ms": millis(self.idle_gt),
+            "idle_lt_ms": millis(self.idle_lt),
+        })
+    }
+
+    fn filters_ttl(&self) -> bool {
+        self.ttl_lt.is_some() || self.ttl_gt.is_some() || self.no_ttl || self.expiring
+    }
+
+    fn filters_idle(&self) -> bool {
+        self.idle_gt.is_some() || self.idle_lt.is_some()
+    }
+
+    fn idle_matches(&self, idle: Duration) -> bool {
+        self.idle_gt.is_none_or(|min| idle > min) && self.idle_lt.is_none_or(|max| idle < max)
+    }
+
+    /// Whether a PTTL reply passes the TTL filters
+    fn ttl_matches(&self, pttl: i64) -> bool {
+        let ttl = match pttl {
+            -2 => return false,
+            -1 => None,
+            millis => Some(Duration::from_millis(millis as u64)),
+        };
+        if self.no_ttl {
+            return ttl.is_none();
+        }
+        if self.expiring && ttl.is_none() {
+            return false;
+        }
+        let below = self
+            .ttl_lt
+            .is_none_or(|max| ttl.is_some_and(|ttl| ttl < max));
+        let above = self
+            .ttl_gt
+            .is_none_or(|min| ttl.is_none_or(|ttl| ttl > min));
+        below && above
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct ItemOptions {
+    /// Skip keys that are not hashes
+    #[arg(long, action)]
+    hash_only: bool,
+
+    /// Fetch at most this many elements from each list, set and sorted set
+    ///
+    /// Large sets are sampled with SRANDMEMBER rather than fully read
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_elements: Option<u64>,
+
+    /// Output format
+    ///
+    /// JSON formats produce {"key": ..., "value": ...} records, CSV and TSV a key,value header row.
+    /// Keys deleted during the scan get an empty value.
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+    output: OutputFormat,
+
+    /// Prefix each value with its key and a tab in plain output
+    ///
+    /// Tabs, newlines and backslashes in values are escaped
+    #[arg(long, action)]
+    show_keys: bool,
+
+    /// Include the remaining time to live of each key, in milliseconds
+    ///
+    /// Keys without an expiry get "none" in plain output and null in JSON.
+    /// Keys that expire during the scan are left out.
+    #[arg(long, action)]
+    with_ttl: bool,
+
+    /// How values are written
+    ///
+    /// JSON output falls back to {"base64": ...} objects for values that aren't valid UTF-8.
+    #[arg(long, value_enum, default_value_t = Encoding::Utf8)]
+    encoding: Encoding,
+
+    /// Number of keys read per round trip
+    ///
+    /// With --type string alone, each batch is read with a single MGET
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+
+    /// Only read this hash field, can be repeated
+    ///
+    /// Keys that aren't hashes are skipped, missing fields are written as nil
+    #[arg(short, long = "field", value_name = "FIELD")]
+    fields: Vec<String>,
+
+    /// Write the size of each value instead of the value itself
+    ///
+    /// Sizes are in bytes for strings and in elements for other types. A summary of the sizes is
+    /// written to stderr at the end.
+    #[arg(long, action, conflicts_with_all = ["fields", "max_elements"])]
+    sizes: bool,
+
+    /// Parse string values as JSON documents
+    ///
+    /// Plain output pretty-prints them, JSON output embeds them as objects rather than strings.
+    /// Values that aren't valid JSON are skipped.
+    #[arg(long, action, conflicts_with_all = ["fields", "sizes"])]
+    json_values: bool,
+
+    /// Extract this JSONPath expression from each string value, implies --json-values
+    ///
+    /// Plain output writes the key followed by the extracted value, null when nothing matches
+    #[arg(long, conflicts_with_all = ["fields", "sizes"])]
+    jsonpath: Option<JsonPath>,
+
+    /// Path passed to JSON.GET when reading RedisJSON documents
+    ///
+    /// Projects part of each document server side, the matches being returned as an array
+    #[arg(long, default_value = "$")]
+    path: String,
+
+    /// Write the items to this file instead of stdout, `-` meaning stdout
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// Split the output in files of at most this many items
+    ///
+    /// Files are named after --out followed by the shard number and the format extension, e.g.
+    /// dump.00000.jsonl. A JSON manifest of the shards is printed once they are all written.
+    #[arg(long, requires = "out", value_parser = clap::value_parser!(u64).range(1..))]
+    shard_size: Option<u64>,
+}
+
+impl ItemOptions {
+    /// Options reading whole values along with their TTL, for commands showing a few keys
+    fn preview(max_elements: u64) -> Self {
+        Self {
+            hash_only: false,
+            max_elements: Some(max_elements),
+            output: OutputFormat::Plain,
+            show_keys: false,
+            with_ttl: true,
+            encoding: Encoding::Utf8,
+            batch_size: 1000,
+            fields: Vec::new(),
+            sizes: false,
+            json_values: false,
+            jsonpath: None,
+            path: "$".to_owned(),
+            out: None,
+            shard_size: None,
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct DeleteOptions {
+    #[command(flatten)]
+    batch: BatchOptions,
+
+    /// Use UNLINK rather than DEL, freeing memory in the background on the server
+    ///
+    /// Falls back to DEL on servers older than 4.0
+    #[arg(long, action)]
+    unlink: bool,
+
+    /// Abort before deleting anything if the pattern matches more than this many keys
+    #[arg(long)]
+    max_keys: Option<usize>,
+
+    /// Number of matched keys listed by the dry run
+    #[arg(long, default_value_t = 20)]
+    sample: usize,
+
+    /// Estimate the memory used by the matched keys in the dry run, from MEMORY USAGE on a sample
+    #[arg(long, action)]
+    estimate_size: bool,
+
+    /// Run the idle filters even under an LFU eviction policy, where they match no key
+    #[arg(long, action)]
+    force: bool,
+
+    /// Delete each page of keys as soon as it is scanned instead of scanning everything first
+    ///
+    /// Memory stays bounded on huge patterns, but the number of keys is only known at the end
+    #[arg(long, action, conflicts_with_all = ["max_keys", "sorted", "reversed"])]
+    streaming: bool,
+
+    /// Save the scan progress to this file after each page of a streaming deletion
+    #[arg(long, requires = "streaming")]
+    checkpoint: Option<PathBuf>,
+
+    /// Resume a streaming deletion from a checkpoint file, which keeps being updated
+    ///
+    /// The pattern and filters (--type, --regex, --exclude, TTL and idle time) must be the same as
+    /// when the checkpoint was saved.
+    #[arg(long, requires = "streaming")]
+    resume: Option<PathBuf>,
+
+    /// Append the deleted keys to this file as JSON lines, with the batch number and time
+    #[arg(long)]
+    log_deleted: Option<PathBuf>,
+
+    /// In a cluster, delete on up to this many nodes at once
+    ///
+    /// Each node gets its own queue of batches, paced independently
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    parallelism: u64,
+}
+
+#[derive(Debug, clap::Args)]
+#[command(group(clap::ArgGroup::new("condition").args(["nx", "xx", "gt", "lt"])))]
+struct ExpiryOptions {
+    #[command(flatten)]
+    batch: BatchOptions,
+
+    /// TTL to set, in whole seconds (e.g. "1h", "7days")
+    #[arg(long, value_parser = humantime::parse_duration)]
+    ttl: Duration,
+
+    /// Only set a TTL on the keys that don't have one
+    #[arg(long, action)]
+    only_missing: bool,
+
+    /// Only set the TTL when the key has none, on Redis 7.0 and later
+    #[arg(long, action)]
+    nx: bool,
+
+    /// Only set the TTL when the key already has one, on Redis 7.0 and later
+    #[arg(long, action)]
+    xx: bool,
+
+    /// Only set the TTL when it is greater than the current one, on Redis 7.0 and later
+    #[arg(long, action)]
+    gt: bool,
+
+    /// Only set the TTL when it is less than the current one, on Redis 7.0 and later
+    #[arg(long, action)]
+    lt: bool,
+}
+
+impl ExpiryOptions {
+    /// Condition passed to EXPIRE
+    fn condition(&self) -> Option<fred::types::ExpireOptions> {
+        use fred::types::ExpireOptions;
+
+        [
+            (self.nx, ExpireOptions::NX),
+            (self.xx, ExpireOptions::XX),
+            (self.gt, ExpireOptions::GT),
+            (self.lt, ExpireOptions::LT),
+        ]
+        .into_iter()
+        .find_map(|(set, condition)| set.then_some(condition))
+    }
+}
+
+/// New names of the matched keys, replacing either a prefix or the match of --regex
+#[derive(Debug, clap::Args)]
+#[command(group(clap::ArgGroup::new("rewrite").args(["from_prefix", "replacement"]).required(true)))]
+struct KeyRewrite {
+    /// Prefix of the matched keys to replace, keys not starting with it being left alone
+    #[arg(long, requires = "to_prefix")]
+    from_prefix: Option<String>,
+
+    /// Prefix replacing --from-prefix in the new names
+    #[arg(long, requires = "from_prefix")]
+    to_prefix: Option<String>,
+
+    /// Replacement of the first match of --regex in the names, "$1" or "${name}" standing for a
+    /// capture group
+    #[arg(long, requires = "regex")]
+    replacement: Option<String>,
+}
+
+impl KeyRewrite {
+    /// New name of a key, or `None` if it is left alone
+    ///
+    /// `regex` is the --regex of the scan options, only used along with --replacement.
+    fn rewrite(&self, key: &Key, regex: Option<&Regex>) -> Option<Key> {
+        let renamed = if let (Some(regex), Some(replacement)) = (regex, &self.replacement) {
+            regex
+                .replace(key.as_bytes(), replacement.as_bytes())
+                .into_owned()
+        } else {
+            let (from, to) = (self.from_prefix.as_deref()?, self.to_prefix.as_deref()?);
+            let rest = key.as_bytes().strip_prefix(from.as_bytes())?;
+            let mut renamed = Vec::with_capacity(to.len() + rest.len());
+            renamed.extend_from_slice(to.as_bytes());
+            renamed.extend_from_slice(rest);
+            renamed
+        };
+        Some(Key::from(renamed.into_boxed_slice()))
+    }
+
+    /// The keys getting a new name, along with it
+    fn mappings<'a>(&self, keys: &'a [Key], regex: Option<&Regex>) -> Vec<(&'a Key, Key)> {
+        keys.iter()
+            .filter_map(|key| Some((key, self.rewrite(key, regex)?)))
+            .collect()
+    }
+}
+
+/// Show the keys about to get a new name, before asking for confirmation
+fn confirm_mappings(pattern: &str, mappings: &[(&Key, Key)]) {
+    eprintln!(
+        "Pattern {pattern} matches {} keys to rewrite, such as:",
+        mappings.len()
+    );
+    print_mappings(mappings);
+}
+
+/// Print the first mappings of source keys to destination keys
+fn print_mappings(mappings: &[(&Key, Key)]) {
+    for (source, destination) in mappings.iter().take(20) {
+        eprintln!(
+            "  {} -> {}",
+            source.as_str_lossy(),
+            destination.as_str_lossy()
+        );
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct CopyOptions {
+    #[command(flatten)]
+    batch: BatchOptions,
+
+    #[command(flatten)]
+    rewrite: KeyRewrite,
+
+    /// Overwrite the destination keys that already exist, instead of skipping them
+    #[arg(long, action)]
+    replace: bool,
+
+    /// Logical database to copy the keys to, instead of the current one
+    #[arg(long)]
+    dest_db: Option<u8>,
+}
+
+#[derive(Debug, clap::Args)]
+struct ImportOptions {
+    #[command(flatten)]
+    batch: BatchOptions,
+
+    /// Overwrite the keys that already exist, instead of skipping them
+    #[arg(long, action)]
+    replace: bool,
+
+    /// Prefix added to the name of every imported key
+    #[arg(long)]
+    key_prefix: Option<String>,
+
+    /// Keep the original expiry times, instead of the TTLs the keys had when they were exported
+    ///
+    /// Keys that expired since the export are skipped.
+    #[arg(long, action)]
+    absttl: bool,
+
+    /// Restore payloads exported from a later Redis version, which usually fails
+    #[arg(long, action)]
+    force: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct MigrateOptions {
+    #[command(flatten)]
+    batch: BatchOptions,
+
+    /// Connection URL to the destination instance
+    #[arg(long)]
+    dest_url: String,
+
+    /// The destination is a cluster
+    #[arg(long, action)]
+    dest_cluster: bool,
+
+    /// Leave the keys in the source instance
+    #[arg(long, action)]
+    copy: bool,
+
+    /// Overwrite the destination keys that already exist, instead of skipping them
+    #[arg(long, action)]
+    replace: bool,
+
+    /// Number of migrated keys whose DUMP payloads are compared once done, 0 to disable
+    ///
+    /// Payloads only match when both instances run the same Redis version.
+    #[arg(long, default_value_t = 100)]
+    verify: usize,
+}
+
+#[derive(Debug, clap::Args)]
+struct CompareOptions {
+    /// Connection URL to the source instance
+    #[arg(long)]
+    source_url: String,
+
+    /// The source is a cluster
+    #[arg(long, action)]
+    source_cluster: bool,
+
+    /// Connection URL to the target instance
+    #[arg(long)]
+    target_url: String,
+
+    /// The target is a cluster
+    #[arg(long, action)]
+    target_cluster: bool,
+
+    /// Also compare the values of the keys present in both instances
+    ///
+    /// Strings are compared with GET and the other types with DUMP, whose payloads only match
+    /// when both instances run the same Redis version.
+    #[arg(long, action)]
+    values: bool,
+
+    /// Directory to write the keys of each category to, one file per category
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+struct MonitorOptions {
+    /// Only show the commands on keys matching this pattern, using the glob syntax of SCAN
+    ///
+    /// The first argument is the key of most commands, every argument being checked for
+    /// DEL, UNLINK, EXISTS, TOUCH, MGET and WATCH. Commands without arguments only match "*".
+    #[arg(default_value = "*")]
+    pattern: String,
+
+    /// Only show these commands, comma separated (e.g. "GET,SET")
+    #[arg(long = "command", value_delimiter = ',')]
+    commands: Vec<String>,
+
+    /// Stop after this long (e.g. "30s")
+    #[arg(long, value_parser = humantime::parse_duration)]
+    duration: Option<Duration>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+    output: OutputFormat,
+
+    /// Run against a cluster anyway, MONITOR only showing the commands of the node behind --url
+    #[arg(long, action)]
+    yes_i_know: bool,
+}
+
+impl MonitorOptions {
+    fn matches(&self, command: &fred::monitor::MonitorCommand) -> bool {
+        const MULTI_KEY: [&str; 6] = ["DEL", "UNLINK", "EXISTS", "TOUCH", "MGET", "WATCH"];
+
+        if !self.commands.is_empty()
+            && !self
+                .commands
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(&command.command))
+        {
+            return false;
+        }
+        if self.pattern == "*" {
+            return true;
+        }
+        let key_matches = |arg: &Value| {
+            arg.as_bytes()
+                .is_some_and(|key| glob_match(self.pattern.as_bytes(), key))
+        };
+        if MULTI_KEY
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(&command.command))
+        {
+            command.args.iter().any(key_matches)
+        } else {
+            command.args.first().is_some_and(key_matches)
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct FieldScanOptions {
+    /// Hash to scan
+    key: String,
+
+    /// Pattern of the fields to scan
+    #[arg(default_value = "*")]
+    pattern: String,
+
+    /// Sort the fields
+    #[arg(short, long, action)]
+    sorted: bool,
+
+    /// Reverse the fields
+    #[arg(short, long, action)]
+    reversed: bool,
+
+    /// Stop scanning once this many fields have been found
+    ///
+    /// When combined with --sorted, only the collected fields are sorted
+    #[arg(short, long)]
+    limit: Option<usize>,
+
+    /// Number of fields requested to the server per HSCAN call
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..))]
+    count: u32,
+}
+
+#[derive(Debug, clap::Args)]
+struct SeedOptions {
+    /// Number of keys to write
+    #[arg(long)]
+    count: u64,
+
+    /// Template of the key names
+    ///
+    /// "{seq}" is replaced by the number of the key, "{uuid}" by a random UUID and "{rand:N}" by
+    /// N random alphanumeric characters. Other braces are kept, for hash tags.
+    #[arg(long, default_value = "seed:{seq}")]
+    key_template: KeyTemplate,
+
+    /// Type of the values
+    #[arg(long = "type", value_enum, default_value_t = SeedType::String)]
+    value_type: SeedType,
+
+    /// Size of each string, hash field value or set member, in bytes
+    #[arg(long, default_value_t = 64)]
+    value_size: usize,
+
+    /// Number of fields of each hash, or members of each set
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    fields: u64,
+
+    /// Expire the keys after this long (e.g. "1h")
+    #[arg(long, value_parser = humantime::parse_duration)]
+    ttl: Option<Duration>,
+
+    /// Number of keys sent in a single batch
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+
+    /// Write the keys, the default being a dry run showing a few of them
+    #[arg(long = "no-dry-run", default_value_t = true, action = ArgAction::SetFalse)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum SeedType {
+    String,
+    Hash,
+    Set,
+}
+
+impl SeedType {
+    fn name(&self) -> &'static str {
+        match self {
+            SeedType::String => "string",
+            SeedType::Hash => "hash",
+            SeedType::Set => "set",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum TemplatePart {
+    Literal(String),
+    Seq,
+    Uuid,
+    Rand(usize),
+}
+
+/// A key template parsed once, so that invalid placeholders are caught by clap
+#[derive(Debug, Clone)]
+struct KeyTemplate(Vec<TemplatePart>);
+
+impl std::str::FromStr for KeyTemplate {
+    type Err = color_eyre::Report;
+
+    fn from_str(template: &str) -> color_eyre::Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            literal.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let Some(end) = rest.find('}') else {
+                break;
+            };
+            let part = match &rest[1..end] {
+                "seq" => TemplatePart::Seq,
+                "uuid" => TemplatePart::Uuid,
+                placeholder => match placeholder.strip_prefix("rand:") {
+                    Some(len) => TemplatePart::Rand(
+                        len.parse()
+                            .map_err(|_| eyre!("invalid length in {{{placeholder}}}"))?,
+                    ),
+                    None => {
+                        literal.push_str(&rest[..=end]);
+                        rest = &rest[end + 1..];
+                        continue;
+                    }
+                },
+            };
+            if !literal.is_empty() {
+                parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+            }
+            parts.push(part);
+            rest = &rest[end + 1..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+        Ok(Self(parts))
+    }
+}
+
+impl KeyTemplate {
+    /// Whether every key gets a different name
+    fn is_unique(&self) -> bool {
+        self.0
+            .iter()
+            .any(|part| matches!(part, TemplatePart::Seq | TemplatePart::Uuid))
+    }
+
+    fn render(&self, seq: u64, random: &mut SeedRandom) -> String {
+        let mut key = String::new();
+        for part in &self.0 {
+            match part {
+                TemplatePart::Literal(literal) => key.push_str(literal),
+                TemplatePart::Seq => key.push_str(&seq.to_string()),
+                TemplatePart::Uuid => key.push_str(&random.uuid()),
+                TemplatePart::Rand(len) => key.push_str(&random.alphanumeric(*len)),
+            }
+        }
+        key
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct KeyFileOptions {
+    /// File listing the keys, one per line, stdin being read when it is missing or "-"
+    file: Option<PathBuf>,
+
+    /// Keys are terminated by NUL bytes rather than newlines, as written by scan-keys --print0
+    #[arg(short = '0', long, action)]
+    null: bool,
+}
+
+impl KeyFileOptions {
+    fn terminator(&self) -> u8 {
+        if self.null { b'\0' } else { b'\n' }
+    }
+
+    /// Read the listed keys, skipping empty lines
+    fn read_keys(&self) -> color_eyre::Result<Vec<Key>> {
+        let terminator = self.terminator();
+        let mut keys = Vec::new();
+        for key in open_input(self.file.as_deref())?.split(terminator) {
+            let mut key = key?;
+            if !self.null && key.last() == Some(&b'\r') {
+                key.pop();
+            }
+            if !key.is_empty() {
+                keys.push(Key::from(key.into_boxed_slice()));
+            }
+        }
+        Ok(keys)
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct SetFromFileOptions {
+    /// File to read, stdin being read when it is missing or "-"
+    file: Option<PathBuf>,
+
+    /// Format of the file
+    ///
+    /// TSV and CSV rows hold a key, a value and an optional TTL, a header row starting with
+    /// "key" being skipped. JSONL records have "key", "value" and optional "ttl" fields. TTLs
+    /// are in milliseconds, or durations like "10m".
+    #[arg(long, value_enum, default_value_t = RecordFormat::Tsv)]
+    format: RecordFormat,
+
+    /// Encoding of the values in the file
+    #[arg(long, value_enum, default_value_t = Encoding::Utf8)]
+    encoding: Encoding,
+
+    /// Only write the keys that don't exist yet, with SET NX
+    #[arg(long, action)]
+    nx: bool,
+
+    /// Prefix added to the name of every key
+    #[arg(long)]
+    key_prefix: Option<String>,
+
+    #[command(flatten)]
+    batch: BatchOptions,
+}
+
+#[derive(Debug, clap::Args)]
+struct ClientOptions {
+    /// Only keep the clients idle for more than this duration (e.g. "300s")
+    #[arg(long, value_parser = humantime::parse_duration)]
+    idle_gt: Option<Duration>,
+
+    /// Only keep the clients whose address starts with this (e.g. "10.0.")
+    #[arg(long)]
+    addr: Option<String>,
+
+    /// Only keep the clients whose current or last command is this one (e.g. "subscribe")
+    #[arg(long)]
+    cmd: Option<String>,
+
+    /// Only keep the clients with this name
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+    output: OutputFormat,
+
+    /// Kill the matching clients with CLIENT KILL ID
+    #[arg(long, action)]
+    kill: bool,
+
+    /// Kill the clients, the default being a dry run listing them
+    #[arg(long = "no-dry-run", default_value_t = true, action = ArgAction::SetFalse, requires = "kill")]
+    dry_run: bool,
+
+    /// Kill the clients without asking for confirmation
+    #[arg(short, long, action, requires = "kill")]
+    yes: bool,
+}
+
+impl ClientOptions {
+    fn matches(&self, client: &HashMap<&str, &str>) -> bool {
+        let field = |name: &str| client.get(name).copied().unwrap_or_default();
+        let idle = field("idle").parse().map(Duration::from_secs).ok();
+        // Subcommands are reported as "client|list" since Redis 7.0
+        let cmd = field("cmd");
+        self.idle_gt
+            .is_none_or(|min| idle.is_some_and(|idle| idle > min))
+            && self
+                .addr
+                .as_ref()
+                .is_none_or(|addr| field("addr").starts_with(addr.as_str()))
+            && self.cmd.as_ref().is_none_or(|expected| {
+                let command = cmd.split('|').next().unwrap_or_default();
+                cmd.eq_ignore_ascii_case(expected) || command.eq_ignore_ascii_case(expected)
+            })
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|name| field("name") == name.as_str())
+    }
+}
+
+#[derive(Debug, clap::Args)]
+#[group(required = true, multiple = false)]
+struct ValueCheck {
+    /// Regular expression the values must match, unanchored unless it uses ^ and $
+    ///
+    /// Unlike --regex, which filters the key names, this applies to the values.
+    #[arg(long)]
+    value_regex: Option<Regex>,
+
+    /// JSON schema file the values must be valid JSON documents for
+    #[arg(long)]
+    json_schema: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+#[group(required = true, multiple = false)]
+struct ValueSearch {
+    /// Substring the values must contain
+    #[arg(long)]
+    contains: Option<String>,
+
+    /// Regular expression the values must match
+    ///
+    /// Unlike --regex, which filters the key names, this applies to the values.
+    #[arg(long)]
+    value_regex: Option<Regex>,
+}
+
+impl ValueSearch {
+    /// The search as a regular expression, substrings being escaped
+    fn regex(&self) -> color_eyre::Result<Regex> {
+        match (&self.contains, &self.value_regex) {
+            (Some(substring), _) => Ok(Regex::new(&regex::escape(substring))?),
+            (None, Some(regex)) => Ok(regex.clone()),
+            (None, None) => Err(eyre!("--contains or --value-regex is required")),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct GrepOptions {
+    show_match: bool,
+    max_value_size: Option<u64>,
+    limit_matches: Option<u64>,
+}
+
+#[derive(Debug, clap::Args)]
+struct NoTtlOptions {
+    /// Only print the number of keys without TTL
+    #[arg(long, action, conflicts_with_all = ["by_prefix", "output"])]
+    count: bool,
+
+    /// Add the memory used by each key, with MEMORY USAGE
+    #[arg(long, action)]
+    with_size: bool,
+
+    /// Count the keys per group, as compute-stats does, instead of listing them
+    #[arg(long, action)]
+    by_prefix: bool,
+
+    /// Delimiter used by each group with --by-prefix
+    #[arg(short, long, default_value = ":", requires = "by_prefix", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    delimiter: Vec<String>,
+
+    /// Number of segments making up a group with --by-prefix
+    #[arg(long, default_value_t = 1, requires = "by_prefix", value_parser = clap::value_parser!(u64).range(1..))]
+    depth: u64,
+
+    /// Output format, plain output being a list of keys or a table of groups
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+    output: OutputFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum RecordFormat {
+    /// Tab separated values
+    Tsv,
+    /// Comma separated values
+    Csv,
+    /// One JSON object per line
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Existence {
+    Present,
+    Missing,
+}
+
+#[derive(Debug, clap::Args)]
+struct RenameOptions {
+    #[command(flatten)]
+    batch: BatchOptions,
+
+    #[command(flatten)]
+    rewrite: KeyRewrite,
+
+    /// What to do with the keys whose new name already exists
+    #[arg(long, value_enum, default_value_t = OnConflict::Fail)]
+    on_conflict: OnConflict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OnConflict {
+    /// Leave the key under its current name
+    Skip,
+    /// Replace the existing key
+    Overwrite,
+    /// Abort before renaming anything, or as soon as a new conflict appears
+    Fail,
+}
+
+/// Pacing and safety options of the commands writing to all the matched keys
+#[derive(Debug, clap::Args)]
+struct BatchOptions {
+    /// Apply the changes, the default being a dry run describing them
+    #[arg(long = "no-dry-run", default_value_t=true, action = ArgAction::SetFalse)]
+    dry_run: bool,
+
+    /// Number of keys sent in a single batch
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+
+    /// Pause between two batches (e.g. "200ms", 0 to disable)
+    #[arg(long, default_value = "1s", value_parser = humantime::parse_duration)]
+    sleep: Duration,
+
+    /// Process at most this many keys per second, pausing after each batch as needed
+    #[arg(long, conflicts_with = "sleep", value_parser = clap::value_parser!(u64).range(1..))]
+    rate: Option<u64>,
+
+    /// Apply the changes without asking for confirmation, required when stdin is not a terminal
+    #[arg(short, long, action)]
+    yes: bool,
+}
+
+#[derive(Debug, clap::Args)]
+#[command(group(clap::ArgGroup::new("measured").args(["memory", "value_sizes"]).multiple(true)))]
+struct StatsOptions {
+    /// Delimiter used by each group
+    ///
+    /// For instance, the key "abc:123:456" will belong to group "abc". Can be repeated to split on
+    /// the first occurrence of any of the delimiters.
+    #[arg(short, long, default_value = ":", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    delimiter: Vec<String>,
+
+    /// Common prefix to remove before computing the stats
+    ///
+    /// For instance, with the prefix "abc:", key "abc:123:456" will belong to group "123"
+    #[arg(long)]
+    prefix: Option<String>,
+
+    /// Number of segments making up a group
+    ///
+    /// For instance, with a depth of 2, key "abc:123:456" will belong to group "abc:123". Keys with
+    /// fewer segments belong to a group made of all of them.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    depth: u64,
+
+    /// Group keys by the first capture group of this regular expression, or the one named "g"
+    ///
+    /// Keys that don't match belong to "other".
+    #[arg(long, conflicts_with_all = ["delimiter", "prefix", "depth", "tree"])]
+    group_by_regex: Option<Regex>,
+
+    /// Replace the segments looking like IDs with "*" before grouping
+    ///
+    /// For instance, keys "order:8231:items" and "order:9942:items" both belong to group
+    /// "order:*:items" with a depth of 3.
+    #[arg(long, conflicts_with_all = ["group_by_regex", "collapse"])]
+    collapse_ids: bool,
+
+    /// Kinds of IDs replaced with "*" before grouping
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        conflicts_with = "group_by_regex"
+    )]
+    collapse: Vec<IdKind>,
+
+    /// Only show the most common groups, collapsing the others into a single row
+    #[arg(long)]
+    top: Option<usize>,
+
+    /// Fold the groups with fewer keys into a single row
+    #[arg(long)]
+    min_count: Option<usize>,
+
+    /// Add the share of the keys in each group, and the cumulative share
+    #[arg(long)]
+    percent: bool,
+
+    /// Add the total and average memory used by the keys of each group, with MEMORY USAGE
+    #[arg(long)]
+    memory: bool,
+
+    /// Add the total and average size of the values of each group
+    ///
+    /// Measured with STRLEN for strings and MEMORY USAGE for the other types.
+    #[arg(long)]
+    value_sizes: bool,
+
+    /// Fraction of the keys to measure with --memory and --value-sizes, extrapolating to the whole
+    /// group
+    #[arg(long, default_value_t = 1.0, requires = "measured", value_parser = parse_sample_rate)]
+    sample_rate: f64,
+
+    /// Order of the groups, ties being ordered by name
+    #[arg(long, value_enum, default_value_t = StatsOrder::Count, requires_if("memory", "memory"))]
+    sort_by: StatsOrder,
+
+    /// List the groups with the fewest keys or the least memory first
+    ///
+    /// Names are always sorted alphabetically.
+    #[arg(long)]
+    ascending: bool,
+
+    /// Add the number of keys without TTL, the remaining TTLs and the keys expiring within a day
+    #[arg(long)]
+    ttl: bool,
+
+    /// Add the number of keys of each type
+    #[arg(long = "types")]
+    type_counts: bool,
+
+    /// Output format
+    ///
+    /// Plain output is a table. JSON output is an object with the "groups", the "other_groups"
+    /// collapsed by --top, the groups "below_threshold" of --min-count, the "total", the number of
+    /// keys "not_matching_prefix" and the "sample_size" of --sample, while JSON lines output has a
+    /// record per group followed by a summary record. CSV and TSV have a row per group, followed by
+    /// the "(other N groups)", "(below threshold, N groups)", "(total)" and "(not matching prefix)"
+    /// aggregate rows.
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+    output: OutputFormat,
+
+    /// Show the groups as a tree of nested prefixes, down to --depth
+    ///
+    /// Branches with fewer keys than --min-count are pruned.
+    #[arg(long, conflicts_with_all = ["output", "top", "percent", "memory", "value_sizes", "ttl", "type_counts"])]
+    tree: bool,
+
+    /// Estimate the counts from this many keys drawn with RANDOMKEY instead of a full scan
+    ///
+    /// In a cluster, the keys are drawn from each node proportionally to its number of keys. The
+    /// TTL and type figures are those of the sampled keys.
+    #[arg(long, conflicts_with = "tree", value_parser = clap::value_parser!(u64).range(1..))]
+    sample: Option<u64>,
+
+    /// Compute the stats of each cluster node separately
+    ///
+    /// Plain output has a table per node, while JSON lines, CSV and TSV records get a "node" field
+    /// or column and JSON output is an array with an object per node.
+    #[arg(long, conflicts_with = "tree")]
+    by_node: bool,
+
+    /// Compute the stats again at this interval (e.g. "30s"), with a column showing the change of
+    /// each count since the previous run, until interrupted
+    #[arg(long, conflicts_with_all = ["output", "tree"], value_parser = humantime::parse_duration)]
+    watch: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum StatsOrder {
+    /// Most keys first
+    Count,
+    /// Alphabetical order, to compare runs
+    Name,
+    /// Most memory first, requires --memory
+    Memory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum IdKind {
+    /// Only digits
+    Numeric,
+    /// Hyphenated UUIDs
+    Uuid,
+    /// Hexadecimal strings longer than 16 characters
+    Hex,
+}
+
+impl IdKind {
+    fn matches(self, segment: &str) -> bool {
+        let is_hex = |s: &str| s.bytes().all(|b| b.is_ascii_hexdigit());
+        match self {
+            IdKind::Numeric => !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()),
+            IdKind::Uuid => {
+                let parts: Vec<&str> = segment.split('-').collect();
+                parts.iter().map(|part| part.len()).eq([8, 4, 4, 4, 12])
+                    && parts.into_iter().all(is_hex)
+            }
+            IdKind::Hex => segment.len() > 16 && is_hex(segment),
+        }
+    }
+}
+
+impl StatsOptions {
+    /// Group of a key, `None` for keys that don't start with the prefix
+    fn group(&self, key: &Key) -> Option<String> {
+        let Some(re) = &self.group_by_regex else {
+            let prefix = self.prefix.as_deref().unwrap_or_default();
+            let collapse = if self.collapse_ids {
+                &[IdKind::Numeric, IdKind::Uuid, IdKind::Hex]
+            } else {
+                self.collapse.as_slice()
+            };
+            return key_group(key, prefix, &self.delimiter, self.depth as usize, collapse);
+        };
+        let group = re.captures(key.as_bytes()).and_then(|captures| {
+            captures
+                .name("g")
+                .or_else(|| captures.get(1))
+                .or_else(|| captures.get(0))
+        });
+        Some(match group {
+            Some(group) => String::from_utf8_lossy(group.as_bytes()).into_owned(),
+            None => "other".to_owned(),
+        })
+    }
+}
+
+fn parse_encoding_sample_rate(value: &str) -> color_eyre::Result<f64> {
+    match value.parse()? {
+        0.0 => Ok(0.0),
+        _ => parse_sample_rate(value),
+    }
+}
+
+fn parse_sample_rate(value: &str) -> color_eyre::Result<f64> {
+    let rate: f64 = value.parse()?;
+    if rate > 0.0 && rate <= 1.0 {
+        Ok(rate)
+    } else {
+        Err(eyre!("the sample rate must be in (0, 1]"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Encoding {
+    /// UTF-8, invalid sequences being replaced
+    Utf8,
+    /// Standard base64
+    Base64,
+    /// Lowercase hexadecimal
+    Hex,
+    /// The bytes as stored, plain output only
+    ///
+    /// String values are written without a trailing newline, which is meant for dumping a single
+    /// key.
+    Raw,
+}
+
+impl Encoding {
+    /// Encode bytes as text, raw bytes being read as UTF-8 with invalid sequences replaced
+    fn text<'a>(&self, bytes: &'a [u8]) -> Cow<'a, str> {
+        match self {
+            Encoding::Utf8 | Encoding::Raw => String::from_utf8_lossy(bytes),
+            Encoding::Base64 => BASE64.encode(bytes).into(),
+            Encoding::Hex => bytes.iter().map(|byte| format!("{byte:02x}")).collect(),
+        }
+    }
+
+    /// Encode bytes for plain output, escaping them unless they are raw
+    fn plain<'a>(&self, bytes: &'a [u8]) -> Cow<'a, [u8]> {
+        match self {
+            Encoding::Raw => bytes.into(),
+            Encoding::Utf8 => escape_plain(&self.text(bytes)).into_bytes().into(),
+            _ => self.text(bytes).into_owned().into_bytes().into(),
+        }
+    }
+
+    /// Decode text encoded this way
+    fn decode(&self, text: &str) -> color_eyre::Result<Vec<u8>> {
+        match self {
+            Encoding::Utf8 | Encoding::Raw => Ok(text.as_bytes().to_vec()),
+            Encoding::Base64 => Ok(BASE64.decode(text)?),
+            Encoding::Hex => {
+                if !text.len().is_multiple_of(2) || !text.is_ascii() {
+                    return Err(eyre!("{text:?} is not valid hexadecimal"));
+                }
+                (0..text.len())
+                    .step_by(2)
+                    .map(|index| Ok(u8::from_str_radix(&text[index..index + 2], 16)?))
+                    .collect()
+            }
+        }
+    }
+
+    fn json(&self, bytes: &[u8]) -> serde_json::Value {
+        match (self, std::str::from_utf8(bytes)) {
+            (Encoding::Utf8 | Encoding::Raw, Ok(text)) => text.into(),
+            (Encoding::Utf8 | Encoding::Raw, Err(_)) => {
+                serde_json::json!({"base64": BASE64.encode(bytes)})
+            }
+            _ => self.text(bytes).into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum KeyType {
+    String,
+    List,
+    Set,
+    Zset,
+    Hash,
+    Stream,
+    /// RedisJSON document
+    Json,
+}
+
+impl KeyType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            KeyType::String => "string",
+            KeyType::List => "list",
+            KeyType::Set => "set",
+            KeyType::Zset => "zset",
+            KeyType::Hash => "hash",
+            KeyType::Stream => "stream",
+            KeyType::Json => "ReJSON-RL",
+        }
+    }
+
+    /// Parse a TYPE reply, `None` for types not handled here such as "none" or module types
+    fn from_reply(reply: &str) -> Option<Self> {
+        match reply {
+            "string" => Some(KeyType::String),
+            "list" => Some(KeyType::List),
+            "set" => Some(KeyType::Set),
+            "zset" => Some(KeyType::Zset),
+            "hash" => Some(KeyType::Hash),
+            "stream" => Some(KeyType::Stream),
+            "ReJSON-RL" => Some(KeyType::Json),
+            _ => None,
+        }
+    }
+
+    /// Queue the command counting the elements of a key of this type, or the bytes of a string
+    ///
+    /// Returns `false` for RedisJSON keys, which have no such command.
+    async fn queue_len(self, pipe: &Pipeline<Client>, key: &Key) -> FredResult<bool> {
+        match self {
+            KeyType::String => pipe.strlen::<(), _>(key).await?,
+            KeyType::List => pipe.llen::<(), _>(key).await?,
+            KeyType::Set => pipe.scard::<(), _>(key).await?,
+            KeyType::Zset => pipe.zcard::<(), _>(key).await?,
+            KeyType::Hash => pipe.hlen::<(), _>(key).await?,
+            KeyType::Stream => pipe.xlen::<(), _>(key).await?,
+            KeyType::Json => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// SCAN TYPE filter, `None` for module types that fred doesn't know about
+    fn scan_type(&self) -> Option<ScanType> {
+        match self {
+            KeyType::String => Some(ScanType::String),
+            KeyType::List => Some(ScanType::List),
+            KeyType::Set => Some(ScanType::Set),
+            KeyType::Zset => Some(ScanType::ZSet),
+            KeyType::Hash => Some(ScanType::Hash),
+            KeyType::Stream => Some(ScanType::Stream),
+            KeyType::Json => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    /// One item per line
+    Plain,
+    /// A single JSON array
+    Json,
+    /// One JSON value per line
+    Jsonl,
+    /// Comma separated values, with a header row
+    Csv,
+    /// Tab separated values, with a header row
+    Tsv,
+}
+
+impl OutputFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Plain => "txt",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Tsv => "tsv",
+        }
+    }
+}
+
+/// Where formatted records end up, CSV and TSV going through a csv writer for quoting
+enum RecordSink<W: Write> {
+    Text(W),
+    Csv(Box<csv::Writer<W>>),
+}
+
+impl<W: Write> RecordSink<W> {
+    fn new(out: W, output: OutputFormat) -> Self {
+        let delimiter = match output {
+            OutputFormat::Csv => b',',
+            OutputFormat::Tsv => b'\t',
+            _ => return RecordSink::Text(out),
+        };
+        RecordSink::Csv(Box::new(
+            csv::WriterBuilder::new()
+                .delimiter(delimiter)
+                .flexible(true)
+                .from_writer(out),
+        ))
+    }
+
+    /// The underlying writer, for formats that don't go through the csv writer
+    fn text(&mut self) -> &mut W {
+        match self {
+            RecordSink::Text(out) => out,
+            RecordSink::Csv(_) => unreachable!("text record written to a CSV sink"),
+        }
+    }
+
+    fn write_csv<I, T>(&mut self, record: I) -> color_eyre::Result<()>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        if let RecordSink::Csv(writer) = self {
+            writer.write_record(record)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            RecordSink::Text(out) => out.flush(),
+            RecordSink::Csv(writer) => writer.flush(),
+        }
+    }
+
+    /// Close the JSON array if needed and return the underlying writer, flushed
+    fn finish(self, output: OutputFormat, written: usize) -> color_eyre::Result<W> {
+        match self {
+            RecordSink::Text(mut out) => {
+                finish_json_records(&mut out, output, written)?;
+                out.flush()?;
+                Ok(out)
+            }
+            RecordSink::Csv(writer) => writer.into_inner().map_err(|e| e.into_error().into()),
+        }
+    }
+}
+
+async fn setup_client(info: &RedisInfo) -> color_eyre::Result<Client> {
+    let config = if info.cluster {
+        Config::from_url_clustered(&info.url)?
+    } else {
+        Config::from_url(&info.url)?
+    };
+    let client = Client::new(config, None, None, None);
+    client.init().await?;
+    Ok(client)
+}
+
+fn scan_stream<'a>(
+    client: &'a Client,
+    pattern: &'a str,
+    count: u32,
+    scan_type: Option<ScanType>,
+) -> color_eyre::Result<Pin<Box<dyn Stream<Item = FredResult<ScanResult>> + 'a>>> {
+    if client.is_clustered() {
+        Ok(Box::pin(client.scan_cluster(
+            pattern,
+            Some(count),
+            scan_type,
+        )))
+    } else {
+        Ok(Box::pin(client.scan(pattern, Some(count), scan_type)))
+    }
+}
+
+/// SCAN driven by explicit cursors rather than fred's scan stream, so that progress can be saved
+///
+/// A cluster gets a cursor per primary node, nodes being scanned one after the other.
+struct CursorScan<'a> {
+    client: &'a Client,
+    pattern: &'a str,
+    count: u32,
+    scan_type: Option<ScanType>,
+    /// Nodes left to scan with their cursor, the server being `None` outside of a cluster
+    cursors: Vec<(Option<Server>, String)>,
+}
+
+impl<'a> CursorScan<'a> {
+    /// Start a scan from the beginning, or from the cursors saved by a previous run
+    fn new(
+        client: &'a Client,
+        pattern: &'a str,
+        count: u32,
+        scan_type: Option<ScanType>,
+        cursors: Option<Vec<(Option<Server>, String)>>,
+    ) -> color_eyre::Result<Self> {
+        let cursors = match cursors {
+            Some(cursors) => cursors,
+            None if client.is_clustered() => client
+                .cached_cluster_state()
+                .ok_or_else(|| eyre!("Cluster state is not known"))?
+                .unique_primary_nodes()
+                .into_iter()
+                .map(|server| (Some(server), "0".to_owned()))
+                .collect(),
+            None => vec![(None, "0".to_owned())],
+        };
+        Ok(Self {
+            client,
+            pattern,
+            count,
+            scan_type,
+            cursors,
+        })
+    }
+
+    /// Fetch the next page, which may be empty, or `None` once every node has been scanned
+    async fn next_page(&mut self) -> color_eyre::Result<Option<Vec<Key>>> {
+        let Some((server, cursor)) = self.cursors.first_mut() else {
+            return Ok(None);
+        };
+        let (pattern, count, scan_type) = (self.pattern, Some(self.count), self.scan_type.clone());
+        let (next, keys): (String, Vec<Key>) = match server {
+            Some(server) => {
+                self.client
+                    .with_cluster_node(server.clone())
+                    .scan_page(cursor.clone(), pattern, count, scan_type)
+                    .await?
+            }
+            None => {
+                self.client
+                    .scan_page(cursor.clone(), pattern, count, scan_type)
+                    .await?
+            }
+        };
+        if next == "0" {
+            self.cursors.remove(0);
+        } else {
+            *cursor = next;
+        }
+        Ok(Some(keys))
+    }
+}
+
+/// Source of the raw SCAN pages of a `KeyScanner`
+enum ScanSource<'a> {
+    Stream(Pin<Box<dyn Stream<Item = FredResult<ScanResult>> + 'a>>),
+    Cursors(CursorScan<'a>),
+}
+
+/// Match a key against a glob pattern, following the semantics of the Redis MATCH option
+fn glob_match(pattern: &[u8], key: &[u8]) -> bool {
+    let (mut p, mut k) = (0, 0);
+    while p < pattern.len() && k < key.len() {
+        match pattern[p] {
+            b'*' => {
+                while pattern.get(p + 1) == Some(&b'*') {
+                    p += 1;
+                }
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+                return (k..key.len()).any(|start| glob_match(&pattern[p + 1..], &key[start..]));
+            }
+            b'?' => k += 1,
+            b'[' => {
+                p += 1;
+                let negate = pattern.get(p) == Some(&b'^');
+                if negate {
+                    p += 1;
+                }
+                let mut matched = false;
+                loop {
+                    match pattern.get(p) {
+                        None => {
+                            p -= 1;
+                            break;
+                        }
+                        Some(b']') => break,
+                        Some(b'\\') if p + 1 < pattern.len() => {
+                            p += 1;
+                            matched |= pattern[p] == key[k];
+                        }
+                        Some(&start) if p + 2 < pattern.len() && pattern[p + 1] == b'-' => {
+                            let end = pattern[p + 2];
+                            let (low, high) = if start <= end {
+                                (start, end)
+                            } else {
+                                (end, start)
+                            };
+                            p += 2;
+                            matched |= (low..=high).contains(&key[k]);
+                        }
+                        Some(&c) => matched |= c == key[k],
+                    }
+                    p += 1;
+                }
+                if matched == negate {
+                    return false;
+                }
+                k += 1;
+            }
+            c => {
+                let c = if c == b'\\' && p + 1 < pattern.len() {
+                    p += 1;
+                    pattern[p]
+                } else {
+                    c
+                };
+                if c != key[k] {
+                    return false;
+                }
+                k += 1;
+            }
+        }
+        p += 1;
+    }
+    if k == key.len() {
+        while pattern.get(p) == Some(&b'*') {
+            p += 1;
+        }
+    }
+    p == pattern.len() && k == key.len()
+}
+
+fn is_excluded(excludes: &[String], key: &Key) -> bool {
+    excludes
+        .iter()
+        .any(|exclude| glob_match(exclude.as_bytes(), key.as_bytes()))
+}
+
+/// The type to pass to SCAN, when the filter can be applied server side
+///
+/// The TYPE option only accepts a single type and requires Redis 6.0 or later
+fn server_scan_type(client: &Client, types: &[KeyType]) -> Option<ScanType> {
+    let supported = client
+        .server_version()
+        .is_some_and(|version| version.major >= 6);
+    match types {
+        [key_type] if supported => key_type.scan_type(),
+        _ => None,
+    }
+}
+
+/// Pipeline TYPE for each key, "none" meaning the key no longer exists
+async fn key_types(client: &Client, keys: &[Key]) -> color_eyre::Result<Vec<String>> {
+    if keys.is_empty() {
+        return Ok(Vec::new());
+    }
+    let pipe = client.pipeline();
+    for key in keys {
+        let _: () = pipe.r#type(key).await?;
+    }
+    Ok(pipe.all().await?)
+}
+
+async fn filter_by_type(
+    client: &Client,
+    types: &[KeyType],
+    keys: Vec<Key>,
+) -> color_eyre::Result<Vec<Key>> {
+    let key_types = key_types(client, &keys).await?;
+    Ok(keys
+        .into_iter()
+        .zip(key_types)
+        .filter(|(_, key_type)| types.iter().any(|t| t.as_str() == key_type))
+        .map(|(key, _)| key)
+        .collect())
+}
+
+async fn filter_by_ttl(
+    client: &Client,
+    options: &ScanOptions,
+    keys: Vec<Key>,
+) -> color_eyre::Result<Vec<Key>> {
+    if keys.is_empty() {
+        return Ok(keys);
+    }
+    let pipe = client.pipeline();
+    for key in &keys {
+        let _: () = pipe.pttl(key).await?;
+    }
+    let ttls: Vec<i64> = pipe.all().await?;
+    Ok(keys
+        .into_iter()
+        .zip(ttls)
+        .filter(|(_, ttl)| options.ttl_matches(*ttl))
+        .map(|(key, _)| key)
+        .collect())
+}
+
+/// OBJECT subcommand taking a key as its argument, routed to the node owning that key
+fn object_command() -> CustomCommand {
+    CustomCommand::new_static("OBJECT", ClusterHash::Offset(1), false)
+}
+
+/// LATENCY subcommand without a key, sent to the node the client is bound to
+fn latency_command() -> CustomCommand {
+    CustomCommand::new_static("LATENCY", ClusterHash::Random, false)
+}
+
+/// TOUCH, which fred has no method for
+fn touch_command() -> CustomCommand {
+    CustomCommand::new_static("TOUCH", ClusterHash::FirstKey, false)
+}
+
+/// JSON.GET, taking a RedisJSON key and a path
+fn json_get_command() -> CustomCommand {
+    CustomCommand::new_static("JSON.GET", ClusterHash::FirstKey, false)
+}
+
+/// Parse a JSON.GET reply, unwrapping the single match of the root path
+fn redisjson_document(reply: &str, path: &str) -> Option<serde_json::Value> {
+    let document: serde_json::Value = serde_json::from_str(reply).ok()?;
+    match document {
+        serde_json::Value::Array(mut matches) if path == "$" && matches.len() == 1 => matches.pop(),
+        document => Some(document),
+    }
+}
+
+/// The eviction policy of the server
+async fn maxmemory_policy(client: &Client) -> color_eyre::Result<Option<String>> {
+    let config: HashMap<String, String> = client.config_get("maxmemory-policy").await?;
+    Ok(config.into_values().next())
+}
+
+/// The eviction policy of the server, if it is an LFU one
+///
+/// OBJECT IDLETIME is not available with these policies, and OBJECT FREQ only works with them
+async fn lfu_policy(client: &Client) -> color_eyre::Result<Option<String>> {
+    Ok(maxmemory_policy(client)
+        .await?
+        .filter(|policy| policy.contains("lfu")))
+}
+
+async fn filter_by_idle(
+    client: &Client,
+    options: &ScanOptions,
+    keys: Vec<Key>,
+) -> color_eyre::Result<Vec<Key>> {
+    if keys.is_empty() {
+        return Ok(keys);
+    }
+    let pipe = client.pipeline();
+    for key in &keys {
+        let _: () = pipe
+            .custom(
+                object_command(),
+                vec![Value::from("IDLETIME"), key.clone().into()],
+            )
+            .await?;
+    }
+    let idle_times: Vec<Option<u64>> = pipe.all().await?;
+    Ok(keys
+        .into_iter()
+        .zip(idle_times)
+        .filter(|(_, idle)| {
+            idle.is_some_and(|idle| options.idle_matches(Duration::from_secs(idle)))
+        })
+        .map(|(key, _)| key)
+        .collect())
+}
+
+/// Incremental scan of the keys matching the scan options
+///
+/// Pages are yielded as they are returned by the server, after the client side filters have been
+/// applied, so memory stays bounded to a single page.
+struct KeyScanner<'a> {
+    client: &'a Client,
+    options: &'a ScanOptions,
+    source: ScanSource<'a>,
+    filter_types: bool,
+    /// Whether OBJECT IDLETIME can be used, checked on the first page
+    idle_available: Option<bool>,
+    remaining: Option<usize>,
+    /// Keys matching the pattern that were dropped by --exclude
+    excluded: usize,
+}
+
+impl<'a> KeyScanner<'a> {
+    fn new(client: &'a Client, options: &'a ScanOptions) -> color_eyre::Result<Self> {
+        let scan_type = server_scan_type(client, &options.types);
+        let stream = scan_stream(
+            client,
+            &options.pattern,
+            options.scan_count,
+            scan_type.clone(),
+        )?;
+        Ok(Self::with_source(
+            client,
+            options,
+            ScanSource::Stream(stream),
+        ))
+    }
+
+    /// Scanner whose progress can be saved with `cursors`, resuming from `cursors` when given
+    fn resumable(
+        client: &'a Client,
+        options: &'a ScanOptions,
+        cursors: Option<Vec<(Option<Server>, String)>>,
+    ) -> color_eyre::Result<Self> {
+        let scan_type = server_scan_type(client, &options.types);
+        let scan = CursorScan::new(
+            client,
+            &options.pattern,
+            options.scan_count,
+            scan_type,
+            cursors,
+        )?;
+        Ok(Self::with_source(
+            client,
+            options,
+            ScanSource::Cursors(scan),
+        ))
+    }
+
+    fn with_source(client: &'a Client, options: &'a ScanOptions, source: ScanSource<'a>) -> Self {
+        let filter_types =
+            !options.types.is_empty() && server_scan_type(client, &options.types).is_none();
+        Self {
+            client,
+            options,
+            source,
+            filter_types,
+            idle_available: None,
+            remaining: options.limit,
+            excluded: 0,
+        }
+    }
+
+    /// Cursors of the nodes left to scan, all the pages returned so far being behind them
+    fn cursors(&self) -> Option<&[(Option<Server>, String)]> {
+        match &self.source {
+            ScanSource::Stream(_) => None,
+            ScanSource::Cursors(scan) => Some(&scan.cursors),
+        }
+    }
+
+    async fn filter_by_idle(&mut self, keys: Vec<Key>) -> color_eyre::Result<Vec<Key>> {
+        let available = match self.idle_available {
+            Some(available) => available,
+            None => {
+                let policy = lfu_policy(self.client).await?;
+                if let Some(policy) = &policy {
+                    eprintln!(
+                        "Warning: maxmemory-policy is {policy}, idle times are not tracked so no key will match the idle filters"
+                    );
+                }
+                *self.idle_available.insert(policy.is_none())
+            }
+        };
+        if !available {
+            return Ok(Vec::new());
+        }
+        filter_by_idle(self.client, self.options, keys).await
+    }
+
+    /// Fetch the next non-empty page of keys, or `None` once the scan is over
+    async fn next_page(&mut self) -> color_eyre::Result<Option<Vec<Key>>> {
+        if self.remaining == Some(0) {
+            return Ok(None);
+        }
+        loop {
+            let (results, page) = match &mut self.source {
+                ScanSource::Stream(stream) => match stream.next().await {
+                    Some(page) => {
+                        let mut page = page?;
+                        (page.take_results().unwrap_or_default(), Some(page))
+                    }
+                    None => return Ok(None),
+                },
+                ScanSource::Cursors(scan) => match scan.next_page().await? {
+                    Some(keys) => (keys, None),
+                    None => return Ok(None),
+                },
+            };
+            let matched = results.len();
+            let mut keys: Vec<Key> = results
+                .into_iter()
+                .filter(|key| !is_excluded(&self.options.exclude, key))
+                .collect();
+            self.excluded += matched - keys.len();
+            keys.retain(|key| {
+                self.options
+                    .regex
+                    .as_ref()
+                    .is_none_or(|re| re.is_match(key.as_bytes()))
+            });
+            if self.filter_types {
+                keys = filter_by_type(self.client, &self.options.types, keys).await?;
+            }
+            if self.options.filters_ttl() {
+                keys = filter_by_ttl(self.client, self.options, keys).await?;
+            }
+            if self.options.filters_idle() {
+                keys = self.filter_by_idle(keys).await?;
+            }
+            if let Some(remaining) = self.remaining.as_mut() {
+                keys.truncate(*remaining);
+                *remaining -= keys.len();
+                if *remaining == 0
+                    && let Some(page) = page
+                {
+                    page.cancel();
+                }
+            }
+            if !keys.is_empty() {
+                return Ok(Some(keys));
+            }
+        }
+    }
+
+    /// Run the scan to completion, sorting the keys if requested
+    async fn collect(&mut self) -> color_eyre::Result<Vec<Key>> {
+        let mut result = Vec::new();
+        while let Some(keys) = self.next_page().await? {
+            result.extend(keys);
+        }
+        if self.options.sorted {
+            result.sort();
+        }
+        if self.options.reversed {
+            result.reverse();
+        }
+        Ok(result)
+    }
+}
+
+async fn scan(client: &Client, options: &ScanOptions) -> color_eyre::Result<Vec<Key>> {
+    KeyScanner::new(client, options)?.collect().await
+}
+
+/// Remaining time to live of a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ttl {
+    Persistent,
+    Remaining(i64),
+}
+
+impl Ttl {
+    /// Interpret a TTL or PTTL reply, returning `None` when the key no longer exists
+    fn from_reply(ttl: i64) -> Option<Self> {
+        match ttl {
+            -2 => None,
+            -1 => Some(Ttl::Persistent),
+            remaining => Some(Ttl::Remaining(remaining)),
+        }
+    }
+
+    fn to_json(self) -> serde_json::Value {
+        match self {
+            Ttl::Persistent => serde_json::Value::Null,
+            Ttl::Remaining(remaining) => remaining.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Ttl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ttl::Persistent => write!(f, "none"),
+            Ttl::Remaining(remaining) => write!(f, "{remaining}"),
+        }
+    }
+}
+
+/// A scanned key along with the details requested on the command line
+struct KeyRecord {
+    key: Key,
+    key_type: Option<String>,
+    ttl: Option<Ttl>,
+    size: Option<u64>,
+    node: Option<String>,
+}
+
+impl KeyRecord {
+    fn new(key: Key) -> Self {
+        Self {
+            key,
+            key_type: None,
+            ttl: None,
+            size: None,
+            node: None,
+        }
+    }
+
+    fn has_details(&self) -> bool {
+        self.key_type.is_some() || self.ttl.is_some() || self.size.is_some() || self.node.is_some()
+    }
+
+    /// Plain output columns following the key
+    fn columns(&self, raw_sizes: bool) -> Vec<String> {
+        let mut columns = Vec::new();
+        columns.extend(self.key_type.clone());
+        columns.extend(self.ttl.map(|ttl| ttl.to_string()));
+        columns.extend(self.size.map(|size| {
+            if raw_sizes {
+                size.to_string()
+            } else {
+                HumanBytes(size).to_string()
+            }
+        }));
+        columns.extend(self.node.clone());
+        columns
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let key = self.key.as_str_lossy().into_owned();
+        if !self.has_details() {
+            return key.into();
+        }
+        let mut object = serde_json::Map::new();
+        object.insert("key".to_owned(), key.into());
+        if let Some(key_type) = &self.key_type {
+            object.insert("type".to_owned(), key_type.clone().into());
+        }
+        if let Some(ttl) = self.ttl {
+            object.insert("ttl".to_owned(), ttl.to_json());
+        }
+        if let Some(size) = self.size {
+            object.insert("size".to_owned(), size.into());
+        }
+        if let Some(node) = &self.node {
+            object.insert("node".to_owned(), node.clone().into());
+        }
+        object.into()
+    }
+}
+
+/// Which details to fetch for each scanned key
+#[derive(Debug, Clone, Copy)]
+struct KeyDetails {
+    key_type: bool,
+    ttl: bool,
+    size: bool,
+    node: bool,
+}
+
+impl KeyDetails {
+    /// Names of the columns in tabular output
+    fn header(&self) -> Vec<&'static str> {
+        let mut header = vec!["key"];
+        if self.key_type {
+            header.push("type");
+        }
+        if self.ttl {
+            header.push("ttl");
+        }
+        if self.size {
+            header.push("size");
+        }
+        if self.node {
+            header.push("node");
+        }
+        header
+    }
+
+    /// Fetch the requested details, skipping keys deleted since they were scanned
+    async fn fetch(&self, client: &Client, keys: Vec<Key>) -> color_eyre::Result<Vec<KeyRecord>> {
+        let mut records = self.fetch_from_server(client, keys).await?;
+        if self.node {
+            let routing = node_routing(client)?;
+            for record in &mut records {
+                record.node = Some(key_node(&routing, &record.key));
+            }
+        }
+        Ok(records)
+    }
+
+    async fn fetch_from_server(
+        &self,
+        client: &Client,
+        keys: Vec<Key>,
+    ) -> color_eyre::Result<Vec<KeyRecord>> {
+        let mut records = Vec::with_capacity(keys.len());
+        if !self.key_type && !self.ttl && !self.size {
+            records.extend(keys.into_iter().map(KeyRecord::new));
+            return Ok(records);
+        }
+        let pipe = client.pipeline();
+        for key in &keys {
+            if self.key_type {
+                let _: () = pipe.r#type(key).await?;
+            }
+            if self.ttl {
+                let _: () = pipe.ttl(key).await?;
+            }
+            if self.size {
+                let _: () = pipe.memory_usage(key, Some(0)).await?;
+            }
+        }
+        let mut replies = pipe.all::<Vec<Value>>().await?.into_iter();
+        for key in keys {
+            let mut exists = true;
+            let mut record = KeyRecord::new(key);
+            if self.key_type {
+                let key_type: String = replies.next().unwrap_or(Value::Null).convert()?;
+                exists &= key_type != "none";
+                record.key_type = Some(key_type);
+            }
+            if self.ttl {
+                let ttl = Ttl::from_reply(replies.next().unwrap_or(Value::Null).convert()?);
+                exists &= ttl.is_some();
+                record.ttl = ttl;
+            }
+            if self.size {
+                let size: Option<u64> = replies.next().unwrap_or(Value::Null).convert()?;
+                exists &= size.is_some();
+                record.size = size;
+            }
+            if exists {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Write a JSON record, either as an element of a single array or on its own line
+fn write_json_record(
+    out: &mut impl Write,
+    output: OutputFormat,
+    index: usize,
+    record: &serde_json::Value,
+) -> std::io::Result<()> {
+    match output {
+        OutputFormat::Json => {
+            let separator = if index == 0 { "[" } else { "," };
+            write!(out, "{separator}{record}")
+        }
+        _ => writeln!(out, "{record}"),
+    }
+}
+
+/// Close the JSON array opened by [write_json_record], if any
+fn finish_json_records(
+    out: &mut impl Write,
+    output: OutputFormat,
+    written: usize,
+) -> std::io::Result<()> {
+    if output == OutputFormat::Json {
+        let opening = if written == 0 { "[" } else { "" };
+        writeln!(out, "{opening}]")?;
+    }
+    Ok(())
+}
+
+/// Writes keys in the requested output format, one page at a time
+///
+/// Plain output writes the raw key bytes, as keys are not guaranteed to be valid UTF-8, followed
+/// by tab separated details, while JSON output replaces invalid sequences.
+struct KeyWriter<W: Write> {
+    out: RecordSink<W>,
+    output: OutputFormat,
+    terminator: u8,
+    raw_sizes: bool,
+    written: usize,
+}
+
+impl<W: Write> KeyWriter<W> {
+    fn new(
+        out: W,
+        output: OutputFormat,
+        print0: bool,
+        raw_sizes: bool,
+        details: &KeyDetails,
+    ) -> color_eyre::Result<Self> {
+        let mut out = RecordSink::new(out, output);
+        out.write_csv(details.header())?;
+        Ok(Self {
+            out,
+            output,
+            terminator: if print0 { b'\0' } else { b'\n' },
+            raw_sizes,
+            written: 0,
+        })
+    }
+
+    fn write_page(&mut self, records: &[KeyRecord]) -> color_eyre::Result<()> {
+        for record in records {
+            match self.output {
+                OutputFormat::Plain => {
+                    let out = self.out.text();
+                    out.write_all(record.key.as_bytes())?;
+                    for column in record.columns(self.raw_sizes) {
+                        write!(out, "\t{column}")?;
+                    }
+                    out.write_all(&[self.terminator])?;
+                }
+                OutputFormat::Csv | OutputFormat::Tsv => {
+                    let columns = record.columns(true);
+                    let fields = std::iter::once(record.key.as_bytes())
+                        .chain(columns.iter().map(|column| column.as_bytes()));
+                    self.out.write_csv(fields)?;
+                }
+                _ => write_json_record(
+                    self.out.text(),
+                    self.output,
+                    self.written,
+                    &record.to_json(),
+                )?,
+            }
+            self.written += 1;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn finish(self) -> color_eyre::Result<W> {
+        self.out.finish(self.output, self.written)
+    }
+}
+
+/// A file written under a temporary name, then moved in place once complete
+///
+/// Readers never see a partially written file, even if the command is interrupted.
+struct AtomicFile {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl AtomicFile {
+    fn create(path: &Path) -> color_eyre::Result<Self> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| eyre!("{} is not a file path", path.display()))?;
+        let mut tmp_name = OsString::from(".");
+        tmp_name.push(file_name);
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+        Ok(Self {
+            path: path.to_owned(),
+            file: BufWriter::new(File::create(&tmp_path)?),
+            tmp_path,
+        })
+    }
+
+    fn commit(self) -> color_eyre::Result<()> {
+        let file = self.file.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        std::fs::rename(&self.tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Destination of a command output, `-` meaning stdout
+enum Output {
+    Stdout(std::io::StdoutLock<'static>),
+    File(AtomicFile),
+}
+
+/// Open a file for reading, stdin being read when there is no path or it is "-"
+fn open_input(path: Option<&Path>) -> color_eyre::Result<Box<dyn BufRead>> {
+    match path {
+        Some(path) if path != Path::new("-") => Ok(Box::new(BufReader::new(File::open(path)?))),
+        _ => Ok(Box::new(std::io::stdin().lock())),
+    }
+}
+
+impl Output {
+    fn open(path: Option<&Path>) -> color_eyre::Result<Self> {
+        match path {
+            Some(path) if path != Path::new("-") => Ok(Output::File(AtomicFile::create(path)?)),
+            _ => Ok(Output::Stdout(std::io::stdout().lock())),
+        }
+    }
+
+    fn is_file(&self) -> bool {
+        matches!(self, Output::File(_))
+    }
+
+    fn commit(self) -> color_eyre::Result<()> {
+        match self {
+            Output::Stdout(mut stdout) => Ok(stdout.flush()?),
+            Output::File(file) => file.commit(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Stdout(stdout) => stdout.write(buf),
+            Output::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Stdout(stdout) => stdout.flush(),
+            Output::File(file) => file.flush(),
+        }
+    }
+}
+
+async fn scan_keys(
+    client: &Client,
+    scan_options: &ScanOptions,
+    output: OutputFormat,
+    print0: bool,
+    raw_sizes: bool,
+    details: KeyDetails,
+    out: Option<&Path>,
+) -> color_eyre::Result<()> {
+    let destination = Output::open(out)?;
+    let pb = if destination.is_file() {
+        ProgressBar::new_spinner().with_style(ProgressStyle::with_template(
+            "{spinner} {pos} keys written",
+        )?)
+    } else {
+        ProgressBar::hidden()
+    };
+    let mut writer = KeyWriter::new(destination, output, print0, raw_sizes, &details)?;
+    if scan_options.sorted || scan_options.reversed {
+        let keys = scan(client, scan_options).await?;
+        for chunk in keys.chunks(1000) {
+            writer.write_page(&details.fetch(client, chunk.to_vec()).await?)?;
+            pb.set_position(writer.written as u64);
+        }
+    } else {
+        let mut scanner = KeyScanner::new(client, scan_options)?;
+        while let Some(keys) = scanner.next_page().await? {
+            writer.write_page(&details.fetch(client, keys).await?)?;
+            pb.set_position(writer.written as u64);
+        }
+    }
+    pb.finish_and_clear();
+    let written = writer.written;
+    writer.finish()?.commit()?;
+    if let Some(out) = out {
+        eprintln!("Wrote {written} keys to {}", out.display());
+    }
+    Ok(())
+}
+
+/// Number of keys matching a pattern, and of each node with --by-node
+#[derive(Debug, Default)]
+struct KeyCount {
+    count: u64,
+    nodes: BTreeMap<String, u64>,
+}
+
+async fn count_keys(
+    client: &Client,
+    scan_options: &ScanOptions,
+    verbose: bool,
+    by_node: bool,
+    watch: Option<Duration>,
+) -> color_eyre::Result<()> {
+    let routing = by_node.then(|| node_routing(client)).transpose()?;
+    let count = || async {
+        let start = std::time::Instant::now();
+        let mut scanner = KeyScanner::new(client, scan_options)?;
+        let mut counted = KeyCount::default();
+        while let Some(keys) = scanner.next_page().await? {
+            counted.count += keys.len() as u64;
+            if let Some(routing) = &routing {
+                for key in &keys {
+                    *counted.nodes.entry(key_node(routing, key)).or_default() += 1;
+                }
+            }
+        }
+        if verbose {
+            let elapsed = start.elapsed();
+            eprintln!(
+                "Counted {} keys in {elapsed:.2?} ({:.0} keys/s)",
+                counted.count,
+                counted.count as f64 / elapsed.as_secs_f64()
+            );
+        }
+        Ok(counted)
+    };
+    let print = |counted: &KeyCount, previous: Option<&KeyCount>| {
+        let delta = |count: u64, previous: Option<u64>| match previous {
+            Some(previous) => format!("\t{}", count_delta(count as usize, previous as usize)),
+            None => String::new(),
+        };
+        for (node, node_count) in &counted.nodes {
+            let node_previous =
+                previous.map(|previous| previous.nodes.get(node).copied().unwrap_or_default());
+            println!("{node}\t{node_count}{}", delta(*node_count, node_previous));
+        }
+        let previous = previous.map(|previous| previous.count);
+        println!("{}{}", counted.count, delta(counted.count, previous));
+        Ok(())
+    };
+    match watch {
+        Some(interval) => watch_runs(interval, count, print).await,
+        None => print(&count().await?, None),
+    }
+}
+
+/// Change of a count since the previous run of a watch mode, with its sign
+fn count_delta(count: usize, previous: usize) -> String {
+    format!("{:+}", count as i64 - previous as i64)
+}
+
+/// Run `run` every `interval` until interrupted, clearing the screen to `render` each result
+///
+/// The previous result is given to `render` to show what changed. A run taking longer than the
+/// interval is followed by the next one right away, so that runs never overlap. The last result is
+/// rendered again when interrupted, leaving it on the terminal.
+async fn watch_runs<T, F>(
+    interval: Duration,
+    mut run: impl FnMut() -> F,
+    mut render: impl FnMut(&T, Option<&T>) -> color_eyre::Result<()>,
+) -> color_eyre::Result<()>
+where
+    F: Future<Output = color_eyre::Result<T>>,
+{
+    let mut last: Option<(T, Option<T>, std::time::SystemTime)> = None;
+    let mut redraw = |result: &T, previous: Option<&T>, time| {
+        // Clear the screen and move the cursor to its top left corner
+        print!("\x1b[2J\x1b[H");
+        println!(
+            "Every {}, last run at {}",
+            humantime::format_duration(interval),
+            humantime::format_rfc3339_seconds(time)
+        );
+        render(result, previous)
+    };
+    loop {
+        let start = std::time::Instant::now();
+        let result = tokio::select! {
+            result = run() => result?,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let time = std::time::SystemTime::now();
+        let previous = last.take().map(|(result, _, _)| result);
+        redraw(&result, previous.as_ref(), time)?;
+        last = Some((result, previous, time));
+        tokio::select! {
+            _ = tokio::time::sleep(interval.saturating_sub(start.elapsed())) => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    if let Some((result, previous, time)) = &last {
+        redraw(result, previous.as_ref(), *time)?;
+    }
+    Ok(())
+}
+
+/// A value fetched by all-items, depending on the type of the key
+///
+/// Values are kept as bytes, as they are not guaranteed to be valid UTF-8.
+enum ItemValue {
+    String(Vec<u8>),
+    Hash(BTreeMap<Vec<u8>, Vec<u8>>),
+    List(Vec<Vec<u8>>),
+    Set(Vec<Vec<u8>>),
+    ZSet(Vec<(Vec<u8>, f64)>),
+    /// Selected hash fields, `None` when missing
+    Fields(Vec<(String, Option<Vec<u8>>)>),
+    /// Length of the value, written by --sizes
+    Size(u64),
+    /// String value parsed by --json-values
+    Json(serde_json::Value),
+    /// Part of a string value extracted by --jsonpath
+    Extracted(serde_json::Value),
+}
+
+/// Text of an extracted JSON value, strings being written without quotes
+fn extracted_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Escape the characters that would break the tab separated plain output
+fn escape_plain(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+impl ItemValue {
+    /// Plain output lines, collection elements each getting their own line prefixed by the key
+    ///
+    /// Strings and hashes are prefixed by the key only when `show_keys` is set. The TTL, when
+    /// given, follows the key. Values sharing a line with a prefix are escaped.
+    fn plain_lines(
+        &self,
+        key: &Key,
+        show_keys: bool,
+        ttl: Option<Ttl>,
+        encoding: Encoding,
+    ) -> Vec<Vec<u8>> {
+        let ttl = ttl.map(|ttl| ttl.to_string());
+        let line = |key: Option<&Key>, fields: &[&[u8]]| {
+            let prefix = key.map(Key::as_bytes).into_iter();
+            let ttl = ttl.as_deref().map(str::as_bytes).into_iter();
+            prefix
+                .chain(ttl)
+                .chain(fields.iter().copied())
+                .collect::<Vec<_>>()
+                .join(&b'\t')
+        };
+        let key_prefix = show_keys.then_some(key);
+        match self {
+            ItemValue::String(value) if key_prefix.is_none() && ttl.is_none() => {
+                let value = match encoding {
+                    Encoding::Utf8 => String::from_utf8_lossy(value).into_owned().into_bytes(),
+                    _ => encoding.plain(value).into_owned(),
+                };
+                vec![value]
+            }
+            ItemValue::String(value) => vec![line(key_prefix, &[&encoding.plain(value)])],
+            ItemValue::Hash(fields) => {
+                let pairs: Vec<String> = fields
+                    .iter()
+                    .map(|(field, value)| {
+                        format!("{}={}", encoding.text(field), encoding.text(value))
+                    })
+                    .collect();
+                let pairs = pairs.join(" ");
+                if key_prefix.is_none() && ttl.is_none() {
+                    vec![pairs.into_bytes()]
+                } else {
+                    vec![line(key_prefix, &[escape_plain(&pairs).as_bytes()])]
+                }
+            }
+            ItemValue::List(elements) | ItemValue::Set(elements) => elements
+                .iter()
+                .map(|element| line(Some(key), &[&encoding.plain(element)]))
+                .collect(),
+            ItemValue::ZSet(members) => members
+                .iter()
+                .map(|(member, score)| {
+                    line(
+                        Some(key),
+                        &[&encoding.plain(member), score.to_string().as_bytes()],
+                    )
+                })
+                .collect(),
+            ItemValue::Fields(fields) => {
+                let values: Vec<Cow<[u8]>> = fields
+                    .iter()
+                    .map(|(_, value)| match value {
+                        Some(value) => encoding.plain(value),
+                        None => b"nil".into(),
+                    })
+                    .collect();
+                let values: Vec<&[u8]> = values.iter().map(AsRef::as_ref).collect();
+                vec![line(Some(key), &values)]
+            }
+            ItemValue::Size(size) => vec![line(Some(key), &[size.to_string().as_bytes()])],
+            ItemValue::Json(document) if key_prefix.is_none() && ttl.is_none() => {
+                let pretty = serde_json::to_string_pretty(document).unwrap_or_default();
+                vec![pretty.into_bytes()]
+            }
+            ItemValue::Json(document) => vec![line(key_prefix, &[document.to_string().as_bytes()])],
+            ItemValue::Extracted(value) => {
+                vec![line(
+                    Some(key),
+                    &[escape_plain(&extracted_text(value)).as_bytes()],
+                )]
+            }
+        }
+    }
+
+    /// Parse a string value as JSON, extracting `path` from it when given
+    ///
+    /// Other types are left as they are.
+    fn parse_json(self, path: Option<&JsonPath>) -> Result<Self, serde_json::Error> {
+        let ItemValue::String(value) = self else {
+            return Ok(self);
+        };
+        let document: serde_json::Value = serde_json::from_slice(&value)?;
+        let Some(path) = path else {
+            return Ok(ItemValue::Json(document));
+        };
+        let extracted = match path.query(&document).all().as_slice() {
+            [] => serde_json::Value::Null,
+            [node] => (*node).clone(),
+            nodes => nodes.iter().map(|node| (*node).clone()).collect(),
+        };
+        Ok(ItemValue::Extracted(extracted))
+    }
+
+    /// JSON representation, hash field names being read as UTF-8 unless base64 or hex encoded
+    fn to_json(&self, encoding: Encoding) -> serde_json::Value {
+        match self {
+            ItemValue::String(value) => encoding.json(value),
+            ItemValue::Hash(fields) => fields
+                .iter()
+                .map(|(field, value)| (encoding.text(field).into_owned(), encoding.json(value)))
+                .collect::<serde_json::Map<_, _>>()
+                .into(),
+            ItemValue::List(elements) | ItemValue::Set(elements) => elements
+                .iter()
+                .map(|element| encoding.json(element))
+                .collect(),
+            ItemValue::ZSet(members) => members
+                .iter()
+                .map(|(member, score)| serde_json::json!([encoding.json(member), score]))
+                .collect(),
+            ItemValue::Fields(fields) => fields
+                .iter()
+                .map(|(field, value)| {
+                    let value = value
+                        .as_deref()
+                        .map_or(serde_json::Value::Null, |value| encoding.json(value));
+                    (field.clone(), value)
+                })
+                .collect::<serde_json::Map<_, _>>()
+                .into(),
+            ItemValue::Size(size) => (*size).into(),
+            ItemValue::Json(value) | ItemValue::Extracted(value) => value.clone(),
+        }
+    }
+}
+
+/// A key read by all-items, the value being `None` when the key was deleted during the scan
+struct Item<'a> {
+    key: &'a Key,
+    ttl: Option<Ttl>,
+    value: Option<ItemValue>,
+}
+
+/// Keys left out of the all-items output, reported once the scan is done
+#[derive(Debug, Default)]
+struct ItemStats {
+    /// Keys of a type all-items can't read
+    unsupported: usize,
+    /// Keys that expired between the scan and the read
+    expired: usize,
+    /// Keys skipped by --field because they aren't hashes
+    not_hashes: usize,
+    /// Sizes read by --sizes
+    sizes: Vec<u64>,
+    /// Values skipped by --json-values because they aren't valid JSON
+    invalid_json: usize,
+}
+
+impl ItemStats {
+    /// Interpret the PTTL reply read along with an item, returning `None` when it must be skipped
+    fn read_ttl(&mut self, reply: Option<Result<Value, Error>>) -> Option<Ttl> {
+        let pttl = reply
+            .and_then(Result::ok)
+            .and_then(|reply| reply.convert::<i64>().ok());
+        let Some(pttl) = pttl else {
+            self.unsupported += 1;
+            return None;
+        };
+        let ttl = Ttl::from_reply(pttl);
+        // Expired since it was scanned
+        if ttl.is_none() {
+            self.expired += 1;
+        }
+        ttl
+    }
+
+    fn report(&self) {
+        if self.unsupported > 0 {
+            eprintln!("Skipped {} keys with an unsupported type", self.unsupported);
+        }
+        if self.expired > 0 {
+            eprintln!("Skipped {} keys that expired during the scan", self.expired);
+        }
+        if self.not_hashes > 0 {
+            eprintln!("Skipped {} keys that are not hashes", self.not_hashes);
+        }
+        if self.invalid_json > 0 {
+            eprintln!(
+                "Skipped {} values that are not valid JSON",
+                self.invalid_json
+            );
+        }
+    }
+
+    /// Summarize the sizes read by --sizes
+    fn report_sizes(&mut self) {
+        if self.sizes.is_empty() {
+            eprintln!("No sizes read");
+            return;
+        }
+        self.sizes.sort_unstable();
+        let total: u64 = self.sizes.iter().sum();
+        let mean = total as f64 / self.sizes.len() as f64;
+        // Nearest rank percentile
+        let percentile = |p: f64| {
+            let rank = (p * self.sizes.len() as f64).ceil() as usize;
+            self.sizes[rank.clamp(1, self.sizes.len()) - 1]
+        };
+        eprintln!(
+            "{} keys, total {total}, mean {mean:.1}, p50 {}, p95 {}, max {}",
+            self.sizes.len(),
+            percentile(0.5),
+            percentile(0.95),
+            self.sizes[self.sizes.len() - 1]
+        );
+    }
+}
+
+/// Read the values of a chunk of keys, dispatching the read command on the type of each key
+async fn fetch_items<'a>(
+    client: &Client,
+    keys: &'a [Key],
+    options: &ItemOptions,
+    stats: &mut ItemStats,
+) -> color_eyre::Result<Vec<Item<'a>>> {
+    let max_elements = options.max_elements;
+    // Bounds of the LRANGE / ZRANGE calls, -1 meaning the whole collection
+    let stop = max_elements.map_or(-1, |max| max as i64 - 1);
+    let key_types = key_types(client, keys).await?;
+    let pipe = client.pipeline();
+    let mut fetched = Vec::with_capacity(keys.len());
+    for (key, key_type) in keys.iter().zip(key_types) {
+        let key_type = match KeyType::from_reply(&key_type) {
+            Some(KeyType::Hash) => KeyType::Hash,
+            _ if options.hash_only => continue,
+            // Deleted since it was scanned
+            None if key_type == "none" => continue,
+            None | Some(KeyType::Stream) => {
+                stats.unsupported += 1;
+                continue;
+            }
+            Some(key_type) => key_type,
+        };
+        if options.with_ttl {
+            pipe.pttl::<(), _>(key).await?;
+        }
+        if max_elements.is_some() {
+            match key_type {
+                KeyType::List => pipe.llen::<(), _>(key).await?,
+                KeyType::Set => pipe.scard::<(), _>(key).await?,
+                KeyType::Zset => pipe.zcard::<(), _>(key).await?,
+                _ => {}
+            }
+        }
+        match key_type {
+            KeyType::Hash => pipe.hgetall::<(), _>(key).await?,
+            KeyType::List => pipe.lrange::<(), _>(key, 0, stop).await?,
+            KeyType::Set => match max_elements {
+                Some(max) => pipe.srandmember::<(), _>(key, Some(max as usize)).await?,
+                None => pipe.smembers::<(), _>(key).await?,
+            },
+            KeyType::Zset => {
+                pipe.zrange::<(), _, _, _>(key, 0, stop, None, false, None, true)
+                    .await?
+            }
+            KeyType::Json => {
+                let args = vec![key.clone().into(), Value::from(options.path.as_str())];
+                pipe.custom::<(), _>(json_get_command(), args).await?
+            }
+            _ => pipe.get::<(), _>(key).await?,
+        }
+        fetched.push((key, key_type));
+    }
+    let mut items = Vec::with_capacity(fetched.len());
+    if fetched.is_empty() {
+        return Ok(items);
+    }
+    // The type of a key may change between TYPE and the read, so errors only skip that key
+    let mut replies = pipe.try_all::<Value>().await.into_iter();
+    for (key, key_type) in fetched {
+        let pttl = options.with_ttl.then(|| replies.next());
+        let total = match key_type {
+            KeyType::List | KeyType::Set | KeyType::Zset if max_elements.is_some() => replies
+                .next()
+                .and_then(Result::ok)
+                .and_then(|reply| reply.convert::<u64>().ok()),
+            _ => None,
+        };
+        let Some(Ok(reply)) = replies.next() else {
+            stats.unsupported += 1;
+            continue;
+        };
+        let ttl = match pttl.map(|pttl| stats.read_ttl(pttl)) {
+            Some(None) => continue,
+            ttl => ttl.flatten(),
+        };
+        // Deleted since its type was read
+        if reply.is_null() {
+            items.push(Item {
+                key,
+                ttl,
+                value: None,
+            });
+            continue;
+        }
+        let value = match key_type {
+            KeyType::Hash => reply.convert().map(ItemValue::Hash).ok(),
+            KeyType::List => reply.convert().map(ItemValue::List).ok(),
+            KeyType::Set => reply.convert().map(ItemValue::Set).ok(),
+            KeyType::Zset => reply.convert().map(ItemValue::ZSet).ok(),
+            KeyType::Json => reply
+                .convert::<String>()
+                .ok()
+                .and_then(|reply| redisjson_document(&reply, &options.path))
+                .map(ItemValue::Json),
+            _ => reply.convert().map(ItemValue::String).ok(),
+        };
+        let Some(value) = value else {
+            stats.unsupported += 1;
+            continue;
+        };
+        if let (Some(total), Some(max)) = (total, max_elements)
+            && total > max
+        {
+            eprintln!(
+                "Warning: {} has {total} elements, only {max} were fetched",
+                key.as_str_lossy()
+            );
+        }
+        items.push(Item {
+            key,
+            ttl,
+            value: Some(value),
+        });
+    }
+    Ok(items)
+}
+
+/// Read a chunk of string keys with MGET, skipping the TYPE lookups
+///
+/// MGET can't span hash slots, so in a cluster keys are grouped by slot, the items still being
+/// returned in the order of the keys. Keys that are no longer strings read as deleted.
+async fn fetch_strings<'a>(
+    client: &Client,
+    keys: &'a [Key],
+    options: &ItemOptions,
+    stats: &mut ItemStats,
+) -> color_eyre::Result<Vec<Item<'a>>> {
+    if keys.is_empty() {
+        return Ok(Vec::new());
+    }
+    let groups: Vec<Vec<usize>> = if client.is_clustered() {
+        let mut slots = BTreeMap::<u16, Vec<usize>>::new();
+        for (index, key) in keys.iter().enumerate() {
+            slots
+                .entry(fred::util::redis_keyslot(key.as_bytes()))
+                .or_default()
+                .push(index);
+        }
+        slots.into_values().collect()
+    } else {
+        vec![(0..keys.len()).collect()]
+    };
+    let pipe = client.pipeline();
+    for group in &groups {
+        if options.with_ttl {
+            for &index in group {
+                pipe.pttl::<(), _>(&keys[index]).await?;
+            }
+        }
+        let group_keys: Vec<Key> = group.iter().map(|&index| keys[index].clone()).collect();
+        pipe.mget::<(), _>(group_keys).await?;
+    }
+    let mut replies = pipe.try_all::<Value>().await.into_iter();
+    let mut items: Vec<Option<Item>> = keys.iter().map(|_| None).collect();
+    for group in &groups {
+        let pttls: Vec<_> = group
+            .iter()
+            .map(|_| options.with_ttl.then(|| replies.next()))
+            .collect();
+        let values: Vec<Option<Vec<u8>>> = replies.next().unwrap_or(Ok(Value::Null))?.convert()?;
+        for ((&index, pttl), value) in group.iter().zip(pttls).zip(values) {
+            let ttl = match pttl.map(|pttl| stats.read_ttl(pttl)) {
+                Some(None) => continue,
+                ttl => ttl.flatten(),
+            };
+            items[index] = Some(Item {
+                key: &keys[index],
+                ttl,
+                value: value.map(ItemValue::String),
+            });
+        }
+    }
+    Ok(items.into_iter().flatten().collect())
+}
+
+/// Read the length of the values of a chunk of keys, dispatching the command on the type of each
+/// key
+async fn fetch_sizes<'a>(
+    client: &Client,
+    keys: &'a [Key],
+    options: &ItemOptions,
+    stats: &mut ItemStats,
+) -> color_eyre::Result<Vec<Item<'a>>> {
+    let key_types = key_types(client, keys).await?;
+    let pipe = client.pipeline();
+    let mut fetched = Vec::with_capacity(keys.len());
+    for (key, key_type) in keys.iter().zip(key_types) {
+        let key_type = match KeyType::from_reply(&key_type) {
+            Some(KeyType::Hash) => KeyType::Hash,
+            _ if options.hash_only => continue,
+            // Deleted since it was scanned
+            None if key_type == "none" => continue,
+            None | Some(KeyType::Json) => {
+                stats.unsupported += 1;
+                continue;
+            }
+            Some(key_type) => key_type,
+        };
+        if options.with_ttl {
+            pipe.pttl::<(), _>(key).await?;
+        }
+        key_type.queue_len(&pipe, key).await?;
+        fetched.push(key);
+    }
+    let mut items = Vec::with_capacity(fetched.len());
+    if fetched.is_empty() {
+        return Ok(items);
+    }
+    let mut replies = pipe.try_all::<Value>().await.into_iter();
+    for key in fetched {
+        let pttl = options.with_ttl.then(|| replies.next());
+        let size = replies
+            .next()
+            .and_then(Result::ok)
+            .and_then(|reply| reply.convert::<u64>().ok());
+        let Some(size) = size else {
+            stats.unsupported += 1;
+            continue;
+        };
+        let ttl = match pttl.map(|pttl| stats.read_ttl(pttl)) {
+            Some(None) => continue,
+            ttl => ttl.flatten(),
+        };
+        stats.sizes.push(size);
+        items.push(Item {
+            key,
+            ttl,
+            value: Some(ItemValue::Size(size)),
+        });
+    }
+    Ok(items)
+}
+
+/// Read the --field hash fields of a chunk of keys with HMGET, which a wrong type error makes skip
+async fn fetch_fields<'a>(
+    client: &Client,
+    keys: &'a [Key],
+    options: &ItemOptions,
+    stats: &mut ItemStats,
+) -> color_eyre::Result<Vec<Item<'a>>> {
+    if keys.is_empty() {
+        return Ok(Vec::new());
+    }
+    let pipe = client.pipeline();
+    for key in keys {
+        if options.with_ttl {
+            pipe.pttl::<(), _>(key).await?;
+        }
+        pipe.hmget::<(), _, _>(key, options.fields.clone()).await?;
+    }
+    let mut replies = pipe.try_all::<Value>().await.into_iter();
+    let mut items = Vec::with_capacity(keys.len());
+    for key in keys {
+        let pttl = options.with_ttl.then(|| replies.next());
+        let values = match replies.next() {
+            Some(Ok(reply)) => reply.convert::<Vec<Option<Vec<u8>>>>()?,
+            Some(Err(_)) => {
+                stats.not_hashes += 1;
+                continue;
+            }
+            None => break,
+        };
+        let ttl = match pttl.map(|pttl| stats.read_ttl(pttl)) {
+            Some(None) => continue,
+            ttl => ttl.flatten(),
+        };
+        let fields = options.fields.iter().cloned().zip(values).collect();
+        items.push(Item {
+            key,
+            ttl,
+            value: Some(ItemValue::Fields(fields)),
+        });
+    }
+    Ok(items)
+}
+
+/// Writes the items read by all-items in the requested output format
+struct ItemWriter<W: Write> {
+    out: RecordSink<W>,
+    output: OutputFormat,
+    show_keys: bool,
+    with_ttl: bool,
+    encoding: Encoding,
+    written: usize,
+}
+
+impl<W: Write> ItemWriter<W> {
+    fn new(out: W, options: &ItemOptions) -> color_eyre::Result<Self> {
+        let mut out = RecordSink::new(out, options.output);
+        let mut header = vec!["key"];
+        if options.with_ttl {
+            header.push("ttl");
+        }
+        if options.sizes {
+            header.push("size");
+        } else if options.fields.is_empty() {
+            header.push("value");
+        } else {
+            header.extend(options.fields.iter().map(String::as_str));
+        }
+        out.write_csv(header)?;
+        Ok(Self {
+            out,
+            output: options.output,
+            show_keys: options.show_keys,
+            with_ttl: options.with_ttl,
+            encoding: options.encoding,
+            written: 0,
+        })
+    }
+
+    fn write(&mut self, item: &Item) -> color_eyre::Result<()> {
+        match (self.output, &item.value) {
+            (OutputFormat::Plain, Some(value)) => {
+                let lines = value.plain_lines(item.key, self.show_keys, item.ttl, self.encoding);
+                // A raw string on its own is written exactly as stored
+                let terminator: &[u8] = match value {
+                    ItemValue::String(_)
+                        if self.encoding == Encoding::Raw && !self.show_keys && !self.with_ttl =>
+                    {
+                        b""
+                    }
+                    _ => b"\n",
+                };
+                let out = self.out.text();
+                for line in lines {
+                    out.write_all(&line)?;
+                    out.write_all(terminator)?;
+                }
+            }
+            (OutputFormat::Plain, None) if self.show_keys => {
+                let out = self.out.text();
+                out.write_all(item.key.as_bytes())?;
+                if let Some(ttl) = item.ttl {
+                    write!(out, "\t{ttl}")?;
+                }
+                writeln!(out, "\t")?;
+            }
+            (OutputFormat::Plain, None) => return Ok(()),
+            (OutputFormat::Csv | OutputFormat::Tsv, value) => {
+                let values = match value {
+                    Some(ItemValue::String(value)) => vec![self.encoding.text(value).into_owned()],
+                    Some(ItemValue::Extracted(value)) => vec![extracted_text(value)],
+                    // One column per field
+                    Some(ItemValue::Fields(fields)) => fields
+                        .iter()
+                        .map(|(_, value)| {
+                            value.as_deref().map_or_else(String::new, |value| {
+                                self.encoding.text(value).into_owned()
+                            })
+                        })
+                        .collect(),
+                    Some(value) => vec![value.to_json(self.encoding).to_string()],
+                    None => vec![String::new()],
+                };
+                let mut fields = vec![item.key.as_bytes().to_vec()];
+                if let Some(ttl) = item.ttl {
+                    fields.push(ttl.to_string().into_bytes());
+                }
+                fields.extend(values.into_iter().map(String::into_bytes));
+                self.out.write_csv(fields)?;
+            }
+            (_, value) => {
+                let key = item.key.as_str_lossy();
+                let name = match value {
+                    Some(ItemValue::Size(_)) => "size",
+                    _ => "value",
+                };
+                let value = value.as_ref().map(|value| value.to_json(self.encoding));
+                let mut record = serde_json::json!({"key": key, name: value});
+                if self.with_ttl {
+                    record["ttl"] = item.ttl.map_or(serde_json::Value::Null, Ttl::to_json);
+                }
+                write_json_record(self.out.text(), self.output, self.written, &record)?;
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> color_eyre::Result<W> {
+        self.out.finish(self.output, self.written)
+    }
+}
+
+/// A completed all-items output file
+struct Shard {
+    path: PathBuf,
+    records: usize,
+    bytes: u64,
+}
+
+/// Destination of the all-items output, moving on to a new shard every `shard_size` items
+struct ItemOutput<'a> {
+    options: &'a ItemOptions,
+    writer: Option<ItemWriter<Output>>,
+    shards: Vec<Shard>,
+}
+
+impl<'a> ItemOutput<'a> {
+    fn new(options: &'a ItemOptions) -> Self {
+        Self {
+            options,
+            writer: None,
+            shards: Vec::new(),
+        }
+    }
+
+    /// Path of the current shard, `None` for stdout
+    fn path(&self) -> Option<PathBuf> {
+        let out = self
+            .options
+            .out
+            .as_deref()
+            .filter(|out| *out != Path::new("-"))?;
+        if self.options.shard_size.is_none() {
+            return Some(out.to_owned());
+        }
+        let mut path = out.as_os_str().to_owned();
+        path.push(format!(
+            ".{:05}.{}",
+            self.shards.len(),
+            self.options.output.extension()
+        ));
+        Some(path.into())
+    }
+
+    fn write(&mut self, item: &Item) -> color_eyre::Result<()> {
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => {
+                let out = Output::open(self.path().as_deref())?;
+                self.writer.insert(ItemWriter::new(out, self.options)?)
+            }
+        };
+        writer.write(item)?;
+        if let Some(shard_size) = self.options.shard_size
+            && writer.written as u64 >= shard_size
+        {
+            self.finish_shard()?;
+        }
+        Ok(())
+    }
+
+    fn finish_shard(&mut self) -> color_eyre::Result<()> {
+        let path = self.path();
+        let Some(writer) = self.writer.take() else {
+            return Ok(());
+        };
+        let records = writer.written;
+        writer.finish()?.commit()?;
+        if let Some(path) = path {
+            let bytes = std::fs::metadata(&path)?.len();
+            self.shards.push(Shard {
+                path,
+                records,
+                bytes,
+            });
+        }
+        Ok(())
+    }
+
+    /// Complete the last shard and report what was written
+    fn finish(mut self) -> color_eyre::Result<()> {
+        // Unsharded output is written even when empty, for the JSON brackets and CSV header
+        if self.writer.is_none() && self.options.shard_size.is_none() {
+            let out = Output::open(self.path().as_deref())?;
+            self.writer = Some(ItemWriter::new(out, self.options)?);
+        }
+        self.finish_shard()?;
+        if self.options.shard_size.is_some() {
+            let files: Vec<_> = self
+                .shards
+                .iter()
+                .map(|shard| {
+                    serde_json::json!({
+                        "path": shard.path,
+                        "records": shard.records,
+                        "bytes": shard.bytes,
+                    })
+                })
+                .collect();
+            let manifest = serde_json::json!({
+                "shards": self.shards.len(),
+                "records": self.shards.iter().map(|shard| shard.records).sum::<usize>(),
+                "bytes": self.shards.iter().map(|shard| shard.bytes).sum::<u64>(),
+                "files": files,
+            });
+            println!("{manifest:#}");
+        } else if let [shard] = self.shards.as_slice() {
+            eprintln!("Wrote {} items to {}", shard.records, shard.path.display());
+        }
+        Ok(())
+    }
+}
+
+async fn all_items(
+    client: &Client,
+    scan_options: &ScanOptions,
+    item_options: &ItemOptions,
+) -> color_eyre::Result<()> {
+    if item_options.encoding == Encoding::Raw && item_options.output != OutputFormat::Plain {
+        return Err(eyre!("--encoding raw can only be used with plain output"));
+    }
+    let mut writer = ItemOutput::new(item_options);
+    let mut stats = ItemStats::default();
+    // Without type dispatch, a single MGET reads a whole batch
+    let strings_only = scan_options.types == [KeyType::String] && !item_options.hash_only;
+    let jsonpath = item_options.jsonpath.as_ref();
+    let mut write_keys = async |keys: &[Key]| -> color_eyre::Result<()> {
+        for chunk in keys.chunks(item_options.batch_size as usize) {
+            let items = if item_options.sizes {
+                fetch_sizes(client, chunk, item_options, &mut stats).await?
+            } else if !item_options.fields.is_empty() {
+                fetch_fields(client, chunk, item_options, &mut stats).await?
+            } else if strings_only {
+                fetch_strings(client, chunk, item_options, &mut stats).await?
+            } else {
+                fetch_items(client, chunk, item_options, &mut stats).await?
+            };
+            for mut item in items {
+                if item_options.json_values || item_options.jsonpath.is_some() {
+                    match item
+                        .value
+                        .map(|value| value.parse_json(jsonpath))
+                        .transpose()
+                    {
+                        Ok(value) => item.value = value,
+                        Err(_) => {
+                            stats.invalid_json += 1;
+                            continue;
+                        }
+                    }
+                }
+                writer.write(&item)?;
+            }
+        }
+        Ok(())
+    };
+    // Sorting needs every key up front, otherwise each page is read as soon as it is scanned
+    if scan_options.sorted || scan_options.reversed {
+        write_keys(&scan(client, scan_options).await?).await?;
+    } else {
+        let mut scanner = KeyScanner::new(client, scan_options)?;
+        while let Some(keys) = scanner.next_page().await? {
+            write_keys(&keys).await?;
+        }
+    }
+    writer.finish()?;
+    stats.report();
+    if item_options.sizes {
+        stats.report_sizes();
+    }
+    Ok(())
+}
+
+/// Print the first few keys to stderr
+fn print_sample(keys: &[Key]) {
+    for key in keys.iter().take(10) {
+        eprintln!("  {}", key.as_str_lossy());
+    }
+}
+
+/// Number of keys whose MEMORY USAGE is read to estimate the size of a deletion
+const SIZE_SAMPLE: usize = 1000;
+
+/// Count the keys with and without a TTL, leaving out those that no longer exist
+async fn count_ttls(
+    client: &Client,
+    keys: &[Key],
+    batch_options: &BatchOptions,
+) -> color_eyre::Result<(usize, usize)> {
+    let (mut with_ttl, mut without_ttl) = (0, 0);
+    for chunk in keys.chunks(batch_options.batch_size as usize) {
+        let pipe = client.pipeline();
+        for key in chunk {
+            let _: () = pipe.pttl(key).await?;
+        }
+        let pttls: Vec<i64> = pipe.all().await?;
+        for pttl in pttls {
+            match Ttl::from_reply(pttl) {
+                Some(Ttl::Persistent) => without_ttl += 1,
+                Some(Ttl::Remaining(_)) => with_ttl += 1,
+                None => {}
+            }
+        }
+    }
+    Ok((with_ttl, without_ttl))
+}
+
+/// Dry run summary of the keys that would be deleted, only issuing read commands
+/// Number of prefixes listed by the dry run of del-pattern, the most common first
+const DELETION_PREFIXES: usize = 20;
+
+async fn describe_deletion(
+    client: &Client,
+    keys: &[Key],
+    delete_options: &DeleteOptions,
+) -> color_eyre::Result<()> {
+    println!("{} keys to delete", keys.len());
+    if keys.is_empty() {
+        return Ok(());
+    }
+    let (with_ttl, without_ttl) = count_ttls(client, keys, &delete_options.batch).await?;
+    println!("{with_ttl} with TTL, {without_ttl} without TTL");
+    if delete_options.sample > 0 {
+        println!("Sample:");
+        for key in keys.iter().take(delete_options.sample) {
+            println!("  {}", key.as_str_lossy());
+        }
+    }
+    let (groups, _) = group_keys(keys, "", &[":".to_owned()], 1);
+    println!("Prefixes:");
+    let groups = groups.most_common();
+    for (group, count) in groups.iter().take(DELETION_PREFIXES) {
+        println!("  {group}: {count}");
+    }
+    if groups.len() > DELETION_PREFIXES {
+        println!("  (and {} more prefixes)", groups.len() - DELETION_PREFIXES);
+    }
+    if delete_options.estimate_size {
+        // Evenly spread over the matched keys rather than the first ones
+        let step = keys.len().div_ceil(SIZE_SAMPLE);
+        let pipe = client.pipeline();
+        for key in keys.iter().step_by(step) {
+            let _: () = pipe.memory_usage(key, None).await?;
+        }
+        let sizes: Vec<Option<u64>> = pipe.all().await?;
+        let sizes: Vec<u64> = sizes.into_iter().flatten().collect();
+        if !sizes.is_empty() {
+            let mean = sizes.iter().sum::<u64>() as f64 / sizes.len() as f64;
+            println!(
+                "Estimated size: {} (from {} sampled keys)",
+                HumanBytes((mean * keys.len() as f64) as u64),
+                sizes.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Show the prompt and wait for the user to type "yes", failing when stdin is not a terminal
+fn ask_confirmation(prompt: &str) -> color_eyre::Result<bool> {
+    use std::io::IsTerminal;
+
+    if !std::io::stdin().is_terminal() {
+        return Err(eyre!(
+            "stdin is not a terminal, pass --yes to run without confirmation"
+        ));
+    }
+    eprint!("Type \"yes\" to {prompt}: ");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim() == "yes")
+}
+
+/// Show the keys about to be changed and ask for confirmation to `action`
+fn confirm_keys(pattern: &str, keys: &[Key], action: &str) -> color_eyre::Result<bool> {
+    eprintln!("Pattern {pattern} matches {} keys, such as:", keys.len());
+    print_sample(keys);
+    ask_confirmation(action)
+}
+
+/// Progress bar of a command going through all the matched keys
+fn keys_progress(len: usize, message: String) -> color_eyre::Result<ProgressBar> {
+    let pb = ProgressBar::new(len as u64).with_style(ProgressStyle::with_template(
+        "[{elapsed_precise}/{eta_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+    )?);
+    pb.set_message(message);
+    Ok(pb)
+}
+
+async fn del_pattern(
+    client: &Client,
+    scan_options: &ScanOptions,
+    delete_options: &DeleteOptions,
+) -> color_eyre::Result<()> {
+    if scan_options.filters_idle()
+        && !delete_options.force
+        && let Some(policy) = lfu_policy(client).await?
+    {
+        return Err(eyre!(
+            "maxmemory-policy is {policy}, idle times are not tracked so --idle-gt and --idle-lt can't be used (pass --force to run anyway)"
+        ));
+    }
+    if delete_options.streaming {
+        return del_pattern_streaming(client, scan_options, delete_options).await;
+    }
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    let keys = scanner.collect().await?;
+    if let Some(max_keys) = delete_options.max_keys
+        && keys.len() > max_keys
+    {
+        eprintln!(
+            "Pattern {} matches {} keys, such as:",
+            scan_options.pattern,
+            keys.len()
+        );
+        print_sample(&keys);
+        return Err(eyre!(
+            "More than {max_keys} keys matched, nothing was deleted"
+        ));
+    }
+    if delete_options.batch.dry_run {
+        if !scan_options.exclude.is_empty() {
+            println!(
+                "{} keys matched, {} excluded",
+                keys.len() + scanner.excluded,
+                scanner.excluded
+            );
+        }
+        return describe_deletion(client, &keys, delete_options).await;
+    }
+    if !keys.is_empty()
+        && !delete_options.batch.yes
+        && !confirm_keys(&scan_options.pattern, &keys, "delete them")?
+    {
+        return Err(eyre!("Deletion aborted"));
+    }
+    let message = format!("Deleting keys from pattern {}", scan_options.pattern);
+    let pb = keys_progress(keys.len(), message)?;
+    let mut deleter = Deleter::new(client, delete_options)?;
+    deleter.delete(&keys, &pb).await?;
+    pb.finish();
+    deleter.finish();
+    Ok(())
+}
+
+/// Deletion of the keys as they are scanned, keeping memory bounded to a page
+async fn del_pattern_streaming(
+    client: &Client,
+    scan_options: &ScanOptions,
+    delete_options: &DeleteOptions,
+) -> color_eyre::Result<()> {
+    let resumed = match &delete_options.resume {
+        Some(path) => {
+            let checkpoint = Checkpoint::load(path)?;
+            if checkpoint.pattern != scan_options.pattern {
+                return Err(eyre!(
+                    "{} was saved for pattern {}",
+                    path.display(),
+                    checkpoint.pattern
+                ));
+            }
+            if checkpoint.filters != scan_options.filters() {
+                return Err(eyre!(
+                    "{} was saved with other filters: {}",
+                    path.display(),
+                    checkpoint.filters
+                ));
+            }
+            Some(checkpoint)
+        }
+        None => None,
+    };
+    let checkpoint_path = delete_options
+        .checkpoint
+        .as_deref()
+        .or(delete_options.resume.as_deref());
+    let mut scanner = match (&resumed, checkpoint_path) {
+        (Some(checkpoint), _) => {
+            KeyScanner::resumable(client, scan_options, Some(checkpoint.cursors.clone()))?
+        }
+        (None, Some(_)) => KeyScanner::resumable(client, scan_options, None)?,
+        (None, None) => KeyScanner::new(client, scan_options)?,
+    };
+    if delete_options.batch.dry_run {
+        let mut count = 0;
+        while let Some(keys) = scanner.next_page().await? {
+            count += keys.len();
+        }
+        println!("{count} keys to delete");
+        return Ok(());
+    }
+    if !delete_options.batch.yes
+        && !ask_confirmation(&format!(
+            "delete the keys matching {} as they are scanned",
+            scan_options.pattern
+        ))?
+    {
+        return Err(eyre!("Deletion aborted"));
+    }
+    let pb = ProgressBar::new_spinner().with_style(ProgressStyle::with_template(
+        "[{elapsed_precise}] {spinner} {pos} keys deleted, {msg}",
+    )?);
+    let mut deleter = Deleter::new(client, delete_options)?;
+    let (mut deleted, mut pages) = resumed
+        .as_ref()
+        .map_or((0, 0), |checkpoint| (checkpoint.deleted, checkpoint.pages));
+    pb.set_position(deleted);
+    loop {
+        let page = scanner.next_page().await?;
+        if let Some(keys) = &page {
+            deleter.delete(keys, &pb).await?;
+            deleted += keys.len() as u64;
+            pages += 1;
+            pb.set_message(format!("{pages} pages"));
+        }
+        // Saved once more at the end, so that resuming a completed run deletes nothing
+        if let (Some(path), Some(cursors)) = (checkpoint_path, scanner.cursors()) {
+            let checkpoint = Checkpoint {
+                pattern: scan_options.pattern.clone(),
+                filters: scan_options.filters(),
+                cursors: cursors.to_vec(),
+                deleted,
+                pages,
+            };
+            checkpoint.save(path)?;
+        }
+        if page.is_none() {
+            break;
+        }
+    }
+    pb.finish();
+    deleter.finish();
+    Ok(())
+}
+
+/// Progress of a streaming deletion, saved by --checkpoint and read back by --resume
+struct Checkpoint {
+    pattern: String,
+    /// Filters of the scan options, from [ScanOptions::filters]
+    filters: serde_json::Value,
+    /// Nodes left to scan with their cursor, the server being `None` outside of a cluster
+    cursors: Vec<(Option<Server>, String)>,
+    deleted: u64,
+    pages: u64,
+}
+
+impl Checkpoint {
+    fn load(path: &Path) -> color_eyre::Result<Self> {
+        let invalid = || eyre!("{} is not a valid checkpoint file", path.display());
+        let json: serde_json::Value = serde_json::from_reader(File::open(path)?)?;
+        let cursors = json["cursors"]
+            .as_array()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|cursor| {
+                let server = match cursor["node"].as_str() {
+                    Some(node) => {
+                        let (host, port) = node.rsplit_once(':').ok_or_else(invalid)?;
+                        Some(Server::new(host, port.parse().map_err(|_| invalid())?))
+                    }
+                    None => None,
+                };
+                let cursor = cursor["cursor"].as_str().ok_or_else(invalid)?;
+                Ok((server, cursor.to_owned()))
+            })
+            .collect::<color_eyre::Result<_>>()?;
+        Ok(Self {
+            pattern: json["pattern"].as_str().ok_or_else(invalid)?.to_owned(),
+            filters: json["filters"].clone(),
+            cursors,
+            deleted: json["deleted"].as_u64().unwrap_or_default(),
+            pages: json["pages"].as_u64().unwrap_or_default(),
+        })
+    }
+
+    /// Write the checkpoint atomically, a crash leaving the previous version in place
+    fn save(&self, path: &Path) -> color_eyre::Result<()> {
+        let cursors: Vec<_> = self
+            .cursors
+            .iter()
+            .map(|(server, cursor)| {
+                let node = server
+                    .as_ref()
+                    .map(|server| format!("{}:{}", server.host, server.port));
+                serde_json::json!({"node": node, "cursor": cursor})
+            })
+            .collect();
+        let json = serde_json::json!({
+            "pattern": self.pattern,
+            "filters": self.filters,
+            "cursors": cursors,
+            "deleted": self.deleted,
+            "pages": self.pages,
+        });
+        let mut file = AtomicFile::create(path)?;
+        serde_json::to_writer_pretty(&mut file, &json)?;
+        file.commit()
+    }
+}
+
+/// Audit trail of the keys deleted by del-pattern, written by --log-deleted
+struct DeletionLog {
+    path: PathBuf,
+    file: BufWriter<File>,
+    records: u64,
+}
+
+impl DeletionLog {
+    fn open(path: &Path) -> color_eyre::Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_owned(),
+            file: BufWriter::new(file),
+            records: 0,
+        })
+    }
+
+    /// Record a batch once it has been deleted, syncing it to disk so that a crash loses at most
+    /// the batch in flight
+    fn write_batch(&mut self, batch: u64, keys: &[Key]) -> color_eyre::Result<()> {
+        let time = humantime::format_rfc3339_millis(std::time::SystemTime::now()).to_string();
+        for key in keys {
+            let record = serde_json::json!({
+                "key": key.as_str_lossy(),
+                "batch": batch,
+                "time": time,
+            });
+            writeln!(self.file, "{record}")?;
+        }
+        self.file.flush()?;
+        self.file.get_ref().sync_data()?;
+        self.records += keys.len() as u64;
+        Ok(())
+    }
+}
+
+/// Pause between the batches of a delete queue, following --sleep or --rate
+#[derive(Debug, Default)]
+struct Pacing {
+    /// Start time and size of the previous batch, which the pause before the next one is based on
+    previous: Option<(std::time::Instant, usize)>,
+}
+
+impl Pacing {
+    /// Wait as needed before a batch of `len` keys
+    ///
+    /// Pausing before the batch rather than after it keeps the ETA honest.
+    async fn wait(&mut self, options: &BatchOptions, len: usize) {
+        self.wait_for(options.rate, options.sleep, len).await;
+    }
+
+    /// Wait as needed before a batch of `len` items, to process at most `rate` items per second
+    /// or else pause for `sleep`
+    async fn wait_for(&mut self, rate: Option<u64>, sleep: Duration, len: usize) {
+        if let Some((start, previous_len)) = self.previous {
+            let pause = match rate {
+                Some(rate) => Duration::from_secs_f64(previous_len as f64 / rate as f64)
+                    .saturating_sub(start.elapsed()),
+                None => sleep,
+            };
+            tokio::time::sleep(pause).await;
+        }
+        self.previous = Some((std::time::Instant::now(), len));
+    }
+}
+
+/// Issues the DEL or UNLINK batches, pausing between them as configured
+struct Deleter<'a> {
+    client: &'a Client,
+    options: &'a DeleteOptions,
+    unlink: bool,
+    /// Pacing of each delete queue, keyed by node for parallel cluster deletes
+    pacing: HashMap<Option<Server>, Pacing>,
+    batches: std::cell::Cell<u64>,
+    log: std::cell::RefCell<Option<DeletionLog>>,
+}
+
+impl<'a> Deleter<'a> {
+    fn new(client: &'a Client, options: &'a DeleteOptions) -> color_eyre::Result<Self> {
+        let unlink = options.unlink
+            && client
+                .server_version()
+                .is_none_or(|version| version.major >= 4);
+        if options.unlink && !unlink {
+            eprintln!("Warning: UNLINK requires Redis 4.0, falling back to DEL");
+        }
+        let log = options
+            .log_deleted
+            .as_deref()
+            .map(DeletionLog::open)
+            .transpose()?;
+        Ok(Self {
+            client,
+            options,
+            unlink,
+            pacing: HashMap::new(),
+            batches: Default::default(),
+            log: log.into(),
+        })
+    }
+
+    /// Split the keys into one queue per node when deleting in parallel
+    fn queues(&self, keys: &[Key]) -> Vec<(Option<Server>, Vec<Key>)> {
+        let routing = self
+            .client
+            .cached_cluster_state()
+            .filter(|_| self.options.parallelism > 1 && self.client.is_clustered());
+        let Some(routing) = routing else {
+            return vec![(None, keys.to_vec())];
+        };
+        let mut queues = HashMap::<Option<Server>, Vec<Key>>::new();
+        for key in keys {
+            let slot = fred::util::redis_keyslot(key.as_bytes());
+            let node = routing.get_server(slot).cloned();
+            queues.entry(node).or_default().push(key.clone());
+        }
+        queues.into_iter().collect()
+    }
+
+    async fn delete(&mut self, keys: &[Key], pb: &ProgressBar) -> color_eyre::Result<()> {
+        let queues: Vec<_> = self
+            .queues(keys)
+            .into_iter()
+            .map(|(node, keys)| {
+                let pacing = self.pacing.remove(&node).unwrap_or_default();
+                (node, keys, pacing)
+            })
+            .collect();
+        let this = &*self;
+        let clustered = self.client.is_clustered();
+        let workers = queues
+            .into_iter()
+            .map(|(node, keys, mut pacing)| async move {
+                let batch_size = this.options.batch.batch_size as usize;
+                for batch in delete_batches(&keys, batch_size, clustered) {
+                    pacing.wait(&this.options.batch, batch.keys.len()).await;
+                    delete_batch(this.client, &batch.commands, this.unlink).await?;
+                    let index = this.batches.replace(this.batches.get() + 1);
+                    if let Some(log) = this.log.borrow_mut().as_mut() {
+                        log.write_batch(index, batch.keys)?;
+                    }
+                    pb.inc(batch.keys.len() as u64);
+                }
+                color_eyre::Result::<_>::Ok((node, pacing))
+            });
+        let results: Vec<_> = futures::StreamExt::buffer_unordered(
+            futures::stream::iter(workers),
+            self.options.parallelism as usize,
+        )
+        .collect()
+        .await;
+        for result in results {
+            let (node, pacing) = result?;
+            self.pacing.insert(node, pacing);
+        }
+        Ok(())
+    }
+
+    fn finish(self) {
+        if let Some(log) = self.log.into_inner() {
+            eprintln!(
+                "Logged {} deleted keys to {}",
+                log.records,
+                log.path.display()
+            );
+        }
+    }
+}
+
+/// Keys deleted together, paced and logged as one batch
+struct DeleteBatch<'a> {
+    keys: &'a [Key],
+    /// Keys of each DEL or UNLINK sent for the batch, a single command unless in a cluster
+    commands: Vec<Vec<Key>>,
+}
+
+/// Split the keys into batches of `batch_size`, each deleted with a command per hash slot in a
+/// cluster
+fn delete_batches(
+    keys: &[Key],
+    batch_size: usize,
+    clustered: bool,
+) -> impl Iterator<Item = DeleteBatch<'_>> {
+    keys.chunks(batch_size).map(move |chunk| {
+        let commands = if clustered {
+            // Multi-key commands can't span hash slots
+            let mut slots = BTreeMap::<u16, Vec<Key>>::new();
+            for key in chunk {
+                let slot = fred::util::redis_keyslot(key.as_bytes());
+                slots.entry(slot).or_default().push(key.clone());
+            }
+            slots.into_values().collect()
+        } else {
+            vec![chunk.to_vec()]
+        };
+        DeleteBatch {
+            keys: chunk,
+            commands,
+        }
+    })
+}
+
+/// Send the commands of a batch from [delete_batches], returning how many keys existed
+async fn delete_batch(
+    client: &Client,
+    commands: &[Vec<Key>],
+    unlink: bool,
+) -> color_eyre::Result<u64> {
+    let pipe = client.pipeline();
+    for keys in commands.iter().cloned() {
+        if unlink {
+            let _: () = pipe.unlink(keys).await?;
+        } else {
+            let _: () = pipe.del(keys).await?;
+        }
+    }
+    let deleted: Vec<u64> = pipe.all().await?;
+    Ok(deleted.into_iter().sum())
+}
+
+async fn expire_pattern(
+    client: &Client,
+    scan_options: &ScanOptions,
+    options: &ExpiryOptions,
+) -> color_eyre::Result<()> {
+    let seconds = options.ttl.as_secs();
+    if seconds == 0 {
+        return Err(eyre!("the TTL must be at least one second"));
+    }
+    let ttl = humantime::format_duration(Duration::from_secs(seconds));
+    let condition = options.condition();
+    if condition.is_some()
+        && client
+            .server_version()
+            .is_some_and(|version| version.major < 7)
+    {
+        return Err(eyre!("--nx, --xx, --gt and --lt require Redis 7.0"));
+    }
+    let keys = scan(client, scan_options).await?;
+    if options.batch.dry_run {
+        let (with_ttl, without_ttl) = count_ttls(client, &keys, &options.batch).await?;
+        println!(
+            "{} keys matched, {with_ttl} with TTL, {without_ttl} without TTL",
+            keys.len()
+        );
+        let updated = if options.only_missing {
+            without_ttl
+        } else {
+            keys.len()
+        };
+        println!("{updated} keys would get a TTL of {ttl}");
+        print_sample(&keys);
+        return Ok(());
+    }
+    let action = format!("set a TTL of {ttl} on them");
+    if !keys.is_empty()
+        && !options.batch.yes
+        && !confirm_keys(&scan_options.pattern, &keys, &action)?
+    {
+        return Err(eyre!("Expiration aborted"));
+    }
+    let message = format!("Expiring keys from pattern {}", scan_options.pattern);
+    let pb = keys_progress(keys.len(), message)?;
+    let mut pacing = Pacing::default();
+    let (mut updated, mut unchanged) = (0, 0);
+    for chunk in keys.chunks(options.batch.batch_size as usize) {
+        pacing.wait(&options.batch, chunk.len()).await;
+        let mut targets = chunk.to_vec();
+        if options.only_missing {
+            let pipe = client.pipeline();
+            for key in chunk {
+                let _: () = pipe.pttl(key).await?;
+            }
+            let pttls: Vec<i64> = pipe.all().await?;
+            targets = targets
+                .into_iter()
+                .zip(pttls)
+                .filter(|(_, pttl)| Ttl::from_reply(*pttl) == Some(Ttl::Persistent))
+                .map(|(key, _)| key)
+                .collect();
+        }
+        let pipe = client.pipeline();
+        for key in &targets {
+            let _: () = pipe
+                .expire(key.clone(), seconds as i64, condition.clone())
+                .await?;
+        }
+        let replies: Vec<i64> = if targets.is_empty() {
+            Vec::new()
+        } else {
+            pipe.all().await?
+        };
+        let set = replies.iter().filter(|&&reply| reply == 1).count();
+        updated += set;
+        unchanged += chunk.len() - set;
+        pb.inc(chunk.len() as u64);
+    }
+    pb.finish();
+    eprintln!("Set a TTL of {ttl} on {updated} keys, {unchanged} left unchanged");
+    Ok(())
+}
+
+async fn persist_pattern(
+    client: &Client,
+    scan_options: &ScanOptions,
+    options: &BatchOptions,
+) -> color_eyre::Result<()> {
+    let keys = scan(client, scan_options).await?;
+    if options.dry_run {
+        let (with_ttl, without_ttl) = count_ttls(client, &keys, options).await?;
+        println!("{} keys matched, {without_ttl} without TTL", keys.len());
+        println!("{with_ttl} keys would have their TTL removed");
+        print_sample(&keys);
+        return Ok(());
+    }
+    if !keys.is_empty()
+        && !options.yes
+        && !confirm_keys(&scan_options.pattern, &keys, "remove their TTL")?
+    {
+        return Err(eyre!("Persist aborted"));
+    }
+    let message = format!("Persisting keys from pattern {}", scan_options.pattern);
+    let pb = keys_progress(keys.len(), message)?;
+    let mut pacing = Pacing::default();
+    let (mut removed, mut without_ttl) = (0, 0);
+    for chunk in keys.chunks(options.batch_size as usize) {
+        pacing.wait(options, chunk.len()).await;
+        let pipe = client.pipeline();
+        for key in chunk {
+            let _: () = pipe.persist(key).await?;
+        }
+        let replies: Vec<i64> = pipe.all().await?;
+        let persisted = replies.iter().filter(|&&reply| reply == 1).count();
+        removed += persisted;
+        without_ttl += chunk.len() - persisted;
+        pb.inc(chunk.len() as u64);
+    }
+    pb.finish();
+    eprintln!("Removed the TTL of {removed} keys, {without_ttl} had no TTL or no longer existed");
+    Ok(())
+}
+
+async fn touch_pattern(
+    client: &Client,
+    scan_options: &ScanOptions,
+    options: &BatchOptions,
+) -> color_eyre::Result<()> {
+    let keys = scan(client, scan_options).await?;
+    if options.dry_run {
+        println!("{} keys matched and would be touched", keys.len());
+        print_sample(&keys);
+        return Ok(());
+    }
+    if !keys.is_empty()
+        && !options.yes
+        && !confirm_keys(&scan_options.pattern, &keys, "touch them")?
+    {
+        return Err(eyre!("Touch aborted"));
+    }
+    let message = format!("Touching keys from pattern {}", scan_options.pattern);
+    let pb = keys_progress(keys.len(), message)?;
+    let mut pacing = Pacing::default();
+    let mut touched = 0;
+    for chunk in keys.chunks(options.batch_size as usize) {
+        pacing.wait(options, chunk.len()).await;
+        let pipe = client.pipeline();
+        for key in chunk {
+            let _: () = pipe.custom(touch_command(), vec![key.clone()]).await?;
+        }
+        let replies: Vec<i64> = pipe.all().await?;
+        touched += replies.iter().sum::<i64>() as usize;
+        pb.inc(chunk.len() as u64);
+    }
+    pb.finish();
+    eprintln!(
+        "Touched {touched} keys, {} no longer existed",
+        keys.len() - touched
+    );
+    Ok(())
+}
+
+async fn copy_pattern(
+    client: &Client,
+    scan_options: &ScanOptions,
+    options: &CopyOptions,
+) -> color_eyre::Result<()> {
+    let keys = scan(client, scan_options).await?;
+    let mappings: Vec<_> = options
+        .rewrite
+        .mappings(&keys, scan_options.regex.as_ref())
+        .into_iter()
+        .filter(|(source, destination)| options.dest_db.is_some() || *source != destination)
+        .collect();
+    if options.dest_db.is_some() && client.is_clustered() {
+        return Err(eyre!(
+            "a cluster only has database 0, --dest-db can't be used"
+        ));
+    }
+    let destination = match options.dest_db {
+        Some(db) => format!("{} keys in database {db}", mappings.len()),
+        None => format!("{} keys", mappings.len()),
+    };
+    if options.batch.dry_run {
+        println!("{} keys matched, {} to copy", keys.len(), mappings.len());
+        println!("{destination} would be created, such as:");
+        print_mappings(&mappings);
+        return Ok(());
+    }
+    if !mappings.is_empty() && !options.batch.yes {
+        confirm_mappings(&scan_options.pattern, &mappings);
+        if !ask_confirmation(&format!("create {destination}"))? {
+            return Err(eyre!("Copy aborted"));
+        }
+    }
+    let copier = Copier::new(client, options).await?;
+    let message = format!("Copying keys from pattern {}", scan_options.pattern);
+    let pb = keys_progress(mappings.len(), message)?;
+    let mut pacing = Pacing::default();
+    let (mut copied, mut skipped) = (0, 0);
+    for chunk in mappings.chunks(options.batch.batch_size as usize) {
+        pacing.wait(&options.batch, chunk.len()).await;
+        let batch_copied = copier.copy_batch(chunk).await?;
+        copied += batch_copied;
+        skipped += chunk.len() - batch_copied;
+        pb.inc(chunk.len() as u64);
+    }
+    pb.finish();
+    eprintln!(
+        "Copied {copied} keys, {skipped} already existed at their destination or no longer existed"
+    );
+    Ok(())
+}
+
+/// Copies keys with COPY when possible, and with DUMP and RESTORE otherwise
+struct Copier<'a> {
+    client: &'a Client,
+    /// Client of the destination database, for RESTORE
+    destination: Client,
+    options: &'a CopyOptions,
+    copy_available: bool,
+}
+
+impl<'a> Copier<'a> {
+    async fn new(client: &'a Client, options: &'a CopyOptions) -> color_eyre::Result<Self> {
+        let copy_available = client
+            .server_version()
+            .is_some_and(|version| (version.major, version.minor) >= (6, 2));
+        let destination = match options.dest_db {
+            Some(db) if !copy_available => {
+                let mut config = client.client_config();
+                config.database = Some(db);
+                let destination = Client::new(config, None, None, None);
+                destination.init().await?;
+                destination
+            }
+            _ => client.clone(),
+        };
+        Ok(Self {
+            client,
+            destination,
+            options,
+            copy_available,
+        })
+    }
+
+    /// Whether COPY can be used, which requires both keys to be in the same slot of a cluster
+    fn uses_copy(&self, source: &Key, destination: &Key) -> bool {
+        self.copy_available
+            && (!self.client.is_clustered()
+                || fred::util::redis_keyslot(source.as_bytes())
+                    == fred::util::redis_keyslot(destination.as_bytes()))
+    }
+
+    /// Copy a batch of keys, returning the number of keys created
+    async fn copy_batch(&self, mappings: &[(&Key, Key)]) -> color_eyre::Result<usize> {
+        let (copies, restores): (Vec<_>, Vec<_>) = mappings
+            .iter()
+            .partition(|(source, destination)| self.uses_copy(source, destination));
+
+        let mut copied = 0;
+        if !copies.is_empty() {
+            let pipe = self.client.pipeline();
+            for (source, destination) in &copies {
+                let _: () = pipe
+                    .copy(
+                        *source,
+                        destination.clone(),
+                        self.options.dest_db,
+                        self.options.replace,
+                    )
+                    .await?;
+            }
+            let replies: Vec<i64> = pipe.all().await?;
+            copied += replies.iter().filter(|&&reply| reply == 1).count();
+        }
+
+        let sources: Vec<&Key> = restores.iter().map(|(source, _)| *source).collect();
+        let dumps = dump_keys(self.client, &sources).await?;
+        let restores = restores
+            .iter()
+            .zip(dumps)
+            .filter_map(|((_, destination), dump)| Some((destination, dump?)));
+        let created = restore_keys(&self.destination, restores, self.options.replace).await?;
+        copied += created.iter().filter(|&&created| created).count();
+        Ok(copied)
+    }
+}
+
+async fn rename_pattern(
+    client: &Client,
+    scan_options: &ScanOptions,
+    options: &RenameOptions,
+) -> color_eyre::Result<()> {
+    let keys = scan(client, scan_options).await?;
+    let mappings: Vec<_> = options
+        .rewrite
+        .mappings(&keys, scan_options.regex.as_ref())
+        .into_iter()
+        .filter(|(source, destination)| *source != destination)
+        .collect();
+    let mut destinations = HashSet::with_capacity(mappings.len());
+    if let Some((source, destination)) = mappings
+        .iter()
+        .find(|(_, destination)| !destinations.insert(destination.as_bytes()))
+    {
+        return Err(eyre!(
+            "several keys would be renamed to {}, such as {}",
+            destination.as_str_lossy(),
+            source.as_str_lossy()
+        ));
+    }
+    let destinations: Vec<&Key> = mappings
+        .iter()
+        .map(|(_, destination)| destination)
+        .collect();
+    let existing = existing_keys(client, &destinations, &options.batch).await?;
+    if options.batch.dry_run {
+        println!("{} keys matched, {} to rename", keys.len(), mappings.len());
+        if !existing.is_empty() {
+            let outcome = match options.on_conflict {
+                OnConflict::Skip => "would be skipped",
+                OnConflict::Overwrite => "would be overwritten",
+                OnConflict::Fail => "prevent the rename",
+            };
+            println!("{} new names already exist and {outcome}", existing.len());
+        }
+        print_mappings(&mappings);
+        return Ok(());
+    }
+    if options.on_conflict == OnConflict::Fail
+        && let Some(first) = existing.first()
+    {
+        return Err(eyre!(
+            "{} new names already exist, such as {} (pass --on-conflict skip or overwrite)",
+            existing.len(),
+            first.as_str_lossy()
+        ));
+    }
+    if !mappings.is_empty() && !options.batch.yes {
+        confirm_mappings(&scan_options.pattern, &mappings);
+        if !ask_confirmation(&format!("rename {} keys", mappings.len()))? {
+            return Err(eyre!("Rename aborted"));
+        }
+    }
+    let message = format!("Renaming keys from pattern {}", scan_options.pattern);
+    let pb = keys_progress(mappings.len(), message)?;
+    let mut pacing = Pacing::default();
+    let (mut renamed, mut skipped) = (0, 0);
+    for chunk in mappings.chunks(options.batch.batch_size as usize) {
+        pacing.wait(&options.batch, chunk.len()).await;
+        let batch_renamed = rename_batch(client, chunk, options.on_conflict).await?;
+        renamed += batch_renamed;
+        skipped += chunk.len() - batch_renamed;
+        pb.inc(chunk.len() as u64);
+    }
+    pb.finish();
+    eprintln!(
+        "Renamed {renamed} keys, {skipped} were skipped as their new name already existed or they no longer existed"
+    );
+    Ok(())
+}
+
+/// The keys that exist among `keys`
+async fn existing_keys<'a>(
+    client: &Client,
+    keys: &[&'a Key],
+    batch_options: &BatchOptions,
+) -> color_eyre::Result<Vec<&'a Key>> {
+    let mut existing = Vec::new();
+    for chunk in keys.chunks(batch_options.batch_size as usize) {
+        let pipe = client.pipeline();
+        for key in chunk {
+            let _: () = pipe.exists(*key).await?;
+        }
+        let replies: Vec<i64> = pipe.all().await?;
+        existing.extend(
+            chunk
+                .iter()
+                .zip(replies)
+                .filter(|(_, exists)| *exists > 0)
+                .map(|(key, _)| *key),
+        );
+    }
+    Ok(existing)
+}
+
+/// Rename a batch of keys, returning the number of keys renamed
+async fn rename_batch(
+    client: &Client,
+    mappings: &[(&Key, Key)],
+    on_conflict: OnConflict,
+) -> color_eyre::Result<usize> {
+    let (renames, moves): (Vec<_>, Vec<_>) = mappings.iter().partition(|(source, destination)| {
+        !client.is_clustered()
+            || fred::util::redis_keyslot(source.as_bytes())
+                == fred::util::redis_keyslot(destination.as_bytes())
+    });
+    let conflict = |destination: &Key| {
+        eyre!(
+            "{} was created during the rename, stopping",
+            destination.as_str_lossy()
+        )
+    };
+
+    let mut renamed = 0;
+    if !renames.is_empty() {
+        let pipe = client.pipeline();
+        for (source, destination) in &renames {
+            let _: () = if on_conflict == OnConflict::Overwrite {
+                pipe.rename(*source, destination.clone()).await?
+            } else {
+                pipe.renamenx(*source, destination.clone()).await?
+            };
+        }
+        for ((_, destination), reply) in renames.iter().zip(pipe.try_all::<Value>().await) {
+            match reply {
+                // RENAMENX replies 0 when the new name exists
+                Ok(Value::Integer(0)) if on_conflict == OnConflict::Fail => {
+                    return Err(conflict(destination));
+                }
+                Ok(Value::Integer(0)) => {}
+                Ok(_) => renamed += 1,
+                // The key no longer exists
+                Err(error) if error.details().contains("no such key") => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+
+    let sources: Vec<&Key> = moves.iter().map(|(source, _)| *source).collect();
+    let dumps = dump_keys(client, &sources).await?;
+    let moved: Vec<_> = moves
+        .iter()
+        .zip(dumps)
+        .filter_map(|((source, destination), dump)| Some((*source, destination, dump?)))
+        .collect();
+    let restores = moved
+        .iter()
+        .map(|(_, destination, dump)| (*destination, dump.clone()));
+    let created = restore_keys(client, restores, on_conflict == OnConflict::Overwrite).await?;
+    let pipe = client.pipeline();
+    let mut deleted = 0;
+    let mut conflicting = None;
+    for ((source, destination, _), created) in moved.iter().zip(created) {
+        if created {
+            let _: () = pipe.del(*source).await?;
+            deleted += 1;
+        } else {
+            conflicting.get_or_insert(*destination);
+        }
+    }
+    // Sources of the restored keys are deleted even when stopping, not to leave them duplicated
+    if deleted > 0 {
+        let _: Vec<Value> = pipe.all().await?;
+    }
+    match conflicting {
+        Some(destination) if on_conflict == OnConflict::Fail => Err(conflict(destination)),
+        _ => Ok(renamed + deleted),
+    }
+}
+
+/// TTL in milliseconds, `None` standing for no TTL, and DUMP payload of each key
+///
+/// `None` is returned for the keys that no longer exist.
+async fn dump_keys(
+    client: &Client,
+    keys: &[&Key],
+) -> color_eyre::Result<Vec<Option<(Option<i64>, Value)>>> {
+    if keys.is_empty() {
+        return Ok(Vec::new());
+    }
+    let pipe = client.pipeline();
+    for key in keys {
+        let _: () = pipe.pttl(*key).await?;
+        let _: () = pipe.dump(*key).await?;
+    }
+    let replies: Vec<Value> = pipe.all().await?;
+    Ok(replies
+        .chunks(2)
+        .map(|reply| {
+            // Less than a millisecond left reads as 0, which RESTORE would take as no TTL
+            let ttl = match Ttl::from_reply(reply[0].as_i64()?)? {
+                Ttl::Persistent => None,
+                Ttl::Remaining(ms) => Some(ms.max(1)),
+            };
+            match &reply[1] {
+                dumped @ Value::Bytes(_) => Some((ttl, dumped.clone())),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+/// RESTORE dumped keys under their new name, returning whether each one was created
+///
+/// Keys whose destination already exists are not created, unless `replace` is set.
+async fn restore_keys<'a>(
+    client: &Client,
+    dumps: impl Iterator<Item = (&'a Key, (Option<i64>, Value))>,
+    replace: bool,
+) -> color_eyre::Result<Vec<bool>> {
+    let pipe = client.pipeline();
+    let mut restored = 0;
+    for (destination, (ttl, dumped)) in dumps {
+        let _: () = pipe
+            .restore(
+                destination.clone(),
+                ttl.unwrap_or(0),
+                dumped,
+                replace,
+                false,
+                None,
+                None,
+            )
+            .await?;
+        restored += 1;
+    }
+    if restored == 0 {
+        return Ok(Vec::new());
+    }
+    let mut created = Vec::with_capacity(restored);
+    for reply in pipe.try_all::<Value>().await {
+        match reply {
+            Ok(_) => created.push(true),
+            Err(error) if error.details().starts_with("BUSYKEY") => created.push(false),
+            Err(error) => return Err(error.into()),
+        }
+    }
+    Ok(created)
+}
+
+/// Group of a key, made of the part following `prefix` up to the `depth`-th delimiter
+///
+/// Keys without a delimiter belong to "other", and `None` is returned for keys that don't start
+/// with `prefix`.
+fn key_group(
+    key: &Key,
+    prefix: &str,
+    delimiters: &[String],
+    depth: usize,
+    collapse: &[IdKind],
+) -> Option<String> {
+    let key = key.as_str_lossy();
+    let key = key.strip_prefix(prefix)?;
+    let collapsed = collapse_ids(key, delimiters, collapse);
+    let key: &str = &collapsed;
+    let mut delimiters = delimiter_matches(key, delimiters).peekable();
+    Some(if delimiters.peek().is_none() {
+        "other".to_owned()
+    } else if let Some((end, _)) = delimiters.nth(depth - 1) {
+        key[..end].to_owned()
+    } else {
+        key.to_owned()
+    })
+}
+
+/// Replace the segments matching any of the ID kinds with "*", keeping the delimiters
+fn collapse_ids<'a>(key: &'a str, delimiters: &[String], kinds: &[IdKind]) -> Cow<'a, str> {
+    if kinds.is_empty() {
+        return Cow::Borrowed(key);
+    }
+    let mut collapsed = String::with_capacity(key.len());
+    let mut start = 0;
+    let ends = delimiter_matches(key, delimiters).chain([(key.len(), key.len())]);
+    for (index, end) in ends {
+        let segment = &key[start..index];
+        if kinds.iter().any(|kind| kind.matches(segment)) {
+            collapsed.push('*');
+        } else {
+            collapsed.push_str(segment);
+        }
+        collapsed.push_str(&key[index..end]);
+        start = end;
+    }
+    Cow::Owned(collapsed)
+}
+
+/// Start and end of each occurrence of any of the delimiters, from left to right
+fn delimiter_matches<'a>(
+    key: &'a str,
+    delimiters: &'a [String],
+) -> impl Iterator<Item = (usize, usize)> + 'a {
+    let mut start = 0;
+    std::iter::from_fn(move || {
+        let (index, len) = delimiters
+            .iter()
+            .filter_map(|delimiter| {
+                let index = key[start..].find(delimiter.as_str())?;
+                Some((start + index, delimiter.len()))
+            })
+            .min()?;
+        start = index + len;
+        Some((index, start))
+    })
+}
+
+/// Segments of a group, between its delimiters
+fn group_segments<'a>(group: &'a str, delimiters: &'a [String]) -> Vec<&'a str> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for (index, end) in delimiter_matches(group, delimiters) {
+        segments.push(&group[start..index]);
+        start = end;
+    }
+    segments.push(&group[start..]);
+    segments
+}
+
+/// Count keys by group, see [`key_group`]
+///
+/// Also returns the number of keys that don't start with `prefix`.
+fn group_keys(
+    keys: &[Key],
+    prefix: &str,
+    delimiters: &[String],
+    depth: usize,
+) -> (Counter<String>, usize) {
+    let mut counter = Counter::<String>::new();
+    let mut other = 0;
+    for key in keys {
+        match key_group(key, prefix, delimiters, depth, &[]) {
+            Some(group) => counter[&group] += 1,
+            None => other += 1,
+        }
+    }
+    (counter, other)
+}
+
+/// Bytes used by the keys measured in a group
+#[derive(Debug, Default, Clone, Copy)]
+struct Measured {
+    keys: usize,
+    bytes: u64,
+}
+
+impl Measured {
+    fn add(&mut self, other: Measured) {
+        self.keys += other.keys;
+        self.bytes += other.bytes;
+    }
+
+    /// Bytes used by the `count` keys of the group, extrapolated from the measured keys
+    fn extrapolate(&self, count: usize) -> Option<u64> {
+        (self.keys > 0).then(|| (self.bytes as f64 / self.keys as f64 * count as f64) as u64)
+    }
+}
+
+/// Number of keys of a group, bytes used by its measured keys and their TTLs
+#[derive(Debug, Default, Clone)]
+struct GroupStats {
+    count: usize,
+    memory: Measured,
+    values: Measured,
+    persistent: usize,
+    /// Remaining TTLs in milliseconds
+    ttls: Vec<i64>,
+    types: BTreeMap<String, usize>,
+}
+
+impl GroupStats {
+    fn add(&mut self, other: &GroupStats) {
+        self.count += other.count;
+        self.memory.add(other.memory);
+        self.values.add(other.values);
+        self.persistent += other.persistent;
+        self.ttls.extend(&other.ttls);
+        for (key_type, count) in &other.types {
+            *self.types.entry(key_type.clone()).or_default() += count;
+        }
+    }
+
+    /// Key types with their counts, most common first
+    fn types_summary(&self) -> String {
+        let mut types: Vec<_> = self.types.iter().collect();
+        types.sort_by(|a, b| b.1.cmp(a.1));
+        let types: Vec<String> = types
+            .into_iter()
+            .map(|(key_type, count)| format!("{key_type}:{count}"))
+            .collect();
+        types.join(" ")
+    }
+
+    /// Minimum, median and maximum remaining TTL in milliseconds, and number of keys expiring
+    /// within a day
+    fn ttl_summary(&mut self) -> Option<(i64, i64, i64, usize)> {
+        if self.ttls.is_empty() {
+            return None;
+        }
+        self.ttls.sort_unstable();
+        let day = 24 * 60 * 60 * 1000;
+        Some((
+            self.ttls[0],
+            // Nearest rank median
+            self.ttls[self.ttls.len().div_ceil(2) - 1],
+            self.ttls[self.ttls.len() - 1],
+            self.ttls.partition_point(|&ttl| ttl < day),
+        ))
+    }
+
+    /// Fields of the structured outputs, `null` when unknown
+    fn fields(
+        &mut self,
+        options: &StatsOptions,
+        total: usize,
+        cumulative: Option<usize>,
+    ) -> Vec<(&'static str, serde_json::Value)> {
+        let share = |count: usize| (1000.0 * count as f64 / total.max(1) as f64).round() / 10.0;
+        let mut fields = vec![("count", self.count.into())];
+        if options.percent {
+            fields.push(("percent", share(self.count).into()));
+            fields.push(("cumulative_percent", cumulative.map(share).into()));
+        }
+        if options.memory {
+            let memory = self.memory();
+            fields.push(("memory_bytes", memory.into()));
+            let average = memory.map(|memory| memory / self.count as u64);
+            fields.push(("average_bytes", average.into()));
+        }
+        if options.value_sizes {
+            let values = self.values.extrapolate(self.count);
+            fields.push(("value_bytes", values.into()));
+            let average = values.map(|values| values / self.count as u64);
+            fields.push(("average_value_bytes", average.into()));
+        }
+        if options.ttl {
+            fields.push(("no_ttl", self.persistent.into()));
+            let summary = self.ttl_summary();
+            fields.push(("min_ttl_ms", summary.map(|s| s.0).into()));
+            fields.push(("median_ttl_ms", summary.map(|s| s.1).into()));
+            fields.push(("max_ttl_ms", summary.map(|s| s.2).into()));
+            fields.push(("expiring_24h", summary.map_or(0, |s| s.3).into()));
+        }
+        if options.type_counts {
+            fields.push(("types", serde_json::json!(self.types)));
+        }
+        fields
+    }
+
+    /// Memory used by the whole group, extrapolated from the measured keys
+    fn memory(&self) -> Option<u64> {
+        self.memory.extrapolate(self.count)
+    }
+}
+
+/// Groups folded into a single row of the compute-stats output
+struct FoldedGroups {
+    /// Field of the structured outputs
+    field: &'static str,
+    /// Table and CSV row name, parenthesized so it can't be mistaken for the "other" group of keys
+    /// without a delimiter
+    name: String,
+    groups: usize,
+    stats: GroupStats,
+}
+
+impl FoldedGroups {
+    fn new(
+        field: &'static str,
+        name: impl Fn(usize) -> String,
+        groups: &[(String, GroupStats)],
+    ) -> Option<Self> {
+        if groups.is_empty() {
+            return None;
+        }
+        let mut stats = GroupStats::default();
+        for (_, group) in groups {
+            stats.add(group);
+        }
+        Some(Self {
+            field,
+            name: name(groups.len()),
+            groups: groups.len(),
+            stats,
+        })
+    }
+}
+
+/// The cached slot map of a cluster client
+fn node_routing(client: &Client) -> color_eyre::Result<ClusterRouting> {
+    match client.cached_cluster_state() {
+        Some(routing) if client.is_clustered() => Ok(routing),
+        _ => Err(eyre!("node details require --cluster")),
+    }
+}
+
+/// Address of the primary owning the slot of a key
+fn key_node(routing: &ClusterRouting, key: &Key) -> String {
+    let slot = fred::util::redis_keyslot(key.as_bytes());
+    routing
+        .get_server(slot)
+        .map(|server| server.to_string())
+        .unwrap_or_default()
+}
+
+/// The primaries of a cluster, or `None` for the server of a standalone client
+fn primary_nodes(client: &Client) -> Vec<Option<Server>> {
+    match client.cached_cluster_state() {
+        Some(state) if client.is_clustered() => {
+            state.unique_primary_nodes().into_iter().map(Some).collect()
+        }
+        _ => vec![None],
+    }
+}
+
+/// Draw `size` random keys with RANDOMKEY, spread across the primary nodes of a cluster in
+/// proportion to their number of keys, keeping those matching the scan options
+///
+/// Also returns the number of keys that were drawn and the number of keys in the database.
+async fn sample_keys(
+    client: &Client,
+    options: &ScanOptions,
+    size: u64,
+) -> color_eyre::Result<(Vec<Key>, u64, u64)> {
+    let nodes = primary_nodes(client);
+    let mut node_sizes = Vec::with_capacity(nodes.len());
+    for node in &nodes {
+        let node_size: u64 = match node {
+            Some(server) => client.with_cluster_node(server.clone()).dbsize().await?,
+            None => client.dbsize().await?,
+        };
+        node_sizes.push(node_size);
+    }
+    let total: u64 = node_sizes.iter().sum();
+
+    let mut keys = Vec::new();
+    let mut drawn = 0;
+    for (node, node_size) in nodes.into_iter().zip(node_sizes) {
+        let mut left = (size as f64 * node_size as f64 / total.max(1) as f64).round() as u64;
+        let node_options = fred::types::config::Options {
+            cluster_node: node,
+            ..Default::default()
+        };
+        while left > 0 {
+            let batch = left.min(1000);
+            left -= batch;
+            let pipe = client.pipeline();
+            let node_pipe = pipe.with_options(&node_options);
+            for _ in 0..batch {
+                let _: () = node_pipe.randomkey().await?;
+            }
+            let replies: Vec<Option<Key>> = pipe.all().await?;
+            let page: Vec<Key> = replies.into_iter().flatten().collect();
+            drawn += page.len() as u64;
+            keys.extend(page.into_iter().filter(|key| {
+                glob_match(options.pattern.as_bytes(), key.as_bytes())
+                    && !is_excluded(&options.exclude, key)
+                    && options
+                        .regex
+                        .as_ref()
+                        .is_none_or(|re| re.is_match(key.as_bytes()))
+            }));
+        }
+    }
+    if !options.types.is_empty() {
+        keys = filter_by_type(client, &options.types, keys).await?;
+    }
+    if options.filters_ttl() {
+        keys = filter_by_ttl(client, options, keys).await?;
+    }
+    if options.filters_idle() {
+        keys = filter_by_idle(client, options, keys).await?;
+    }
+    Ok((keys, drawn, total))
+}
+
+/// Measure a random fraction of the keys, in bytes
+///
+/// The memory used by the keys is measured with MEMORY USAGE, while the values are measured with
+/// STRLEN for strings. Keys that disappeared since the scan are left out.
+async fn sample_sizes<'a>(
+    client: &Client,
+    keys: &[&'a Key],
+    rate: f64,
+    values: bool,
+) -> color_eyre::Result<Vec<(&'a Key, u64)>> {
+    use std::hash::BuildHasher;
+
+    let hasher = std::collections::hash_map::RandomState::new();
+    let sampled: Vec<&Key> = keys
+        .iter()
+        .copied()
+        .filter(|key| {
+            rate >= 1.0 || (hasher.hash_one(key.as_bytes()) as f64) < rate * u64::MAX as f64
+        })
+        .collect();
+    let mut sizes = Vec::with_capacity(sampled.len());
+    for chunk in sampled.chunks(1000) {
+        let strings: Vec<bool> = if values {
+            let pipe = client.pipeline();
+            for key in chunk {
+                let _: () = pipe.r#type(*key).await?;
+            }
+            let types: Vec<String> = pipe.all().await?;
+            types
+                .into_iter()
+                .map(|key_type| key_type == "string")
+                .collect()
+        } else {
+            vec![false; chunk.len()]
+        };
+        let pipe = client.pipeline();
+        for (key, is_string) in chunk.iter().zip(&strings) {
+            if *is_string {
+                let _: () = pipe.strlen(*key).await?;
+            } else {
+                let _: () = pipe.memory_usage(*key, None).await?;
+            }
+        }
+        let result: Vec<Option<u64>> = pipe.all().await?;
+        sizes.extend(
+            chunk
+                .iter()
+                .zip(result)
+                .filter_map(|(key, size)| Some((*key, size?))),
+        );
+    }
+    Ok(sizes)
+}
+
+async fn compute_stats(
+    client: &Client,
+    scan_options: &ScanOptions,
+    options: &StatsOptions,
+) -> color_eyre::Result<()> {
+    if options.tree {
+        return print_stats_tree(client, scan_options, options).await;
+    }
+    let routing = options.by_node.then(|| node_routing(client)).transpose()?;
+    let gather = || gather_stats(client, scan_options, options, routing.as_ref());
+    let write = |stats: &StatsSnapshot, previous: Option<&StatsSnapshot>| {
+        write_stats(options, stats, previous)
+    };
+    match options.watch {
+        Some(interval) => watch_runs(interval, gather, write).await,
+        None => write(&gather().await?, None),
+    }
+}
+
+/// Stats of the groups of each node, the node being empty unless --by-node is given
+#[derive(Debug)]
+struct StatsSnapshot {
+    /// Groups and number of keys not matching the prefix of each node
+    nodes: BTreeMap<String, (HashMap<String, GroupStats>, usize)>,
+    by_node: bool,
+    sample_size: Option<u64>,
+}
+
+impl StatsSnapshot {
+    /// Number of keys of each group of a node, named as in the table, and their total
+    fn counts(&self, node: &str, prefix: &str) -> StatsCounts {
+        let groups = self.nodes.get(node).map(|(groups, _)| groups);
+        let groups: HashMap<String, usize> = groups
+            .into_iter()
+            .flatten()
+            .map(|(group, stats)| (format!("{prefix}{group}"), stats.count))
+            .collect();
+        StatsCounts {
+            total: groups.values().sum(),
+            groups,
+        }
+    }
+}
+
+/// Counts of a previous compute-stats run, to show the change of each group with --watch
+#[derive(Debug, Default)]
+struct StatsCounts {
+    groups: HashMap<String, usize>,
+    total: usize,
+}
+
+impl StatsCounts {
+    fn delta(&self, name: &str, count: usize) -> String {
+        count_delta(count, self.groups.get(name).copied().unwrap_or_default())
+    }
+}
+
+async fn gather_stats(
+    client: &Client,
+    scan_options: &ScanOptions,
+    options: &StatsOptions,
+    routing: Option<&ClusterRouting>,
+) -> color_eyre::Result<StatsSnapshot> {
+    let node_of = |key: &Key| match routing {
+        Some(routing) => key_node(routing, key),
+        None => String::new(),
+    };
+    // Stats are keyed by node and group, the node being empty unless --by-node is given
+    let mut groups = HashMap::<(String, String), GroupStats>::new();
+    let mut other = HashMap::<String, usize>::new();
+    let sample = match options.sample {
+        Some(size) => Some(sample_keys(client, scan_options, size).await?),
+        None => None,
+    };
+    let mut sampled_pages = sample.as_ref().map(|(keys, _, _)| keys.chunks(1000));
+    let mut scanner = match sample {
+        Some(_) => None,
+        None => Some(KeyScanner::new(client, scan_options)?),
+    };
+    let pb = stats_progress()?;
+    let mut pages = 0;
+    // Stats are gathered page by page, so that the keyspace is only scanned once
+    loop {
+        let keys = match (&mut scanner, &mut sampled_pages) {
+            (Some(scanner), _) => scanner.next_page().await?,
+            (None, Some(sampled)) => sampled.next().map(<[Key]>::to_vec),
+            (None, None) => None,
+        };
+        let Some(keys) = keys else {
+            break;
+        };
+        pages += 1;
+        pb.inc(keys.len() as u64);
+        pb.set_message(format!("{pages} pages, {} groups", groups.len()));
+        let mut matching = Vec::with_capacity(keys.len());
+        for key in &keys {
+            match options.group(key) {
+                Some(group) => {
+                    groups.entry((node_of(key), group)).or_default().count += 1;
+                    matching.push(key);
+                }
+                None => *other.entry(node_of(key)).or_default() += 1,
+            }
+        }
+        if options.type_counts {
+            for (key, key_type) in keys.iter().zip(key_types(client, &keys).await?) {
+                if let Some(group) = options.group(key)
+                    && key_type != "none"
+                {
+                    let stats = groups.entry((node_of(key), group)).or_default();
+                    *stats.types.entry(key_type).or_default() += 1;
+                }
+            }
+        }
+        if options.ttl && !matching.is_empty() {
+            let pipe = client.pipeline();
+            for key in &matching {
+                let _: () = pipe.pttl(*key).await?;
+            }
+            let pttls: Vec<i64> = pipe.all().await?;
+            for (key, pttl) in matching.iter().zip(pttls) {
+                let stats = groups
+                    .entry((node_of(key), options.group(key).unwrap_or_default()))
+                    .or_default();
+                match Ttl::from_reply(pttl) {
+                    Some(Ttl::Persistent) => stats.persistent += 1,
+                    Some(Ttl::Remaining(remaining)) => stats.ttls.push(remaining),
+                    None => {}
+                }
+            }
+        }
+        if options.memory {
+            for (key, size) in sample_sizes(client, &matching, options.sample_rate, false).await? {
+                let stats = groups
+                    .entry((node_of(key), options.group(key).unwrap_or_default()))
+                    .or_default();
+                stats.memory.add(Measured {
+                    keys: 1,
+                    bytes: size,
+                });
+            }
+        }
+        if options.value_sizes {
+            for (key, size) in sample_sizes(client, &matching, options.sample_rate, true).await? {
+                let stats = groups
+                    .entry((node_of(key), options.group(key).unwrap_or_default()))
+                    .or_default();
+                stats.values.add(Measured {
+                    keys: 1,
+                    bytes: size,
+                });
+            }
+        }
+    }
+
+    pb.finish_and_clear();
+    if let Some((_, drawn, total_keys)) = &sample {
+        let factor = *total_keys as f64 / (*drawn).max(1) as f64;
+        let estimate = |count: usize| (count as f64 * factor).round() as usize;
+        for stats in groups.values_mut() {
+            stats.count = estimate(stats.count);
+        }
+        for other in other.values_mut() {
+            *other = estimate(*other);
+        }
+        eprintln!("Counts estimated from {drawn} keys sampled out of {total_keys}");
+    }
+
+    let mut nodes = BTreeMap::<String, (HashMap<String, GroupStats>, usize)>::new();
+    if routing.is_none() {
+        // The table is printed even when no key matched
+        nodes.insert(String::new(), Default::default());
+    }
+    for ((node, group), stats) in groups {
+        nodes.entry(node).or_default().0.insert(group, stats);
+    }
+    for (node, other) in other {
+        nodes.entry(node).or_default().1 = other;
+    }
+    Ok(StatsSnapshot {
+        nodes,
+        by_node: routing.is_some(),
+        sample_size: sample.as_ref().map(|(_, drawn, _)| *drawn),
+    })
+}
+
+/// Write the stats report of each node, with the change since `previous` in watch mode
+fn write_stats(
+    options: &StatsOptions,
+    stats: &StatsSnapshot,
+    previous: Option<&StatsSnapshot>,
+) -> color_eyre::Result<()> {
+    let prefix = options.prefix.as_deref().unwrap_or_default();
+    let mut out = RecordSink::new(std::io::stdout().lock(), options.output);
+    let by_node_array = stats.by_node && options.output == OutputFormat::Json;
+    let empty = stats.nodes.is_empty();
+    for (index, (node, (groups, other))) in stats.nodes.iter().enumerate() {
+        if by_node_array {
+            write!(out.text(), "{}", if index == 0 { "[" } else { "," })?;
+        }
+        let previous = previous.map(|previous| previous.counts(node, prefix));
+        let report = StatsReport {
+            options,
+            node: stats.by_node.then_some(node.as_str()),
+            other: *other,
+            sample_size: stats.sample_size,
+            previous: previous.as_ref(),
+        };
+        report.write(groups.clone(), index, &mut out)?;
+    }
+    if by_node_array {
+        writeln!(out.text(), "{}", if empty { "[]" } else { "]" })?;
+    }
+    let _ = out.finish(OutputFormat::Plain, 0)?;
+    Ok(())
+}
+
+/// The stats of the groups of keys, or of those of a node with --by-node
+struct StatsReport<'a> {
+    options: &'a StatsOptions,
+    node: Option<&'a str>,
+    /// Keys not matching the prefix
+    other: usize,
+    sample_size: Option<u64>,
+    /// Counts of the previous run with --watch
+    previous: Option<&'a StatsCounts>,
+}
+
+impl StatsReport<'_> {
+    /// Write the report, `index` being the position of the node
+    fn write(
+        &self,
+        groups: HashMap<String, GroupStats>,
+        index: usize,
+        out: &mut RecordSink<std::io::StdoutLock<'_>>,
+    ) -> color_eyre::Result<()> {
+        let options = self.options;
+        let prefix = options.prefix.as_deref().unwrap_or_default();
+        let other = self.other;
+        let mut groups: Vec<(String, GroupStats)> = groups.into_iter().collect();
+        groups.sort_by(|(a_name, a), (b_name, b)| {
+            let order = match options.sort_by {
+                StatsOrder::Count => b.count.cmp(&a.count),
+                StatsOrder::Name => std::cmp::Ordering::Equal,
+                StatsOrder::Memory => b.memory().cmp(&a.memory()),
+            };
+            let order = if options.ascending {
+                order.reverse()
+            } else {
+                order
+            };
+            order.then_with(|| a_name.cmp(b_name))
+        });
+        let mut total = GroupStats::default();
+        for (_, stats) in &groups {
+            total.add(stats);
+        }
+        let (mut groups, below): (Vec<_>, Vec<_>) = groups
+            .into_iter()
+            .partition(|(_, stats)| options.min_count.is_none_or(|min| stats.count >= min));
+        let shown = options.top.unwrap_or(groups.len()).min(groups.len());
+        let mut folded: Vec<FoldedGroups> = [
+            FoldedGroups::new(
+                "other_groups",
+                |groups| format!("(other {groups} groups)"),
+                &groups[shown..],
+            ),
+            FoldedGroups::new(
+                "below_threshold",
+                |groups| format!("(below threshold, {groups} groups)"),
+                &below,
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        groups.truncate(shown);
+        let rows = groups
+            .into_iter()
+            .map(|(group, stats)| (format!("{prefix}{group}"), stats));
+        let total_count = total.count;
+        let mut cumulative = 0;
+        let mut cumulative = |count: usize| {
+            cumulative += count;
+            Some(cumulative)
+        };
+
+        if options.output == OutputFormat::Plain {
+            if let Some(node) = self.node {
+                println!("Node {node}:");
+            }
+            print_stats_table(options, rows, folded, total, other, prefix, self.previous);
+            return Ok(());
+        }
+        let node_field = self.node.map(|node| ("node", node.into()));
+        match options.output {
+            OutputFormat::Json | OutputFormat::Jsonl => {
+                let record = |name: Option<(&str, serde_json::Value)>, fields: Vec<_>| {
+                    serde_json::Value::Object(
+                        name.into_iter()
+                            .chain(fields)
+                            .map(|(field, value)| (field.to_owned(), value))
+                            .collect(),
+                    )
+                };
+                let mut records = Vec::new();
+                for (name, mut stats) in rows {
+                    let cumulative = cumulative(stats.count);
+                    let mut fields = stats.fields(options, total_count, cumulative);
+                    fields.extend(node_field.clone());
+                    records.push(record(Some(("group", name.into())), fields));
+                }
+                let mut summary = serde_json::Map::new();
+                if let Some((field, node)) = node_field {
+                    summary.insert(field.to_owned(), node);
+                }
+                for field in ["other_groups", "below_threshold"] {
+                    summary.insert(field.to_owned(), serde_json::Value::Null);
+                }
+                for folded in &mut folded {
+                    let cumulative = cumulative(folded.stats.count);
+                    let mut fields =
+                        vec![("aggregate", true.into()), ("groups", folded.groups.into())];
+                    fields.extend(folded.stats.fields(options, total_count, cumulative));
+                    summary.insert(folded.field.to_owned(), record(None, fields));
+                }
+                let total = record(None, total.fields(options, total_count, None));
+                summary.insert("total".to_owned(), total);
+                summary.insert("not_matching_prefix".to_owned(), other.into());
+                summary.insert("sample_size".to_owned(), self.sample_size.into());
+                let out = out.text();
+                if options.output == OutputFormat::Json {
+                    summary.insert("groups".to_owned(), records.into());
+                } else {
+                    for record in records {
+                        writeln!(out, "{record}")?;
+                    }
+                }
+                writeln!(out, "{}", serde_json::Value::Object(summary))?;
+            }
+            _ => {
+                let cell = |value: serde_json::Value| match value {
+                    serde_json::Value::Null => String::new(),
+                    serde_json::Value::String(value) => value,
+                    serde_json::Value::Object(types) => {
+                        let types: Vec<String> = types
+                            .iter()
+                            .map(|(key_type, count)| format!("{key_type}:{count}"))
+                            .collect();
+                        types.join(" ")
+                    }
+                    value => value.to_string(),
+                };
+                let node = self.node.map(str::to_owned);
+                if index == 0 {
+                    let fields = GroupStats::default().fields(options, 0, None);
+                    let header = fields.into_iter().map(|(field, _)| field);
+                    let node_header = node.as_ref().map(|_| "node");
+                    out.write_csv(
+                        node_header
+                            .into_iter()
+                            .chain(["group", "aggregate"])
+                            .chain(header),
+                    )?;
+                }
+                let write_row =
+                    |out: &mut RecordSink<_>,
+                     name: String,
+                     aggregate: bool,
+                     fields: Vec<(&str, serde_json::Value)>| {
+                        let values = fields.into_iter().map(|(_, value)| cell(value));
+                        out.write_csv(
+                            node.clone()
+                                .into_iter()
+                                .chain([name, aggregate.to_string()])
+                                .chain(values),
+                        )
+                    };
+                for (name, mut stats) in rows {
+                    let cumulative = cumulative(stats.count);
+                    let fields = stats.fields(options, total_count, cumulative);
+                    write_row(out, name, false, fields)?;
+                }
+                for mut folded in folded {
+                    let cumulative = cumulative(folded.stats.count);
+                    let fields = folded.stats.fields(options, total_count, cumulative);
+                    write_row(out, folded.name, true, fields)?;
+                }
+                let fields = total.fields(options, total_count, None);
+                write_row(out, "(total)".to_owned(), true, fields)?;
+                let not_matching = ["(not matching prefix)", "true", &other.to_string()];
+                out.write_csv(node.iter().map(String::as_str).chain(not_matching))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Print the stats of each group as a table, followed by the number of keys not matching the prefix
+fn print_stats_table(
+    options: &StatsOptions,
+    rows: impl Iterator<Item = (String, GroupStats)>,
+    folded: Vec<FoldedGroups>,
+    mut total: GroupStats,
+    other: usize,
+    prefix: &str,
+    previous: Option<&StatsCounts>,
+) {
+    let total_count = total.count.max(1);
+    let percent = |count: usize| format!("{:.1}%", 100.0 * count as f64 / total_count as f64);
+
+    let mut header = vec!["prefix", "count"];
+    if previous.is_some() {
+        header.push("delta");
+    }
+    if options.percent {
+        header.extend(["%", "cumulative %"]);
+    }
+    if options.memory {
+        header.extend(["memory", "average"]);
+    }
+    if options.value_sizes {
+        header.extend(["value size", "average value"]);
+    }
+    if options.ttl {
+        header.extend([
+            "no ttl",
+            "min ttl",
+            "median ttl",
+            "max ttl",
+            "expiring in 24h",
+        ]);
+    }
+    if options.type_counts {
+        header.push("types");
+    }
+    let mut b = tabled::builder::Builder::new();
+    b.push_record(header);
+    // Folded groups change as groups come and go, so only the groups and total have a delta
+    let record =
+        |name: String, stats: &mut GroupStats, cumulative: Option<usize>, delta: Option<String>| {
+            let mut record = vec![name, stats.count.to_string()];
+            if previous.is_some() {
+                record.push(delta.unwrap_or_default());
+            }
+            if options.percent {
+                record.extend([
+                    percent(stats.count),
+                    cumulative.map(percent).unwrap_or_default(),
+                ]);
+            }
+            if options.memory {
+                record.extend(match stats.memory() {
+                    Some(memory) => [
+                        HumanBytes(memory).to_string(),
+                        HumanBytes(memory / stats.count as u64).to_string(),
+                    ],
+                    None => ["-".to_owned(), "-".to_owned()],
+                });
+            }
+            if options.value_sizes {
+                record.extend(match stats.values.extrapolate(stats.count) {
+                    Some(values) => [
+                        HumanBytes(values).to_string(),
+                        HumanBytes(values / stats.count as u64).to_string(),
+                    ],
+                    None => ["-".to_owned(), "-".to_owned()],
+                });
+            }
+            if options.ttl {
+                let ttl = |ms: i64| {
+                    humantime::format_duration(Duration::from_secs(ms as u64 / 1000)).to_string()
+                };
+                record.push(stats.persistent.to_string());
+                record.extend(match stats.ttl_summary() {
+                    Some((min, median, max, expiring)) => {
+                        [ttl(min), ttl(median), ttl(max), expiring.to_string()]
+                    }
+                    None => ["-", "-", "-", "0"].map(str::to_owned),
+                });
+            }
+            if options.type_counts {
+                record.push(stats.types_summary());
+            }
+            record
+        };
+    let mut cumulative = 0;
+    for (name, mut stats) in rows {
+        cumulative += stats.count;
+        let delta = previous.map(|previous| previous.delta(&name, stats.count));
+        b.push_record(record(name, &mut stats, Some(cumulative), delta));
+    }
+    for mut folded in folded {
+        cumulative += folded.stats.count;
+        b.push_record(record(
+            folded.name,
+            &mut folded.stats,
+            Some(cumulative),
+            None,
+        ));
+    }
+    if options.top.is_some() || options.min_count.is_some() || options.percent || previous.is_some()
+    {
+        let delta = previous.map(|previous| count_delta(total.count, previous.total));
+        b.push_record(record("(total)".to_owned(), &mut total, None, delta));
+    }
+    let mut table = b.build();
+    table.with(tabled::settings::Style::psql());
+    println!("{table}");
+    if other > 0 {
+        println!("Keys not matching prefix \"{prefix}\": {other}");
+    }
+}
+
+/// Spinner showing the progress of compute-stats on stderr
+fn stats_progress() -> color_eyre::Result<ProgressBar> {
+    Ok(
+        ProgressBar::new_spinner().with_style(ProgressStyle::with_template(
+            "[{elapsed_precise}] {spinner} {pos} keys, {msg}",
+        )?),
+    )
+}
+
+/// Node of the prefix trie of compute-stats --tree
+#[derive(Debug, Default)]
+struct PrefixNode {
+    count: usize,
+    children: HashMap<Rc<str>, PrefixNode>,
+}
+
+impl PrefixNode {
+    /// Count a key under each of its segments, sharing the segment strings through `segments`
+    fn insert<'a>(&mut self, path: impl Iterator<Item = &'a str>, segments: &mut HashSet<Rc<str>>) {
+        self.count += 1;
+        let mut node = self;
+        for segment in path {
+            let segment = match segments.get(segment) {
+                Some(segment) => segment.clone(),
+                None => {
+                    let segment = Rc::<str>::from(segment);
+                    segments.insert(segment.clone());
+                    segment
+                }
+            };
+            node = node.children.entry(segment).or_default();
+            node.count += 1;
+        }
+    }
+
+    /// Render the children with box-drawing characters, skipping those below `min_count`
+    fn render(
+        &self,
+        options: &StatsOptions,
+        indent: &str,
+        label_prefix: &str,
+        out: &mut impl Write,
+    ) -> std::io::Result<()> {
+        let mut children: Vec<_> = self
+            .children
+            .iter()
+            .filter(|(_, node)| options.min_count.is_none_or(|min| node.count >= min))
+            .collect();
+        children.sort_by(|(a_name, a), (b_name, b)| {
+            let order = match options.sort_by {
+                StatsOrder::Name => std::cmp::Ordering::Equal,
+                // Sorting by memory requires --memory, which conflicts with --tree
+                StatsOrder::Count | StatsOrder::Memory => b.count.cmp(&a.count),
+            };
+            let order = if options.ascending {
+                order.reverse()
+            } else {
+                order
+            };
+            order.then_with(|| a_name.cmp(b_name))
+        });
+        let last = children.len().saturating_sub(1);
+        for (index, (segment, node)) in children.into_iter().enumerate() {
+            let (branch, continuation) = if index == last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            writeln!(
+                out,
+                "{indent}{branch}{label_prefix}{segment} ({})",
+                node.count
+            )?;
+            node.render(options, &format!("{indent}{continuation}"), "", out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Print the groups as a tree, each level adding a segment to the prefix of its parent
+async fn print_stats_tree(
+    client: &Client,
+    scan_options: &ScanOptions,
+    options: &StatsOptions,
+) -> color_eyre::Result<()> {
+    let prefix = options.prefix.as_deref().unwrap_or_default();
+    let mut root = PrefixNode::default();
+    let mut segments = HashSet::new();
+    let mut other = 0;
+    let pb = stats_progress()?;
+    let mut pages = 0;
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    while let Some(keys) = scanner.next_page().await? {
+        for key in &keys {
+            match options.group(key) {
+                Some(group) => {
+                    let path = group_segments(&group, &options.delimiter);
+                    root.insert(path.into_iter(), &mut segments);
+                }
+                None => other += 1,
+            }
+        }
+        pages += 1;
+        pb.inc(keys.len() as u64);
+        pb.set_message(format!("{pages} pages"));
+    }
+    pb.finish_and_clear();
+    let mut out = std::io::stdout().lock();
+    writeln!(out, "{prefix}* ({})", root.count)?;
+    root.render(options, "", prefix, &mut out)?;
+    if other > 0 {
+        writeln!(out, "Keys not matching prefix \"{prefix}\": {other}")?;
+    }
+    Ok(())
+}
+
+async fn find_largest_keys(
+    client: &Client,
+    scan_options: &ScanOptions,
+    top_n: usize,
+) -> color_eyre::Result<()> {
+    let keys = scan(client, scan_options).await?;
+    let mut counter = Counter::<Key>::new();
+
+    for chunk in keys.chunks(1000) {
+        let pipe = client.pipeline();
+        for key in chunk {
+            let _: () = pipe.memory_usage(key, None).await?;
+        }
+        let result: Vec<usize> = pipe.all().await?;
+        for (key, size) in chunk.iter().zip(result) {
+            counter[key] = size;
+        }
+        let cutoff = counter
+            .k_most_common_ordered(top_n)
+            .last()
+            .map(|(_, size)| *size)
+            .unwrap_or_default();
+        counter.retain(|_, size| *size >= cutoff);
+    }
+
+    let mut b = tabled::builder::Builder::with_capacity(counter.len() + 1, 2);
+    b.push_record(["key", "size"]);
+    for (key, size) in counter.k_most_common_ordered(top_n) {
+        b.push_record([key.as_str_lossy().into_owned(), size.to_string()]);
+    }
+    let mut table = b.build();
+    table.with(tabled::settings::Style::psql());
+    println!("{table}");
+    Ok(())
+}
+
+/// Upper bounds of the TTL report buckets, followed by the keys expiring in a month or more
+const TTL_BUCKETS: [(&str, Duration); 5] = [
+    ("<1m", Duration::from_secs(60)),
+    ("<1h", Duration::from_secs(60 * 60)),
+    ("<1d", Duration::from_secs(24 * 60 * 60)),
+    ("<7d", Duration::from_secs(7 * 24 * 60 * 60)),
+    ("<30d", Duration::from_secs(30 * 24 * 60 * 60)),
+];
+
+async fn ttl_report(
+    client: &Client,
+    scan_options: &ScanOptions,
+    output: OutputFormat,
+) -> color_eyre::Result<()> {
+    let now = std::time::SystemTime::now();
+    let mut persistent = 0;
+    // Keys expiring within each bucket, the last one being for a month or more
+    let mut counts = [0; TTL_BUCKETS.len() + 1];
+    let mut remaining = None::<(i64, i64)>;
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    while let Some(keys) = scanner.next_page().await? {
+        let pipe = client.pipeline();
+        for key in &keys {
+            let _: () = pipe.pttl(key).await?;
+        }
+        let pttls: Vec<i64> = pipe.all().await?;
+        for pttl in pttls {
+            match Ttl::from_reply(pttl) {
+                Some(Ttl::Persistent) => persistent += 1,
+                Some(Ttl::Remaining(ms)) => {
+                    let ttl = Duration::from_millis(ms as u64);
+                    let bucket = TTL_BUCKETS
+                        .iter()
+                        .position(|(_, bound)| ttl < *bound)
+                        .unwrap_or(TTL_BUCKETS.len());
+                    counts[bucket] += 1;
+                    remaining = Some(match remaining {
+                        Some((min, max)) => (min.min(ms), max.max(ms)),
+                        None => (ms, ms),
+                    });
+                }
+                None => {}
+            }
+        }
+    }
+
+    let mut buckets = vec![("no TTL", persistent)];
+    let names = TTL_BUCKETS.iter().map(|(name, _)| *name).chain([">=30d"]);
+    buckets.extend(names.zip(counts));
+    let total: usize = buckets.iter().map(|(_, count)| count).sum();
+    let expiry = |ms: i64| {
+        humantime::format_rfc3339_seconds(now + Duration::from_millis(ms as u64)).to_string()
+    };
+    let earliest = remaining.map(|(min, _)| expiry(min));
+    let latest = remaining.map(|(_, max)| expiry(max));
+
+    let mut out = RecordSink::new(std::io::stdout().lock(), output);
+    match output {
+        OutputFormat::Plain => {
+            let mut b = tabled::builder::Builder::with_capacity(buckets.len() + 2, 2);
+            b.push_record(["ttl", "count"]);
+            for (bucket, count) in &buckets {
+                b.push_record([bucket.to_string(), count.to_string()]);
+            }
+            b.push_record(["(total)".to_owned(), total.to_string()]);
+            let mut table = b.build();
+            table.with(tabled::settings::Style::psql());
+            let out = out.text();
+            writeln!(out, "{table}")?;
+            if let (Some(earliest), Some(latest)) = (earliest, latest) {
+                writeln!(out, "Earliest expiry: {earliest}")?;
+                writeln!(out, "Latest expiry: {latest}")?;
+            }
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            let records = buckets
+                .iter()
+                .map(|(bucket, count)| serde_json::json!({"bucket": bucket, "count": count}));
+            let mut summary = serde_json::json!({
+                "total": total,
+                "earliest_expiry": earliest,
+                "latest_expiry": latest,
+            });
+            let out = out.text();
+            if output == OutputFormat::Json {
+                summary["buckets"] = records.collect();
+            } else {
+                for record in records {
+                    writeln!(out, "{record}")?;
+                }
+            }
+            writeln!(out, "{summary}")?;
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            out.write_csv(["bucket", "count"])?;
+            for (bucket, count) in &buckets {
+                out.write_csv([bucket.to_string(), count.to_string()])?;
+            }
+            out.write_csv(["(total)".to_owned(), total.to_string()])?;
+        }
+    }
+    let _ = out.finish(OutputFormat::Plain, 0)?;
+    Ok(())
+}
+
+/// A key ranked by a score, ties being broken by name
+#[derive(Debug)]
+struct RankedKey<T> {
+    score: u64,
+    key: Key,
+    details: T,
+}
+
+impl<T> PartialEq for RankedKey<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl<T> Eq for RankedKey<T> {}
+
+impl<T> Ord for RankedKey<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.score
+            .cmp(&other.score)
+            .then_with(|| other.key.as_bytes().cmp(self.key.as_bytes()))
+    }
+}
+
+impl<T> PartialOrd for RankedKey<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The keys with the highest scores seen so far, only ever holding `top` of them
+struct TopKeys<T> {
+    top: usize,
+    // Lowest of the highest scores first, to be evicted by higher ones
+    heap: std::collections::BinaryHeap<std::cmp::Reverse<RankedKey<T>>>,
+}
+
+impl<T> TopKeys<T> {
+    fn new(top: usize) -> Self {
+        Self {
+            top,
+            heap: std::collections::BinaryHeap::with_capacity(top + 1),
+        }
+    }
+
+    fn push(&mut self, score: u64, key: Key, details: T) {
+        self.heap.push(std::cmp::Reverse(RankedKey {
+            score,
+            key,
+            details,
+        }));
+        if self.heap.len() > self.top {
+            self.heap.pop();
+        }
+    }
+
+    /// The ranked keys, highest score first
+    fn into_sorted_vec(self) -> Vec<RankedKey<T>> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|std::cmp::Reverse(ranked)| ranked)
+            .collect()
+    }
+}
+
+/// Totals of the keys of a type measured by bigkeys
+#[derive(Debug, Default)]
+struct TypeTotals {
+    keys: usize,
+    bytes: u64,
+    elements: u64,
+}
+
+async fn bigkeys(
+    client: &Client,
+    scan_options: &ScanOptions,
+    top: usize,
+    by_node: bool,
+) -> color_eyre::Result<()> {
+    let routing = by_node.then(|| node_routing(client)).transpose()?;
+    let mut biggest = TopKeys::new(top);
+    let mut totals = BTreeMap::<String, TypeTotals>::new();
+    let pb = stats_progress()?;
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    while let Some(keys) = scanner.next_page().await? {
+        pb.inc(keys.len() as u64);
+        let key_types = key_types(client, &keys).await?;
+        let pipe = client.pipeline();
+        let mut measured = Vec::with_capacity(keys.len());
+        for (key, key_type) in keys.into_iter().zip(key_types) {
+            // Deleted since it was scanned
+            if key_type == "none" {
+                continue;
+            }
+            pipe.memory_usage::<(), _>(&key, None).await?;
+            let has_len = match KeyType::from_reply(&key_type) {
+                Some(parsed) => parsed.queue_len(&pipe, &key).await?,
+                None => false,
+            };
+            measured.push((key, key_type, has_len));
+        }
+        if measured.is_empty() {
+            continue;
+        }
+        let mut replies = pipe.try_all::<Option<u64>>().await.into_iter();
+        for (key, key_type, has_len) in measured {
+            let bytes = replies.next().and_then(Result::ok).flatten();
+            let elements = if has_len {
+                replies.next().and_then(Result::ok).flatten()
+            } else {
+                None
+            };
+            let Some(bytes) = bytes else {
+                continue;
+            };
+            let type_totals = totals.entry(key_type.clone()).or_default();
+            type_totals.keys += 1;
+            type_totals.bytes += bytes;
+            type_totals.elements += elements.unwrap_or_default();
+            biggest.push(bytes, key, (key_type, elements));
+        }
+        pb.set_message(format!("{} types", totals.len()));
+    }
+    pb.finish_and_clear();
+
+    let biggest = biggest.into_sorted_vec();
+    let mut b = tabled::builder::Builder::with_capacity(biggest.len() + 1, 5);
+    let mut header = vec!["key", "type", "bytes", "elements"];
+    if routing.is_some() {
+        header.push("node");
+    }
+    b.push_record(header);
+    for big_key in biggest {
+        let (key_type, elements) = big_key.details;
+        let mut record = vec![
+            big_key.key.as_str_lossy().into_owned(),
+            key_type,
+            big_key.score.to_string(),
+            elements
+                .map(|elements| elements.to_string())
+                .unwrap_or_default(),
+        ];
+        if let Some(routing) = &routing {
+            record.push(key_node(routing, &big_key.key));
+        }
+        b.push_record(record);
+    }
+    let mut table = b.build();
+    table.with(tabled::settings::Style::psql());
+    println!("{table}");
+
+    let mut b = tabled::builder::Builder::with_capacity(totals.len() + 1, 5);
+    b.push_record(["type", "keys", "bytes", "average", "elements"]);
+    for (key_type, totals) in &totals {
+        b.push_record([
+            key_type.clone(),
+            totals.keys.to_string(),
+            HumanBytes(totals.bytes).to_string(),
+            HumanBytes(totals.bytes / totals.keys as u64).to_string(),
+            totals.elements.to_string(),
+        ]);
+    }
+    let mut table = b.build();
+    table.with(tabled::settings::Style::psql());
+    println!("{table}");
+    Ok(())
+}
+
+async fn hotkeys(
+    client: &Client,
+    scan_options: &ScanOptions,
+    top: usize,
+    output: OutputFormat,
+) -> color_eyre::Result<()> {
+    if lfu_policy(client).await?.is_none() {
+        let policy = maxmemory_policy(client).await?.unwrap_or_default();
+        return Err(eyre!(
+            "maxmemory-policy is {policy}, access frequencies are only tracked with allkeys-lfu or volatile-lfu"
+        ));
+    }
+
+    let mut hottest = TopKeys::new(top);
+    let pb = stats_progress()?;
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    while let Some(keys) = scanner.next_page().await? {
+        pb.inc(keys.len() as u64);
+        let pipe = client.pipeline();
+        for key in &keys {
+            let _: () = pipe
+                .custom(
+                    object_command(),
+                    vec![Value::from("FREQ"), key.clone().into()],
+                )
+                .await?;
+        }
+        let freqs = pipe.try_all::<Option<u64>>().await;
+        for (key, freq) in keys.into_iter().zip(freqs) {
+            // Deleted since it was scanned
+            let Ok(Some(freq)) = freq else {
+                continue;
+            };
+            hottest.push(freq, key, ());
+        }
+    }
+    pb.finish_and_clear();
+
+    let hottest = hottest.into_sorted_vec();
+    let keys: Vec<_> = hottest.iter().map(|hot_key| hot_key.key.clone()).collect();
+    let key_types = key_types(client, &keys).await?;
+
+    let mut out = RecordSink::new(std::io::stdout().lock(), output);
+    match output {
+        OutputFormat::Plain => {
+            let mut b = tabled::builder::Builder::with_capacity(hottest.len() + 1, 3);
+            b.push_record(["key", "freq", "type"]);
+            for (hot_key, key_type) in hottest.iter().zip(&key_types) {
+                b.push_record([
+                    hot_key.key.as_str_lossy().into_owned(),
+                    hot_key.score.to_string(),
+                    key_type.clone(),
+                ]);
+            }
+            let mut table = b.build();
+            table.with(tabled::settings::Style::psql());
+            writeln!(out.text(), "{table}")?;
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            for (index, (hot_key, key_type)) in hottest.iter().zip(&key_types).enumerate() {
+                let record = serde_json::json!({
+                    "key": hot_key.key.as_str_lossy(),
+                    "freq": hot_key.score,
+                    "type": key_type,
+                });
+                write_json_record(out.text(), output, index, &record)?;
+            }
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            out.write_csv(["key", "freq", "type"])?;
+            for (hot_key, key_type) in hottest.iter().zip(&key_types) {
+                out.write_csv([
+                    hot_key.key.as_bytes(),
+                    hot_key.score.to_string().as_bytes(),
+                    key_type.as_bytes(),
+                ])?;
+            }
+        }
+    }
+    let _ = out.finish(output, hottest.len())?;
+    Ok(())
+}
+
+/// Upper bounds of the idle report buckets, followed by the keys idle for a month or more
+const IDLE_BUCKETS: [(&str, Duration); 4] = [
+    ("<1h", Duration::from_secs(60 * 60)),
+    ("<1d", Duration::from_secs(24 * 60 * 60)),
+    ("<7d", Duration::from_secs(7 * 24 * 60 * 60)),
+    ("<30d", Duration::from_secs(30 * 24 * 60 * 60)),
+];
+
+async fn idle_report(
+    client: &Client,
+    scan_options: &ScanOptions,
+    top: usize,
+) -> color_eyre::Result<()> {
+    if let Some(policy) = lfu_policy(client).await? {
+        return Err(eyre!(
+            "maxmemory-policy is {policy}, idle times are not tracked with LFU policies"
+        ));
+    }
+
+    let mut idlest = TopKeys::new(top);
+    // Keys and bytes within each bucket, the last one being for a month or more
+    let mut counts = [0; IDLE_BUCKETS.len() + 1];
+    let mut bytes = [0; IDLE_BUCKETS.len() + 1];
+    let pb = stats_progress()?;
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    while let Some(keys) = scanner.next_page().await? {
+        pb.inc(keys.len() as u64);
+        let pipe = client.pipeline();
+        for key in &keys {
+            let _: () = pipe
+                .custom(
+                    object_command(),
+                    vec![Value::from("IDLETIME"), key.clone().into()],
+                )
+                .await?;
+            let _: () = pipe.memory_usage(key, None).await?;
+        }
+        let mut replies = pipe.try_all::<Option<u64>>().await.into_iter();
+        for key in keys {
+            let idle = replies.next().and_then(Result::ok).flatten();
+            let size = replies.next().and_then(Result::ok).flatten();
+            // Deleted since it was scanned
+            let (Some(idle), Some(size)) = (idle, size) else {
+                continue;
+            };
+            let bucket = IDLE_BUCKETS
+                .iter()
+                .position(|(_, bound)| Duration::from_secs(idle) < *bound)
+                .unwrap_or(IDLE_BUCKETS.len());
+            counts[bucket] += 1;
+            bytes[bucket] += size;
+            idlest.push(idle, key, size);
+        }
+    }
+    pb.finish_and_clear();
+
+    let idlest = idlest.into_sorted_vec();
+    let mut b = tabled::builder::Builder::with_capacity(idlest.len() + 1, 3);
+    b.push_record(["key", "idle", "bytes"]);
+    for idle_key in idlest {
+        b.push_record([
+            idle_key.key.as_str_lossy().into_owned(),
+            humantime::format_duration(Duration::from_secs(idle_key.score)).to_string(),
+            idle_key.details.to_string(),
+        ]);
+    }
+    let mut table = b.build();
+    table.with(tabled::settings::Style::psql());
+    println!("{table}");
+
+    let names = IDLE_BUCKETS.iter().map(|(name, _)| *name).chain([">=30d"]);
+    let mut b = tabled::builder::Builder::with_capacity(counts.len() + 2, 3);
+    b.push_record(["idle", "keys", "memory"]);
+    for (name, (count, size)) in names.zip(counts.iter().zip(bytes)) {
+        b.push_record([
+            name.to_owned(),
+            count.to_string(),
+            HumanBytes(size).to_string(),
+        ]);
+    }
+    b.push_record([
+        "(total)".to_owned(),
+        counts.iter().sum::<usize>().to_string(),
+        HumanBytes(bytes.iter().sum()).to_string(),
+    ]);
+    let mut table = b.build();
+    table.with(tabled::settings::Style::psql());
+    println!("{table}");
+    Ok(())
+}
+
+/// Keys of a type counted by type-stats
+#[derive(Debug, Default)]
+struct TypeCount {
+    keys: usize,
+    /// Keys whose number of elements is known, RedisJSON documents having none
+    counted: usize,
+    elements: u64,
+    memory: Measured,
+}
+
+impl TypeCount {
+    fn average_elements(&self) -> Option<u64> {
+        (self.counted > 0).then(|| self.elements / self.counted as u64)
+    }
+}
+
+/// Count the keys per type, measuring the memory of a `sample_rate` fraction of them if given
+///
+/// The number of elements of a key depends on its type, so each page takes a TYPE pipeline
+/// followed by a pipeline of length commands.
+async fn type_stats(
+    client: &Client,
+    scan_options: &ScanOptions,
+    sample_rate: Option<f64>,
+) -> color_eyre::Result<()> {
+    let mut counts = BTreeMap::<String, TypeCount>::new();
+    let pb = stats_progress()?;
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    while let Some(keys) = scanner.next_page().await? {
+        pb.inc(keys.len() as u64);
+        let key_types = key_types(client, &keys).await?;
+        let pipe = client.pipeline();
+        let mut queued = Vec::with_capacity(keys.len());
+        for (key, key_type) in keys.iter().zip(&key_types) {
+            let has_len = match KeyType::from_reply(key_type) {
+                Some(parsed) => parsed.queue_len(&pipe, key).await?,
+                None => false,
+            };
+            queued.push(has_len);
+        }
+        let mut lengths = pipe.try_all::<Option<u64>>().await.into_iter();
+        for (key_type, has_len) in key_types.iter().zip(queued) {
+            // Deleted since it was scanned
+            if key_type == "none" {
+                continue;
+            }
+            let count = counts.entry(key_type.clone()).or_default();
+            count.keys += 1;
+            if has_len && let Some(Ok(Some(elements))) = lengths.next() {
+                count.counted += 1;
+                count.elements += elements;
+            }
+        }
+        if let Some(rate) = sample_rate {
+            let types: HashMap<&Key, &String> = keys.iter().zip(&key_types).collect();
+            let keys: Vec<&Key> = keys.iter().collect();
+            for (key, size) in sample_sizes(client, &keys, rate, false).await? {
+                if let Some(count) = counts.get_mut(types[key].as_str()) {
+                    count.memory.add(Measured {
+                        keys: 1,
+                        bytes: size,
+                    });
+                }
+            }
+        }
+        pb.set_message(format!("{} types", counts.len()));
+    }
+    pb.finish_and_clear();
+
+    let mut header = vec!["type", "count", "average elements"];
+    if sample_rate.is_some() {
+        header.extend(["memory", "average"]);
+    }
+    let mut b = tabled::builder::Builder::new();
+    b.push_record(header);
+    let mut total = TypeCount::default();
+    for count in counts.values() {
+        total.keys += count.keys;
+        total.counted += count.counted;
+        total.elements += count.elements;
+        total.memory.add(count.memory);
+    }
+    let rows = counts
+        .iter()
+        .map(|(key_type, count)| (key_type.clone(), count));
+    for (name, count) in rows.chain([("(total)".to_owned(), &total)]) {
+        let mut record = vec![
+            name,
+            count.keys.to_string(),
+            count
+                .average_elements()
+                .map_or_else(|| "-".to_owned(), |average| average.to_string()),
+        ];
+        if sample_rate.is_some() {
+            record.extend(match count.memory.extrapolate(count.keys) {
+                Some(memory) => [
+                    HumanBytes(memory).to_string(),
+                    HumanBytes(memory / count.keys as u64).to_string(),
+                ],
+                None => ["-".to_owned(), "-".to_owned()],
+            });
+        }
+        b.push_record(record);
+    }
+    let mut table = b.build();
+    table.with(tabled::settings::Style::psql());
+    println!("{table}");
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ExportFormat {
+    /// Length prefixed binary records, after a magic number and a JSON header
+    Binary,
+    /// A JSON header line, then one JSON record per key with a base64 payload
+    Jsonl,
+}
+
+/// Magic number starting binary export files, followed by the format version
+const EXPORT_MAGIC: &[u8] = b"REDIS-TOOLS-EXPORT";
+const EXPORT_VERSION: u8 = 1;
+
+/// Header of an export file
+#[derive(Debug)]
+struct ExportHeader {
+    /// Version of the server the keys were dumped from, if known
+    server_version: Option<String>,
+    /// When the export started, in milliseconds since the Unix epoch
+    exported_at_ms: i64,
+}
+
+impl ExportHeader {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "redis_tools_export": EXPORT_VERSION,
+            "server_version": self.server_version,
+            "exported_at_ms": self.exported_at_ms,
+        })
+    }
+
+    fn from_json(json: &serde_json::Value) -> color_eyre::Result<Self> {
+        if json["redis_tools_export"].as_u64() != Some(EXPORT_VERSION.into()) {
+            return Err(eyre!(
+                "not an export file, or written by an unsupported version"
+            ));
+        }
+        Ok(Self {
+            server_version: json["server_version"].as_str().map(str::to_owned),
+            exported_at_ms: json["exported_at_ms"]
+                .as_i64()
+                .ok_or_else(|| eyre!("the export header has no exported_at_ms"))?,
+        })
+    }
+
+    /// Major and minor version of the server the keys were dumped from
+    fn major_minor(&self) -> Option<(u64, u64)> {
+        let mut parts = self.server_version.as_deref()?.split('.');
+        Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+    }
+}
+
+/// A key of an export file
+#[derive(Debug, PartialEq)]
+struct ExportEntry {
+    key: Vec<u8>,
+    /// Expiry time in milliseconds since the Unix epoch, `None` for keys without a TTL
+    expire_at_ms: Option<i64>,
+    payload: Vec<u8>,
+}
+
+/// Milliseconds since the Unix epoch
+fn unix_millis(time: std::time::SystemTime) -> i64 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+/// Writes the header, then the entries of an export file
+struct ExportWriter<W: Write> {
+    out: W,
+    format: ExportFormat,
+    bytes: u64,
+}
+
+impl<W: Write> ExportWriter<W> {
+    fn new(mut out: W, format: ExportFormat, header: &ExportHeader) -> color_eyre::Result<Self> {
+        let header = header.to_json().to_string();
+        let mut bytes = header.len() as u64;
+        match format {
+            ExportFormat::Binary => {
+                out.write_all(EXPORT_MAGIC)?;
+                out.write_all(&[EXPORT_VERSION])?;
+                out.write_all(&(header.len() as u32).to_le_bytes())?;
+                out.write_all(header.as_bytes())?;
+                bytes += EXPORT_MAGIC.len() as u64 + 5;
+            }
+            ExportFormat::Jsonl => {
+                writeln!(out, "{header}")?;
+                bytes += 1;
+            }
+        }
+        Ok(Self { out, format, bytes })
+    }
+
+    fn write(&mut self, entry: &ExportEntry) -> color_eyre::Result<()> {
+        match self.format {
+            ExportFormat::Binary => {
+                // A negative expiry time stands for no TTL
+                let expire_at_ms = entry.expire_at_ms.unwrap_or(-1);
+                self.out
+                    .write_all(&(entry.key.len() as u32).to_le_bytes())?;
+                self.out.write_all(&entry.key)?;
+                self.out.write_all(&expire_at_ms.to_le_bytes())?;
+                self.out
+                    .write_all(&(entry.payload.len() as u32).to_le_bytes())?;
+                self.out.write_all(&entry.payload)?;
+                self.bytes += 16 + (entry.key.len() + entry.payload.len()) as u64;
+            }
+            ExportFormat::Jsonl => {
+                let record = serde_json::json!({
+                    "key": Encoding::Utf8.json(&entry.key),
+                    "expire_at_ms": entry.expire_at_ms,
+                    "payload": BASE64.encode(&entry.payload),
+                });
+                let record = record.to_string();
+                writeln!(self.out, "{record}")?;
+                self.bytes += record.len() as u64 + 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Reads the header, then the entries of an export file, in either format
+struct ExportReader<R: BufRead> {
+    input: R,
+    format: ExportFormat,
+    header: ExportHeader,
+}
+
+impl<R: BufRead> ExportReader<R> {
+    fn new(mut input: R) -> color_eyre::Result<Self> {
+        let format = if input.fill_buf()?.starts_with(EXPORT_MAGIC) {
+            ExportFormat::Binary
+        } else {
+            ExportFormat::Jsonl
+        };
+        let header = match format {
+            ExportFormat::Binary => {
+                let mut magic = [0; EXPORT_MAGIC.len() + 1];
+                input.read_exact(&mut magic)?;
+                let header = read_chunk(&mut input)?;
+                serde_json::from_slice(&header)?
+            }
+            ExportFormat::Jsonl => {
+                let mut line = String::new();
+                input.read_line(&mut line)?;
+                serde_json::from_str(&line)
+                    .map_err(|_| eyre!("not an export file, the header is not valid JSON"))?
+            }
+        };
+        Ok(Self {
+            input,
+            format,
+            header: ExportHeader::from_json(&header)?,
+        })
+    }
+
+    fn next_entry(&mut self) -> color_eyre::Result<Option<ExportEntry>> {
+        match self.format {
+            ExportFormat::Binary => {
+                if self.input.fill_buf()?.is_empty() {
+                    return Ok(None);
+                }
+                let key = read_chunk(&mut self.input)?;
+                let mut expire_at_ms = [0; 8];
+                self.input.read_exact(&mut expire_at_ms)?;
+                let expire_at_ms = i64::from_le_bytes(expire_at_ms);
+                Ok(Some(ExportEntry {
+                    key,
+                    expire_at_ms: (expire_at_ms >= 0).then_some(expire_at_ms),
+                    payload: read_chunk(&mut self.input)?,
+                }))
+            }
+            ExportFormat::Jsonl => {
+                let mut line = String::new();
+                if self.input.read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
+                let record: serde_json::Value = serde_json::from_str(&line)?;
+                let key = match &record["key"] {
+                    serde_json::Value::String(key) => key.as_bytes().to_vec(),
+                    key => BASE64.decode(
+                        key["base64"]
+                            .as_str()
+                            .ok_or_else(|| eyre!("export record without a key"))?,
+                    )?,
+                };
+                let payload = record["payload"]
+                    .as_str()
+                    .ok_or_else(|| eyre!("export record without a payload"))?;
+                Ok(Some(ExportEntry {
+                    key,
+                    expire_at_ms: record["expire_at_ms"].as_i64(),
+                    payload: BASE64.decode(payload)?,
+                }))
+            }
+        }
+    }
+}
+
+/// Read bytes prefixed by their length
+///
+/// The buffer grows with the bytes actually read, so that a corrupt length can't allocate gigabytes
+/// up front.
+fn read_chunk(input: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    input.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len).into();
+    let mut chunk = Vec::new();
+    input.by_ref().take(len).read_to_end(&mut chunk)?;
+    if (chunk.len() as u64) < len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(chunk)
+}
+
+async fn export(
+    client: &Client,
+    scan_options: &ScanOptions,
+    format: ExportFormat,
+    path: &Path,
+) -> color_eyre::Result<()> {
+    let header = ExportHeader {
+        server_version: client.server_version().map(|version| version.to_string()),
+        exported_at_ms: unix_millis(std::time::SystemTime::now()),
+    };
+    let destination = Output::open(Some(path))?;
+    let mut writer = ExportWriter::new(destination, format, &header)?;
+    let pb = stats_progress()?;
+    let (mut exported, mut missing) = (0, 0);
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    while let Some(keys) = scanner.next_page().await? {
+        let refs: Vec<&Key> = keys.iter().collect();
+        let dumps = dump_keys(client, &refs).await?;
+        for (key, dump) in keys.iter().zip(dumps) {
+            let Some((ttl, Value::Bytes(payload))) = dump else {
+                missing += 1;
+                continue;
+            };
+            let now = unix_millis(std::time::SystemTime::now());
+            writer.write(&ExportEntry {
+                key: key.as_bytes().to_vec(),
+                expire_at_ms: ttl.map(|ttl| now + ttl),
+                payload: payload.to_vec(),
+            })?;
+            exported += 1;
+        }
+        pb.set_position(exported);
+        pb.set_message(HumanBytes(writer.bytes).to_string());
+    }
+    pb.finish_and_clear();
+    let bytes = writer.bytes;
+    writer.into_inner().commit()?;
+    let version = header
+        .server_version
+        .as_deref()
+        .unwrap_or("an unknown version");
+    eprintln!(
+        "Exported {exported} keys from Redis {version}, {} written to {}",
+        HumanBytes(bytes),
+        path.display()
+    );
+    if missing > 0 {
+        eprintln!("{missing} keys no longer existed");
+    }
+    Ok(())
+}
+
+async fn import(client: &Client, path: &Path, options: &ImportOptions) -> color_eyre::Result<()> {
+    let mut reader = ExportReader::new(open_input(Some(path))?)?;
+    let header = &reader.header;
+    let version = header
+        .server_version
+        .as_deref()
+        .unwrap_or("an unknown version");
+    if let (Some(source), Some(destination)) = (header.major_minor(), client.server_version())
+        && source > (destination.major, destination.minor)
+        && !options.force
+    {
+        return Err(eyre!(
+            "the keys were exported from Redis {version}, their payloads can't be restored on Redis {destination} (pass --force to try anyway)"
+        ));
+    }
+    let exported_at = humantime::format_rfc3339_seconds(
+        std::time::UNIX_EPOCH + Duration::from_millis(header.exported_at_ms as u64),
+    );
+    eprintln!(
+        "{} holds keys exported from Redis {version} at {exported_at}",
+        path.display()
+    );
+
+    let prefixed = |key: Vec<u8>| match &options.key_prefix {
+        Some(prefix) => Key::from([prefix.as_bytes(), &key].concat().into_boxed_slice()),
+        None => Key::from(key.into_boxed_slice()),
+    };
+    if options.batch.dry_run {
+        let (mut with_ttl, mut without_ttl) = (0, 0);
+        while let Some(entry) = reader.next_entry()? {
+            if with_ttl + without_ttl < 10 {
+                eprintln!("  {}", prefixed(entry.key).as_str_lossy());
+            }
+            match entry.expire_at_ms {
+                Some(_) => with_ttl += 1,
+                None => without_ttl += 1,
+            }
+        }
+        println!(
+            "{} keys would be restored, {with_ttl} with TTL, {without_ttl} without TTL",
+            with_ttl + without_ttl
+        );
+        return Ok(());
+    }
+    if !options.batch.yes && !ask_confirmation(&format!("restore the keys of {}", path.display()))?
+    {
+        return Err(eyre!("Import aborted"));
+    }
+
+    let pb = stats_progress()?;
+    let mut pacing = Pacing::default();
+    let (mut restored, mut skipped, mut failed) = (0, 0, 0);
+    let mut batch = Vec::with_capacity(options.batch.batch_size as usize);
+    loop {
+        let entry = reader.next_entry()?;
+        let done = entry.is_none();
+        if let Some(entry) = entry {
+            let ttl = match entry.expire_at_ms {
+                None => 0,
+                Some(expire_at_ms) if options.absttl => expire_at_ms,
+                // Remaining TTL when exported, at least a millisecond not to be read as no TTL
+                Some(expire_at_ms) => (expire_at_ms - reader.header.exported_at_ms).max(1),
+            };
+            if options.absttl && ttl > 0 && ttl <= unix_millis(std::time::SystemTime::now()) {
+                skipped += 1;
+            } else {
+                batch.push((prefixed(entry.key), ttl, entry.payload));
+            }
+        }
+        if !done && batch.len() < options.batch.batch_size as usize {
+            continue;
+        }
+        if !batch.is_empty() {
+            pacing.wait(&options.batch, batch.len()).await;
+            let pipe = client.pipeline();
+            for (key, ttl, payload) in &batch {
+                let _: () = pipe
+                    .restore(
+                        key.clone(),
+                        *ttl,
+                        Value::from(payload.as_slice()),
+                        options.replace,
+                        options.absttl,
+                        None,
+                        None,
+                    )
+                    .await?;
+            }
+            for ((key, _, _), reply) in batch.iter().zip(pipe.try_all::<Value>().await) {
+                match reply {
+                    Ok(_) => restored += 1,
+                    Err(error) if error.details().starts_with("BUSYKEY") => skipped += 1,
+                    Err(error) => {
+                        pb.println(format!("{}: {}", key.as_str_lossy(), error.details()));
+                        failed += 1;
+                    }
+                }
+            }
+            pb.set_position(restored + skipped + failed);
+            batch.clear();
+        }
+        if done {
+            break;
+        }
+    }
+    pb.finish_and_clear();
+    eprintln!(
+        "Restored {restored} keys, {skipped} skipped as they already existed or expired, {failed} failed"
+    );
+    Ok(())
+}
+
+/// Uniform random sample of a fixed size from a stream of items
+struct Reservoir<T> {
+    size: usize,
+    seen: u64,
+    items: Vec<T>,
+    hasher: std::collections::hash_map::RandomState,
+}
+
+impl<T> Reservoir<T> {
+    fn new(size: usize) -> Self {
+        Self {
+            size,
+            seen: 0,
+            items: Vec::with_capacity(size),
+            hasher: std::collections::hash_map::RandomState::new(),
+        }
+    }
+
+    fn push(&mut self, item: T) {
+        use std::hash::BuildHasher;
+
+        self.seen += 1;
+        if self.items.len() < self.size {
+            self.items.push(item);
+            return;
+        }
+        let index = (self.hasher.hash_one(self.seen) % self.seen) as usize;
+        if index < self.size {
+            self.items[index] = item;
+        }
+    }
+
+    fn into_vec(self) -> Vec<T> {
+        self.items
+    }
+}
+
+async fn migrate(
+    client: &Client,
+    scan_options: &ScanOptions,
+    options: &MigrateOptions,
+) -> color_eyre::Result<()> {
+    let (verb, past) = if options.copy {
+        ("copy", "copied")
+    } else {
+        ("move", "moved")
+    };
+    let keys = scan(client, scan_options).await?;
+    if options.batch.dry_run {
+        println!(
+            "{} keys matched, they would be {past} to {}",
+            keys.len(),
+            options.dest_url
+        );
+        return Ok(());
+    }
+    let destination = setup_client(&RedisInfo {
+        cluster: options.dest_cluster,
+        url: options.dest_url.clone(),
+    })
+    .await?;
+    if !keys.is_empty() && !options.batch.yes {
+        let action = format!("{verb} them to {}", options.dest_url);
+        if !confirm_keys(&scan_options.pattern, &keys, &action)? {
+            return Err(eyre!("Migration aborted"));
+        }
+    }
+    let message = format!("Migrating keys from pattern {}", scan_options.pattern);
+    let pb = keys_progress(keys.len(), message)?;
+    let mut pacing = Pacing::default();
+    let (mut migrated, mut existing, mut missing) = (0, 0, 0);
+    // Migrated keys along with their payload, for the verification
+    let mut samples = Reservoir::new(options.verify);
+    for chunk in keys.chunks(options.batch.batch_size as usize) {
+        pacing.wait(&options.batch, chunk.len()).await;
+        let sources: Vec<&Key> = chunk.iter().collect();
+        let dumps = dump_keys(client, &sources).await?;
+        let dumped: Vec<_> = chunk
+            .iter()
+            .zip(dumps)
+            .filter_map(|(key, dump)| Some((key, dump?)))
+            .collect();
+        missing += chunk.len() - dumped.len();
+        let restores = dumped.iter().map(|(key, dump)| (*key, dump.clone()));
+        let created = restore_keys(&destination, restores, options.replace).await?;
+        let pipe = client.pipeline();
+        let mut deleted = 0;
+        for ((key, (_, payload)), created) in dumped.into_iter().zip(created) {
+            if !created {
+                existing += 1;
+                continue;
+            }
+            if !options.copy {
+                let _: () = pipe.del(key).await?;
+                deleted += 1;
+            }
+            migrated += 1;
+            samples.push((key, payload));
+        }
+        if deleted > 0 {
+            let _: Vec<Value> = pipe.all().await?;
+        }
+        pb.inc(chunk.len() as u64);
+    }
+    pb.finish();
+    eprintln!(
+        "{} {migrated} keys, {existing} already existed in the destination and {missing} no longer existed",
+        if options.copy { "Copied" } else { "Moved" }
+    );
+
+    let samples = samples.into_vec();
+    let sampled: Vec<&Key> = samples.iter().map(|(key, _)| *key).collect();
+    let dumps = dump_keys(&destination, &sampled).await?;
+    let mismatched: Vec<&Key> = samples
+        .iter()
+        .zip(dumps)
+        .filter(|((_, payload), dump)| dump.as_ref().is_none_or(|(_, dumped)| dumped != payload))
+        .map(|((key, _), _)| *key)
+        .collect();
+    if !samples.is_empty() {
+        eprintln!(
+            "Verified {} keys, {} have a different payload in the destination",
+            samples.len(),
+            mismatched.len()
+        );
+        print_sample(&mismatched.into_iter().cloned().collect::<Vec<_>>());
+    }
+    Ok(())
+}
+
+/// Files listing the keys of each category found by compare, one key per line
+struct CompareFiles {
+    only_in_source: AtomicFile,
+    only_in_target: AtomicFile,
+    in_both: AtomicFile,
+    mismatched: AtomicFile,
+}
+
+impl CompareFiles {
+    fn create(dir: &Path) -> color_eyre::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            only_in_source: AtomicFile::create(&dir.join("only_in_source.txt"))?,
+            only_in_target: AtomicFile::create(&dir.join("only_in_target.txt"))?,
+            in_both: AtomicFile::create(&dir.join("in_both.txt"))?,
+            mismatched: AtomicFile::create(&dir.join("mismatched.txt"))?,
+        })
+    }
+
+    fn commit(self) -> color_eyre::Result<()> {
+        self.only_in_source.commit()?;
+        self.only_in_target.commit()?;
+        self.in_both.commit()?;
+        self.mismatched.commit()
+    }
+}
+
+/// Write keys as stored, one per line
+fn write_keys<'a>(
+    out: &mut impl Write,
+    keys: impl IntoIterator<Item = &'a Key>,
+) -> std::io::Result<()> {
+    for key in keys {
+        out.write_all(key.as_bytes())?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Compare two instances, holding the source keys in memory while streaming the target ones
+async fn compare(scan_options: &ScanOptions, options: &CompareOptions) -> color_eyre::Result<()> {
+    let source = setup_client(&RedisInfo {
+        cluster: options.source_cluster,
+        url: options.source_url.clone(),
+    })
+    .await?;
+    let target = setup_client(&RedisInfo {
+        cluster: options.target_cluster,
+        url: options.target_url.clone(),
+    })
+    .await?;
+    let mut files = options
+        .out
+        .as_deref()
+        .map(CompareFiles::create)
+        .transpose()?;
+
+    let pb = stats_progress()?;
+    pb.set_message("reading the source keys");
+    let mut source_keys: HashSet<Key> = scan(&source, scan_options).await?.into_iter().collect();
+    let (mut only_in_target, mut in_both, mut mismatched) = (0, 0, 0);
+    let mut scanner = KeyScanner::new(&target, scan_options)?;
+    while let Some(keys) = scanner.next_page().await? {
+        pb.inc(keys.len() as u64);
+        let (common, missing): (Vec<Key>, Vec<Key>) =
+            keys.into_iter().partition(|key| source_keys.remove(key));
+        only_in_target += missing.len();
+        in_both += common.len();
+        let different = if options.values {
+            mismatched_values(&source, &target, &common).await?
+        } else {
+            Vec::new()
+        };
+        mismatched += different.len();
+        if let Some(files) = &mut files {
+            write_keys(&mut files.only_in_target, &missing)?;
+            write_keys(&mut files.in_both, &common)?;
+            write_keys(&mut files.mismatched, different)?;
+        }
+        pb.set_message(format!(
+            "{in_both} in both, {only_in_target} only in the target"
+        ));
+    }
+    pb.finish_and_clear();
+    if let Some(mut files) = files {
+        write_keys(&mut files.only_in_source, &source_keys)?;
+        files.commit()?;
+    }
+
+    let mut b = tabled::builder::Builder::new();
+    b.push_record(["keys", "count"]);
+    b.push_record(["only in source".to_owned(), source_keys.len().to_string()]);
+    b.push_record(["only in target".to_owned(), only_in_target.to_string()]);
+    b.push_record(["in both".to_owned(), in_both.to_string()]);
+    if options.values {
+        b.push_record(["with different values".to_owned(), mismatched.to_string()]);
+    }
+    let mut table = b.build();
+    table.with(tabled::settings::Style::psql());
+    println!("{table}");
+    Ok(())
+}
+
+/// The keys whose value differs between the source and the target
+async fn mismatched_values<'a>(
+    source: &Client,
+    target: &Client,
+    keys: &'a [Key],
+) -> color_eyre::Result<Vec<&'a Key>> {
+    if keys.is_empty() {
+        return Ok(Vec::new());
+    }
+    let key_types = key_types(source, keys).await?;
+    let mut values = Vec::with_capacity(2);
+    for client in [source, target] {
+        let pipe = client.pipeline();
+        for (key, key_type) in keys.iter().zip(&key_types) {
+            let _: () = if key_type == "string" {
+                pipe.get(key).await?
+            } else {
+                pipe.dump(key).await?
+            };
+        }
+        let replies: Vec<Option<Value>> = pipe
+            .try_all::<Value>()
+            .await
+            .into_iter()
+            .map(Result::ok)
+            .collect();
+        values.push(replies);
+    }
+    Ok(keys
+        .iter()
+        .zip(values[0].iter().zip(&values[1]))
+        .filter(|(_, (source, target))| source != target)
+        .map(|(key, _)| key)
+        .collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum SampleStrategy {
+    /// RANDOMKEY when a probe finds the pattern common enough, a full scan otherwise
+    Auto,
+    /// Repeated RANDOMKEY, keeping the keys matching the filters
+    Randomkey,
+    /// Reservoir sampling over a full scan, uniform however rare the pattern is
+    Scan,
+}
+
+/// Number of RANDOMKEY calls per wanted key before giving up on drawing more
+const RANDOMKEY_DRAWS: u64 = 100;
+
+/// Draw `size` distinct random keys matching the scan options
+async fn sample(
+    client: &Client,
+    options: &ScanOptions,
+    size: usize,
+    strategy: SampleStrategy,
+) -> color_eyre::Result<Vec<Key>> {
+    let budget = size as u64 * RANDOMKEY_DRAWS;
+    let strategy = match strategy {
+        SampleStrategy::Auto => {
+            let probe = (size as u64 * 10).max(100);
+            let (matched, drawn, _) = sample_keys(client, options, probe).await?;
+            if drawn == 0 {
+                return Ok(Vec::new());
+            }
+            let expected_draws = size as f64 * drawn as f64 / matched.len().max(1) as f64;
+            if !matched.is_empty() && expected_draws <= budget as f64 {
+                SampleStrategy::Randomkey
+            } else {
+                SampleStrategy::Scan
+            }
+        }
+        strategy => strategy,
+    };
+    if strategy == SampleStrategy::Scan {
+        let mut reservoir = Reservoir::new(size);
+        let mut scanner = KeyScanner::new(client, options)?;
+        while let Some(keys) = scanner.next_page().await? {
+            for key in keys {
+                reservoir.push(key);
+            }
+        }
+        return Ok(reservoir.into_vec());
+    }
+
+    let mut seen = HashSet::new();
+    let mut keys = Vec::with_capacity(size);
+    let mut left = budget;
+    while keys.len() < size && left > 0 {
+        let draws = ((size - keys.len()) as u64 * 10).clamp(100, left.max(100));
+        left = left.saturating_sub(draws);
+        let (page, _, total) = sample_keys(client, options, draws).await?;
+        if total == 0 {
+            break;
+        }
+        for key in page {
+            if keys.len() < size && seen.insert(key.clone()) {
+                keys.push(key);
+            }
+        }
+    }
+    if keys.len() < size {
+        eprintln!(
+            "Warning: only {} distinct keys were drawn, --strategy scan finds rare keys",
+            keys.len()
+        );
+    }
+    Ok(keys)
+}
+
+/// Print each key with its type, TTL and value, tab separated
+async fn print_sample_values(
+    client: &Client,
+    keys: &[Key],
+    max_elements: u64,
+) -> color_eyre::Result<()> {
+    let key_types = key_types(client, keys).await?;
+    let types: HashMap<&Key, &String> = keys.iter().zip(&key_types).collect();
+    let mut stats = ItemStats::default();
+    let items = fetch_items(
+        client,
+        keys,
+        &ItemOptions::preview(max_elements),
+        &mut stats,
+    )
+    .await?;
+    let mut out = std::io::stdout().lock();
+    for item in items {
+        let ttl = match item.ttl {
+            Some(Ttl::Remaining(ms)) => ms.to_string(),
+            _ => "none".to_owned(),
+        };
+        let value = item.value.map_or(serde_json::Value::Null, |value| {
+            value.to_json(Encoding::Utf8)
+        });
+        out.write_all(item.key.as_bytes())?;
+        writeln!(out, "\t{}\t{ttl}\t{value}", types[item.key])?;
+    }
+    stats.report();
+    Ok(())
+}
+
+async fn monitor(info: &RedisInfo, options: &MonitorOptions) -> color_eyre::Result<()> {
+    if info.cluster && !options.yes_i_know {
+        return Err(eyre!(
+            "MONITOR only attaches to a single node of a cluster, pass --yes-i-know to monitor the node behind --url"
+        ));
+    }
+    // MONITOR doesn't support cluster connections, the node behind the URL is used directly
+    let mut commands = std::pin::pin!(fred::monitor::run(Config::from_url(&info.url)?).await?);
+    let deadline = options
+        .duration
+        .map(|duration| tokio::time::Instant::now() + duration);
+    let mut out = RecordSink::new(std::io::stdout().lock(), options.output);
+    out.write_csv(["timestamp", "db", "client", "command", "args"])?;
+    let mut written = 0;
+    loop {
+        let command = tokio::select! {
+            command = commands.next() => command,
+            _ = sleep_until(deadline) => None,
+        };
+        let Some(command) = command else {
+            break;
+        };
+        if !options.matches(&command) {
+            continue;
+        }
+        let time = std::time::UNIX_EPOCH + Duration::from_secs_f64(command.timestamp);
+        let time = humantime::format_rfc3339_micros(time).to_string();
+        let args: Vec<Cow<str>> = command
+            .args
+            .iter()
+            .map(|arg| arg.as_str().unwrap_or(Cow::Borrowed("?")))
+            .collect();
+        match options.output {
+            OutputFormat::Plain => {
+                let out = out.text();
+                write!(
+                    out,
+                    "{time} [{} {}] {}",
+                    command.db, command.client, command.command
+                )?;
+                for arg in &args {
+                    write!(out, " {}", escape_plain(arg))?;
+                }
+                writeln!(out)?;
+            }
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                out.write_csv([
+                    time,
+                    command.db.to_string(),
+                    command.client,
+                    command.command,
+                    args.join(" "),
+                ])?;
+            }
+            _ => {
+                let record = serde_json::json!({
+                    "timestamp": time,
+                    "db": command.db,
+                    "client": command.client,
+                    "command": command.command,
+                    "args": args,
+                });
+                write_json_record(out.text(), options.output, written, &record)?;
+            }
+        }
+        out.flush()?;
+        written += 1;
+    }
+    let _ = out.finish(options.output, written)?;
+    Ok(())
+}
+
+/// The bytes of a string reply, numeric strings being possibly parsed as integers
+fn value_bytes(value: &Value) -> Cow<'_, [u8]> {
+    match value.as_bytes() {
+        Some(bytes) => bytes.into(),
+        None => value
+            .as_str()
+            .unwrap_or_default()
+            .into_owned()
+            .into_bytes()
+            .into(),
+    }
+}
+
+/// Sleep until the deadline, forever without one
+async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn subscribe(
+    client: &Client,
+    channels: &[String],
+    count: Option<u64>,
+    duration: Option<Duration>,
+    output: OutputFormat,
+) -> color_eyre::Result<()> {
+    let (patterns, channels): (Vec<&String>, Vec<&String>) = channels
+        .iter()
+        .partition(|channel| channel.contains(['*', '?', '[']));
+    // Subscribe before the first message can arrive
+    let mut messages = client.message_rx();
+    if !channels.is_empty() {
+        let channels: Vec<&str> = channels.iter().map(|channel| channel.as_str()).collect();
+        client.subscribe(channels.clone()).await?;
+        // Shard channels were added in Redis 7.0
+        if client.is_clustered()
+            && client
+                .server_version()
+                .is_some_and(|version| version.major >= 7)
+        {
+            client.ssubscribe(channels).await?;
+        }
+    }
+    if !patterns.is_empty() {
+        let patterns: Vec<&str> = patterns.iter().map(|pattern| pattern.as_str()).collect();
+        client.psubscribe(patterns).await?;
+    }
+
+    let deadline = duration.map(|duration| tokio::time::Instant::now() + duration);
+    let mut out = RecordSink::new(std::io::stdout().lock(), output);
+    out.write_csv(["timestamp", "channel", "message"])?;
+    let mut received = 0;
+    while count.is_none_or(|count| received < count) {
+        let message = tokio::select! {
+            message = messages.recv() => message,
+            _ = sleep_until(deadline) => break,
+        };
+        let message = match message {
+            Ok(message) => message,
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                eprintln!("Warning: {skipped} messages were dropped, the output is too slow");
+                continue;
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+        };
+        let time = humantime::format_rfc3339_millis(std::time::SystemTime::now()).to_string();
+        let payload = value_bytes(&message.value);
+        match output {
+            OutputFormat::Plain => {
+                let payload = escape_plain(&String::from_utf8_lossy(&payload));
+                writeln!(out.text(), "{time} {} {payload}", &*message.channel)?;
+            }
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                out.write_csv([time.as_bytes(), message.channel.as_bytes(), &payload])?;
+            }
+            _ => {
+                let record = serde_json::json!({
+                    "timestamp": time,
+                    "channel": &*message.channel,
+                    "message": Encoding::Utf8.json(&payload),
+                });
+                write_json_record(out.text(), output, received as usize, &record)?;
+            }
+        }
+        out.flush()?;
+        received += 1;
+    }
+    let _ = out.finish(output, received as usize)?;
+    Ok(())
+}
+
+/// Read a message to publish, from a raw line or from a JSONL record if `encoding` is given
+fn parse_message(
+    line: &str,
+    channel: Option<&str>,
+    encoding: Option<Encoding>,
+) -> color_eyre::Result<(String, Vec<u8>)> {
+    let Some(encoding) = encoding else {
+        let channel = channel.ok_or_else(|| eyre!("a channel is required to publish lines"))?;
+        return Ok((channel.to_owned(), line.as_bytes().to_vec()));
+    };
+    let record: serde_json::Value = serde_json::from_str(line)?;
+    let channel = record["channel"]
+        .as_str()
+        .or(channel)
+        .ok_or_else(|| eyre!("record without a channel, and none given"))?;
+    let payload = record["payload"]
+        .as_str()
+        .ok_or_else(|| eyre!("record without a payload"))?;
+    Ok((channel.to_owned(), encoding.decode(payload)?))
+}
+
+async fn publish(
+    client: &Client,
+    channel: Option<&str>,
+    file: Option<&Path>,
+    encoding: Option<Encoding>,
+    rate: Option<u64>,
+) -> color_eyre::Result<()> {
+    if channel.is_none() && encoding.is_none() {
+        return Err(eyre!("a channel is required to publish lines"));
+    }
+    let input = open_input(file)?;
+    // Batches small enough for the rate to hold over a tenth of a second
+    let batch_size = rate.map_or(1000, |rate| (rate / 10).clamp(1, 1000)) as usize;
+    let mut pacing = Pacing::default();
+    let (mut published, mut receivers) = (0, 0);
+    let mut lines = input.lines().enumerate().peekable();
+    while lines.peek().is_some() {
+        let mut batch = Vec::with_capacity(batch_size);
+        for (index, line) in lines.by_ref().take(batch_size) {
+            let line = line?;
+            match parse_message(&line, channel, encoding) {
+                Ok(message) => batch.push(message),
+                Err(error) => eprintln!("Skipping line {}: {error}", index + 1),
+            }
+        }
+        if batch.is_empty() {
+            continue;
+        }
+        pacing.wait_for(rate, Duration::ZERO, batch.len()).await;
+        let pipe = client.pipeline();
+        for (channel, payload) in batch {
+            let _: () = pipe.publish(channel, payload.as_slice()).await?;
+        }
+        let counts: Vec<i64> = pipe.all().await?;
+        published += counts.len();
+        receivers += counts.iter().sum::<i64>();
+    }
+    eprintln!("Published {published} messages to {receivers} receivers");
+    Ok(())
+}
+
+/// Width past which the commands of the slow log are truncated in tables
+const SLOWLOG_COMMAND_WIDTH: usize = 80;
+
+async fn slowlog(
+    client: &Client,
+    count: Option<i64>,
+    min_duration: Option<Duration>,
+    reset: bool,
+    output: OutputFormat,
+) -> color_eyre::Result<()> {
+    let mut entries = Vec::new();
+    let nodes = primary_nodes(client);
+    for node in &nodes {
+        let node_client = client.with_options(&fred::types::config::Options {
+            cluster_node: node.clone(),
+            ..Default::default()
+        });
+        let node_entries: Vec<SlowlogEntry> = node_client.slowlog_get(count).await?;
+        let node_name = node.as_ref().map(|server| server.to_string());
+        entries.extend(
+            node_entries
+                .into_iter()
+                .filter(|entry| min_duration.is_none_or(|min| entry.duration >= min))
+                .map(|entry| (node_name.clone(), entry)),
+        );
+    }
+    // Most recent first across nodes, as within each node
+    entries.sort_by_key(|(_, entry)| std::cmp::Reverse((entry.timestamp, entry.id)));
+
+    let with_node = client.is_clustered();
+    let describe = |entry: &SlowlogEntry| {
+        let time = std::time::UNIX_EPOCH + Duration::from_secs(entry.timestamp.max(0) as u64);
+        let client_name = match (&entry.ip, &entry.name) {
+            (Some(ip), Some(name)) if !name.is_empty() => format!("{ip} ({name})"),
+            (Some(ip), _) => ip.to_string(),
+            _ => String::new(),
+        };
+        let command: Vec<_> = entry
+            .args
+            .iter()
+            .map(|arg| match arg.as_bytes() {
+                Some(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+                None => arg.as_string().unwrap_or_default(),
+            })
+            .collect();
+        (
+            humantime::format_rfc3339_seconds(time).to_string(),
+            client_name,
+            command,
+        )
+    };
+
+    let mut out = RecordSink::new(std::io::stdout().lock(), output);
+    let mut header = vec!["id", "time", "duration", "client", "command"];
+    if with_node {
+        header.insert(0, "node");
+    }
+    match output {
+        OutputFormat::Plain => {
+            let mut b = tabled::builder::Builder::with_capacity(entries.len() + 1, header.len());
+            b.push_record(header);
+            for (node, entry) in &entries {
+                let (time, client_name, command) = describe(entry);
+                let mut command = command.join(" ");
+                if let Some((index, _)) = command.char_indices().nth(SLOWLOG_COMMAND_WIDTH) {
+                    command.truncate(index);
+                    command.push('…');
+                }
+                let mut record = vec![
+                    entry.id.to_string(),
+                    time,
+                    humantime::format_duration(entry.duration).to_string(),
+                    client_name,
+                    escape_plain(&command),
+                ];
+                if let Some(node) = node {
+                    record.insert(0, node.clone());
+                }
+                b.push_record(record);
+            }
+            let mut table = b.build();
+            table.with(tabled::settings::Style::psql());
+            writeln!(out.text(), "{table}")?;
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            for (index, (node, entry)) in entries.iter().enumerate() {
+                let (time, client_name, command) = describe(entry);
+                let mut record = serde_json::json!({
+                    "id": entry.id,
+                    "time": time,
+                    "duration_us": entry.duration.as_micros() as u64,
+                    "client": client_name,
+                    "command": command,
+                });
+                if let Some(node) = node {
+                    record["node"] = node.clone().into();
+                }
+                write_json_record(out.text(), output, index, &record)?;
+            }
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            out.write_csv(&header)?;
+            for (node, entry) in &entries {
+                let (time, client_name, command) = describe(entry);
+                let mut record = vec![
+                    entry.id.to_string(),
+                    time,
+                    entry.duration.as_micros().to_string(),
+                    client_name,
+                    command.join(" "),
+                ];
+                if let Some(node) = node {
+                    record.insert(0, node.clone());
+                }
+                out.write_csv(record)?;
+            }
+        }
+    }
+    let _ = out.finish(output, entries.len())?;
+
+    if reset {
+        for node in nodes {
+            let node_client = client.with_options(&fred::types::config::Options {
+                cluster_node: node,
+                ..Default::default()
+            });
+            node_client.slowlog_reset().await?;
+        }
+        eprintln!("Cleared the slow log");
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum InfoSection {
+    /// Version and uptime
+    Server,
+    /// Connected clients
+    Clients,
+    /// Used memory against maxmemory
+    Memory,
+    /// Hit ratio and operations per second
+    Stats,
+    /// Role, replicas and replication lag
+    Replication,
+    /// Keys per database
+    Keyspace,
+}
+
+impl InfoSection {
+    const ALL: [InfoSection; 6] = [
+        InfoSection::Server,
+        InfoSection::Clients,
+        InfoSection::Memory,
+        InfoSection::Stats,
+        InfoSection::Replication,
+        InfoSection::Keyspace,
+    ];
+
+    fn kind(&self) -> InfoKind {
+        match self {
+            InfoSection::Server => InfoKind::Server,
+            InfoSection::Clients => InfoKind::Clients,
+            InfoSection::Memory => InfoKind::Memory,
+            InfoSection::Stats => InfoKind::Stats,
+            InfoSection::Replication => InfoKind::Replication,
+            InfoSection::Keyspace => InfoKind::Keyspace,
+        }
+    }
+
+    /// The summary fields of this section, as JSON and as text for tables
+    fn summary(
+        &self,
+        info: &HashMap<&str, &str>,
+    ) -> Vec<(&'static str, serde_json::Value, String)> {
+        let number = |name: &str| info.get(name).and_then(|value| value.parse::<u64>().ok());
+        let text = |value: Option<u64>| value.map(|value| value.to_string()).unwrap_or_default();
+        match self {
+            InfoSection::Server => {
+                let uptime = number("uptime_in_seconds");
+                vec![
+                    (
+                        "version",
+                        info.get("redis_version").copied().into(),
+                        info.get("redis_version")
+                            .copied()
+                            .unwrap_or_default()
+                            .to_owned(),
+                    ),
+                    (
+                        "uptime_seconds",
+                        uptime.into(),
+                        uptime
+                            .map(|uptime| {
+                                humantime::format_duration(Duration::from_secs(uptime)).to_string()
+                            })
+                            .unwrap_or_default(),
+                    ),
+                ]
+            }
+            InfoSection::Clients => {
+                let clients = number("connected_clients");
+                vec![("clients", clients.into(), text(clients))]
+            }
+            InfoSection::Memory => {
+                let used = number("used_memory");
+                let max = number("maxmemory").filter(|&max| max > 0);
+                let percentage = used
+                    .zip(max)
+                    .map(|(used, max)| used as f64 * 100.0 / max as f64);
+                vec![
+                    (
+                        "used_memory",
+                        used.into(),
+                        used.map(|used| HumanBytes(used).to_string())
+                            .unwrap_or_default(),
+                    ),
+                    (
+                        "maxmemory",
+                        max.into(),
+                        max.map_or_else(|| "none".to_owned(), |max| HumanBytes(max).to_string()),
+                    ),
+                    (
+                        "memory_used_percentage",
+                        percentage.into(),
+                        percentage
+                            .map(|percentage| format!("{percentage:.1}%"))
+                            .unwrap_or_default(),
+                    ),
+                ]
+            }
+            InfoSection::Stats => {
+                let ops = number("instantaneous_ops_per_sec");
+                let hit_ratio = number("keyspace_hits")
+                    .zip(number("keyspace_misses"))
+                    .filter(|(hits, misses)| hits + misses > 0)
+                    .map(|(hits, misses)| hits as f64 / (hits + misses) as f64);
+                vec![
+                    (
+                        "hit_ratio",
+                        hit_ratio.into(),
+                        hit_ratio
+                            .map(|ratio| format!("{:.1}%", ratio * 100.0))
+                            .unwrap_or_default(),
+                    ),
+                    ("ops_per_second", ops.into(), text(ops)),
+                ]
+            }
+            InfoSection::Replication => {
+                let role = info.get("role").copied().unwrap_or_default();
+                let replicas = number("connected_slaves");
+                // Seconds since the last interaction with the primary on a replica, and the
+                // largest lag of the replicas on a primary
+                let lag = if role == "slave" {
+                    number("master_last_io_seconds_ago")
+                } else {
+                    info.iter()
+                        .filter(|(name, _)| {
+                            name.strip_prefix("slave")
+                                .is_some_and(|id| id.bytes().all(|b| b.is_ascii_digit()))
+                        })
+                        .filter_map(|(_, details)| {
+                            details
+                                .split(',')
+                                .find_map(|detail| detail.strip_prefix("lag="))
+                                .and_then(|lag| lag.parse::<u64>().ok())
+                        })
+                        .max()
+                };
+                vec![
+                    ("role", role.into(), role.to_owned()),
+                    ("replicas", replicas.into(), text(replicas)),
+                    ("lag_seconds", lag.into(), text(lag)),
+                ]
+            }
+            InfoSection::Keyspace => {
+                let mut dbs: Vec<(&str, u64)> = info
+                    .iter()
+                    .filter(|(name, _)| {
+                        name.strip_prefix("db")
+                            .is_some_and(|id| id.bytes().all(|b| b.is_ascii_digit()))
+                    })
+                    .filter_map(|(name, details)| {
+                        let keys = details
+                            .split(',')
+                            .find_map(|detail| detail.strip_prefix("keys="))?;
+                        Some((*name, keys.parse().ok()?))
+                    })
+                    .collect();
+                dbs.sort_by_key(|(name, _)| (name.len(), *name));
+                let text = dbs
+                    .iter()
+                    .map(|(name, keys)| format!("{name}={keys}"))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let json = dbs
+                    .into_iter()
+                    .map(|(name, keys)| (name.to_owned(), keys.into()))
+                    .collect::<serde_json::Map<_, _>>();
+                vec![("keys", json.into(), text)]
+            }
+        }
+    }
+}
+
+/// Parse the "field:value" lines of an INFO reply, skipping section headers
+fn parse_info(reply: &str) -> HashMap<&str, &str> {
+    reply
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.trim_end().split_once(':'))
+        .collect()
+}
+
+async fn info(
+    client: &Client,
+    sections: &[InfoSection],
+    output: OutputFormat,
+) -> color_eyre::Result<()> {
+    let sections = if sections.is_empty() {
+        &InfoSection::ALL[..]
+    } else {
+        sections
+    };
+    let kind = match sections {
+        [section] => section.kind(),
+        _ => InfoKind::All,
+    };
+
+    let mut rows = Vec::new();
+    for node in primary_nodes(client) {
+        let node_client = client.with_options(&fred::types::config::Options {
+            cluster_node: node.clone(),
+            ..Default::default()
+        });
+        let reply: String = node_client.info(Some(kind.clone())).await?;
+        let info = parse_info(&reply);
+        let fields: Vec<_> = sections
+            .iter()
+            .flat_map(|section| section.summary(&info))
+            .collect();
+        rows.push((node.map(|server| server.to_string()), fields));
+    }
+
+    let mut header: Vec<&str> = match rows.first() {
+        Some((_, fields)) => fields.iter().map(|(name, _, _)| *name).collect(),
+        None => Vec::new(),
+    };
+    if client.is_clustered() {
+        header.insert(0, "node");
+    }
+    let mut out = RecordSink::new(std::io::stdout().lock(), output);
+    match output {
+        OutputFormat::Plain => {
+            let mut b = tabled::builder::Builder::with_capacity(rows.len() + 1, header.len());
+            b.push_record(header);
+            for (node, fields) in &rows {
+                b.push_record(
+                    node.iter()
+                        .cloned()
+                        .chain(fields.iter().map(|(_, _, text)| text.clone())),
+                );
+            }
+            let mut table = b.build();
+            table.with(tabled::settings::Style::psql());
+            writeln!(out.text(), "{table}")?;
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            for (index, (node, fields)) in rows.iter().enumerate() {
+                let mut record: serde_json::Map<_, _> = fields
+                    .iter()
+                    .map(|(name, value, _)| (name.to_string(), value.clone()))
+                    .collect();
+                if let Some(node) = node {
+                    record.insert("node".to_owned(), node.clone().into());
+                }
+                write_json_record(out.text(), output, index, &record.into())?;
+            }
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            out.write_csv(&header)?;
+            for (node, fields) in &rows {
+                out.write_csv(node.iter().cloned().chain(fields.iter().map(
+                    |(_, value, text)| match value {
+                        serde_json::Value::Null => String::new(),
+                        serde_json::Value::Number(number) => number.to_string(),
+                        _ => text.clone(),
+                    },
+                )))?;
+            }
+        }
+    }
+    let _ = out.finish(output, rows.len())?;
+    Ok(())
+}
+
+/// The notify-keyspace-events class of a keyspace event
+fn event_class(event: &str) -> char {
+    match event {
+        "expired" => 'x',
+        "evicted" => 'e',
+        "new" => 'n',
+        "set" | "setrange" | "incrby" | "incrbyfloat" | "append" => '$',
+        "rpush" | "rpop" => 'l',
+        _ => match event.as_bytes().first() {
+            Some(b'l') => 'l',
+            Some(b's') => 's',
+            Some(b'h') => 'h',
+            Some(b'z') => 'z',
+            Some(b'x') => 't',
+            _ => 'g',
+        },
+    }
+}
+
+/// The flags missing from `current` for notify-keyspace-events to publish `events` on keyevent
+/// channels, or every event on keyspace channels if `events` is empty
+fn missing_notification_flags(current: &str, events: &[String]) -> String {
+    let mut needed = vec![if events.is_empty() { 'K' } else { 'E' }];
+    if events.is_empty() {
+        needed.push('A');
+    } else {
+        needed.extend(events.iter().map(|event| event_class(event)));
+    }
+    let all_classes = current.contains('A');
+    let mut missing = String::new();
+    for flag in needed {
+        let covered = current.contains(flag) || (all_classes && "g$lshzxet".contains(flag));
+        if !covered && !missing.contains(flag) {
+            missing.push(flag);
+        }
+    }
+    missing
+}
+
+async fn watch_events(
+    client: &Client,
+    pattern: &str,
+    events: &[String],
+    enable_notifications: bool,
+    duration: Option<Duration>,
+    output: OutputFormat,
+) -> color_eyre::Result<()> {
+    let db = client.client_config().database.unwrap_or(0);
+    // Notifications are configured and published by each node
+    let nodes: Vec<_> = primary_nodes(client)
+        .into_iter()
+        .map(|node| {
+            client.with_options(&fred::types::config::Options {
+                cluster_node: node,
+                ..Default::default()
+            })
+        })
+        .collect();
+    let mut restore = Vec::new();
+    for node in &nodes {
+        let config: HashMap<String, String> = node.config_get("notify-keyspace-events").await?;
+        let current = config
+            .get("notify-keyspace-events")
+            .cloned()
+            .unwrap_or_default();
+        let missing = missing_notification_flags(&current, events);
+        if missing.is_empty() {
+            continue;
+        }
+        if enable_notifications {
+            node.config_set("notify-keyspace-events", format!("{current}{missing}"))
+                .await?;
+            restore.push((node, current));
+        } else {
+            eprintln!(
+                "Warning: notify-keyspace-events is {current:?} and lacks {missing:?}, some events \
+                 won't be published (see --enable-notifications)"
+            );
+            break;
+        }
+    }
+
+    let result = print_events(client, &nodes, db, pattern, events, duration, output).await;
+    if !restore.is_empty() {
+        for (node, flags) in restore {
+            node.config_set("notify-keyspace-events", flags).await?;
+        }
+        eprintln!("Restored notify-keyspace-events");
+    }
+    result
+}
+
+async fn print_events(
+    client: &Client,
+    nodes: &[fred::clients::WithOptions<Client>],
+    db: u8,
+    pattern: &str,
+    events: &[String],
+    duration: Option<Duration>,
+    output: OutputFormat,
+) -> color_eyre::Result<()> {
+    // Keyevent channels name the event and carry the key, keyspace channels the other way round
+    let keyspace_prefix = format!("__keyspace@{db}__:");
+    let channels: Vec<String> = if events.is_empty() {
+        vec![format!("{keyspace_prefix}{pattern}")]
+    } else {
+        events
+            .iter()
+            .map(|event| format!("__keyevent@{db}__:{event}"))
+            .collect()
+    };
+    let mut messages = client.message_rx();
+    for node in nodes {
+        node.psubscribe(channels.clone()).await?;
+    }
+
+    let deadline = duration.map(|duration| tokio::time::Instant::now() + duration);
+    let mut out = RecordSink::new(std::io::stdout().lock(), output);
+    out.write_csv(["timestamp", "event", "key"])?;
+    let mut received = 0;
+    loop {
+        let message = tokio::select! {
+            message = messages.recv() => message,
+            _ = sleep_until(deadline) => break,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let message = match message {
+            Ok(message) => message,
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                eprintln!("Warning: {skipped} events were dropped, the output is too slow");
+                continue;
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+        };
+        let payload = message.value.as_bytes().unwrap_or_default().to_vec();
+        let (event, key) = match message.channel.strip_prefix(keyspace_prefix.as_str()) {
+            Some(key) => (payload, key.as_bytes().to_vec()),
+            None => match message.channel.rsplit_once(':') {
+                Some((_, event)) => (event.as_bytes().to_vec(), payload),
+                None => continue,
+            },
+        };
+        if !glob_match(pattern.as_bytes(), &key) {
+            continue;
+        }
+        let time = humantime::format_rfc3339_millis(std::time::SystemTime::now()).to_string();
+        let event = String::from_utf8_lossy(&event);
+        match output {
+            OutputFormat::Plain => {
+                let key = escape_plain(&String::from_utf8_lossy(&key));
+                writeln!(out.text(), "{time} {event} {key}")?;
+            }
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                out.write_csv([time.as_bytes(), event.as_bytes(), &key])?;
+            }
+            _ => {
+                let record = serde_json::json!({
+                    "timestamp": time,
+                    "event": event,
+                    "key": Encoding::Utf8.json(&key),
+                });
+                write_json_record(out.text(), output, received, &record)?;
+            }
+        }
+        out.flush()?;
+        received += 1;
+    }
+    let _ = out.finish(output, received)?;
+    Ok(())
+}
+
+/// Scan the fields of a hash matching a pattern, with their values
+///
+/// Pages are passed to `page` as they arrive unless the fields are sorted, in which case they
+/// are all collected first and passed at once.
+async fn scan_fields(
+    client: &Client,
+    options: &FieldScanOptions,
+    mut page: impl FnMut(Vec<(Key, Value)>) -> color_eyre::Result<()>,
+) -> color_eyre::Result<()> {
+    let mut pages = std::pin::pin!(client.hscan(
+        options.key.as_str(),
+        options.pattern.as_str(),
+        Some(options.count),
+    ));
+    let collect = options.sorted || options.reversed;
+    let mut collected = Vec::new();
+    let mut found = 0;
+    while let Some(mut result) = pages.next().await.transpose()? {
+        let Some(fields) = result.take_results() else {
+            continue;
+        };
+        let mut fields: Vec<_> = fields.inner().into_iter().collect();
+        if let Some(limit) = options.limit {
+            fields.truncate(limit - found);
+        }
+        found += fields.len();
+        if collect {
+            collected.extend(fields);
+        } else {
+            page(fields)?;
+        }
+        if options.limit.is_some_and(|limit| found >= limit) {
+            result.cancel();
+            break;
+        }
+    }
+    if collect {
+        if options.sorted {
+            collected.sort_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+        }
+        if options.reversed {
+            collected.reverse();
+        }
+        page(collected)?;
+    }
+    Ok(())
+}
+
+async fn hscan_fields(
+    client: &Client,
+    options: &FieldScanOptions,
+    with_values: bool,
+    output: OutputFormat,
+) -> color_eyre::Result<()> {
+    let mut out = RecordSink::new(std::io::stdout().lock(), output);
+    if with_values {
+        out.write_csv(["field", "value"])?;
+    } else {
+        out.write_csv(["field"])?;
+    }
+    let mut written = 0;
+    scan_fields(client, options, |fields| {
+        for (field, value) in fields {
+            let value = value_bytes(&value);
+            match output {
+                OutputFormat::Plain => {
+                    let out = out.text();
+                    out.write_all(field.as_bytes())?;
+                    if with_values {
+                        out.write_all(b"\t")?;
+                        out.write_all(&value)?;
+                    }
+                    out.write_all(b"\n")?;
+                }
+                OutputFormat::Csv | OutputFormat::Tsv if with_values => {
+                    out.write_csv([field.as_bytes(), &value])?;
+                }
+                OutputFormat::Csv | OutputFormat::Tsv => out.write_csv([field.as_bytes()])?,
+                _ => {
+                    let mut record = serde_json::json!({
+                        "field": Encoding::Utf8.json(field.as_bytes()),
+                    });
+                    if with_values {
+                        record["value"] = Encoding::Utf8.json(&value);
+                    }
+                    write_json_record(out.text(), output, written, &record)?;
+                }
+            }
+            written += 1;
+        }
+        out.flush()?;
+        Ok(())
+    })
+    .await?;
+    let _ = out.finish(output, written)?;
+    Ok(())
+}
+
+async fn delete_fields(
+    client: &Client,
+    field_options: &FieldScanOptions,
+    options: &BatchOptions,
+) -> color_eyre::Result<()> {
+    let mut fields = Vec::new();
+    scan_fields(client, field_options, |page| {
+        fields.extend(page.into_iter().map(|(field, _)| field));
+        Ok(())
+    })
+    .await?;
+    let key = &field_options.key;
+    if options.dry_run {
+        println!(
+            "{} fields of {key} matched and would be deleted",
+            fields.len()
+        );
+        print_sample(&fields);
+        return Ok(());
+    }
+    if !fields.is_empty() && !options.yes {
+        eprintln!(
+            "Pattern {} matches {} fields of {key}, such as:",
+            field_options.pattern,
+            fields.len()
+        );
+        print_sample(&fields);
+        if !ask_confirmation("delete them")? {
+            return Err(eyre!("Deletion aborted"));
+        }
+    }
+    let message = format!("Deleting fields of {key}");
+    let pb = keys_progress(fields.len(), message)?;
+    let mut pacing = Pacing::default();
+    let mut deleted = 0;
+    for chunk in fields.chunks(options.batch_size as usize) {
+        pacing.wait(options, chunk.len()).await;
+        let count: i64 = client.hdel(key.as_str(), chunk.to_vec()).await?;
+        deleted += count as usize;
+        pb.inc(chunk.len() as u64);
+    }
+    pb.finish();
+    eprintln!(
+        "Deleted {deleted} fields, {} no longer existed",
+        fields.len() - deleted
+    );
+    Ok(())
+}
+
+async fn collection_sizes(
+    client: &Client,
+    scan_options: &ScanOptions,
+    top: usize,
+    min_size: u64,
+) -> color_eyre::Result<()> {
+    let mut largest = TopKeys::new(top);
+    let mut totals = BTreeMap::<String, TypeTotals>::new();
+    let pb = stats_progress()?;
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    while let Some(keys) = scanner.next_page().await? {
+        pb.inc(keys.len() as u64);
+        let key_types = key_types(client, &keys).await?;
+        let pipe = client.pipeline();
+        let mut counted = Vec::with_capacity(keys.len());
+        for (key, key_type) in keys.into_iter().zip(key_types) {
+            // Strings have a length but no elements
+            let Some(parsed) = KeyType::from_reply(&key_type)
+                .filter(|parsed| !matches!(parsed, KeyType::String | KeyType::Json))
+            else {
+                continue;
+            };
+            parsed.queue_len(&pipe, &key).await?;
+            counted.push((key, key_type));
+        }
+        if counted.is_empty() {
+            continue;
+        }
+        let replies = pipe.try_all::<u64>().await;
+        for ((key, key_type), elements) in counted.into_iter().zip(replies) {
+            // Deleted or replaced since it was scanned
+            let Ok(elements) = elements else {
+                continue;
+            };
+            if elements < min_size {
+                continue;
+            }
+            let type_totals = totals.entry(key_type.clone()).or_default();
+            type_totals.keys += 1;
+            type_totals.elements += elements;
+            largest.push(elements, key, key_type);
+        }
+        pb.set_message(format!(
+            "{} collections",
+            totals.values().map(|t| t.keys).sum::<usize>()
+        ));
+    }
+    pb.finish_and_clear();
+
+    let largest = largest.into_sorted_vec();
+    let mut b = tabled::builder::Builder::with_capacity(largest.len() + 1, 3);
+    b.push_record(["key", "type", "elements"]);
+    for large_key in largest {
+        b.push_record([
+            large_key.key.as_str_lossy().into_owned(),
+            large_key.details,
+            large_key.score.to_string(),
+        ]);
+    }
+    let mut table = b.build();
+    table.with(tabled::settings::Style::psql());
+    println!("{table}");
+
+    let mut b = tabled::builder::Builder::with_capacity(totals.len() + 1, 4);
+    b.push_record(["type", "keys", "elements", "average"]);
+    for (key_type, totals) in &totals {
+        b.push_record([
+            key_type.clone(),
+            totals.keys.to_string(),
+            totals.elements.to_string(),
+            (totals.elements / totals.keys as u64).to_string(),
+        ]);
+    }
+    let mut table = b.build();
+    table.with(tabled::settings::Style::psql());
+    println!("{table}");
+    Ok(())
+}
+
+struct StreamGroup {
+    name: String,
+    consumers: u64,
+    pending: u64,
+    last_delivered: String,
+    /// Entries not yet delivered to the group, reported since Redis 7.0
+    lag: Option<u64>,
+}
+
+impl StreamGroup {
+    fn from_reply(reply: Value) -> FredResult<Self> {
+        let mut fields: HashMap<String, Value> = reply.convert()?;
+        let mut number = |name: &str| fields.remove(name).and_then(|value| value.as_u64());
+        Ok(Self {
+            consumers: number("consumers").unwrap_or_default(),
+            pending: number("pending").unwrap_or_default(),
+            lag: number("lag"),
+            name: fields
+                .remove("name")
+                .and_then(|name| name.into_string())
+                .unwrap_or_default(),
+            last_delivered: fields
+                .remove("last-delivered-id")
+                .and_then(|id| id.into_string())
+                .unwrap_or_default(),
+        })
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "name": self.name,
+            "consumers": self.consumers,
+            "pending": self.pending,
+            "last_delivered_id": self.last_delivered,
+            "lag": self.lag,
+        })
+    }
+}
+
+struct StreamSummary {
+    key: Key,
+    length: u64,
+    first_id: Option<String>,
+    last_id: Option<String>,
+    groups: Vec<StreamGroup>,
+}
+
+impl StreamSummary {
+    fn from_replies(key: Key, stream: Value, groups: Value) -> FredResult<Self> {
+        let mut fields: HashMap<String, Value> = stream.convert()?;
+        // Entries are an ID followed by their fields, or nil for an empty stream
+        let mut entry_id = |name: &str| match fields.remove(name) {
+            Some(Value::Array(entry)) => entry.into_iter().next().and_then(|id| id.into_string()),
+            _ => None,
+        };
+        let first_id = entry_id("first-entry");
+        let last_id = entry_id("last-entry");
+        let groups: Vec<Value> = groups.convert()?;
+        Ok(Self {
+            key,
+            length: fields
+                .get("length")
+                .and_then(|length| length.as_u64())
+                .unwrap_or_default(),
+            first_id,
+            last_id,
+            groups: groups
+                .into_iter()
+                .map(StreamGroup::from_reply)
+                .collect::<FredResult<_>>()?,
+        })
+    }
+
+    fn pending(&self) -> u64 {
+        self.groups.iter().map(|group| group.pending).sum()
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "key": self.key.as_str_lossy(),
+            "length": self.length,
+            "first_id": self.first_id,
+            "first_time": self.first_id.as_deref().and_then(stream_id_time),
+            "last_id": self.last_id,
+            "last_time": self.last_id.as_deref().and_then(stream_id_time),
+            "pending": self.pending(),
+            "groups": self.groups.iter().map(StreamGroup::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// The time at which a stream entry was added, from the milliseconds in its ID
+fn stream_id_time(id: &str) -> Option<String> {
+    let millis: u64 = id
+        .split_once('-')
+        .map_or(id, |(millis, _)| millis)
+        .parse()
+        .ok()?;
+    let time = std::time::UNIX_EPOCH + Duration::from_millis(millis);
+    Some(humantime::format_rfc3339_millis(time).to_string())
+}
+
+async fn stream_info(
+    client: &Client,
+    scan_options: &ScanOptions,
+    lag_only: bool,
+    output: OutputFormat,
+) -> color_eyre::Result<()> {
+    let mut streams = Vec::new();
+    let pb = stats_progress()?;
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    while let Some(keys) = scanner.next_page().await? {
+        pb.inc(keys.len() as u64);
+        let pipe = client.pipeline();
+        for key in &keys {
+            pipe.xinfo_stream::<(), _>(key, false, None).await?;
+            pipe.xinfo_groups::<(), _>(key).await?;
+        }
+        let mut replies = pipe.try_all::<Value>().await.into_iter();
+        for key in keys {
+            let (Some(stream), Some(groups)) = (replies.next(), replies.next()) else {
+                break;
+            };
+            // Deleted since it was scanned
+            let (Ok(stream), Ok(groups)) = (stream, groups) else {
+                continue;
+            };
+            streams.push(StreamSummary::from_replies(key, stream, groups)?);
+        }
+        pb.set_message(format!("{} streams", streams.len()));
+    }
+    pb.finish_and_clear();
+
+    let mut out = RecordSink::new(std::io::stdout().lock(), output);
+    let text = |value: Option<u64>| value.map(|value| value.to_string()).unwrap_or_default();
+    let groups = streams
+        .iter()
+        .flat_map(|stream| stream.groups.iter().map(move |group| (&stream.key, group)));
+    let group_record = |key: &Key, group: &StreamGroup| {
+        [
+            key.as_str_lossy().into_owned(),
+            group.name.clone(),
+            group.consumers.to_string(),
+            group.pending.to_string(),
+            group.last_delivered.clone(),
+            text(group.lag),
+        ]
+    };
+    let group_header = [
+        "stream",
+        "group",
+        "consumers",
+        "pending",
+        "last delivered",
+        "lag",
+    ];
+    let stream_record = |stream: &StreamSummary| {
+        let first_time = stream.first_id.as_deref().and_then(stream_id_time);
+        let last_time = stream.last_id.as_deref().and_then(stream_id_time);
+        [
+            stream.key.as_str_lossy().into_owned(),
+            stream.length.to_string(),
+            stream.first_id.clone().unwrap_or_default(),
+            first_time.unwrap_or_default(),
+            stream.last_id.clone().unwrap_or_default(),
+            last_time.unwrap_or_default(),
+            stream.groups.len().to_string(),
+            stream.pending().to_string(),
+        ]
+    };
+    let stream_header = [
+        "key",
+        "length",
+        "first id",
+        "first time",
+        "last id",
+        "last time",
+        "groups",
+        "pending",
+    ];
+    let written = match output {
+        OutputFormat::Plain => {
+            if !lag_only {
+                let mut b = tabled::builder::Builder::with_capacity(streams.len() + 1, 8);
+                b.push_record(stream_header);
+                for stream in &streams {
+                    b.push_record(stream_record(stream));
+                }
+                let mut table = b.build();
+                table.with(tabled::settings::Style::psql());
+                writeln!(out.text(), "{table}")?;
+            }
+            let mut b = tabled::builder::Builder::new();
+            b.push_record(group_header);
+            for (key, group) in groups {
+                b.push_record(group_record(key, group));
+            }
+            let mut table = b.build();
+            table.with(tabled::settings::Style::psql());
+            writeln!(out.text(), "{table}")?;
+            0
+        }
+        OutputFormat::Json | OutputFormat::Jsonl if lag_only => {
+            let mut written = 0;
+            for (key, group) in groups {
+                let mut record = group.to_json();
+                record["stream"] = key.as_str_lossy().into();
+                write_json_record(out.text(), output, written, &record)?;
+                written += 1;
+            }
+            written
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            for (index, stream) in streams.iter().enumerate() {
+                write_json_record(out.text(), output, index, &stream.to_json())?;
+            }
+            streams.len()
+        }
+        OutputFormat::Csv | OutputFormat::Tsv if lag_only => {
+            out.write_csv(group_header)?;
+            for (key, group) in groups {
+                out.write_csv(group_record(key, group))?;
+            }
+            0
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            out.write_csv(stream_header)?;
+            for stream in &streams {
+                out.write_csv(stream_record(stream))?;
+            }
+            0
+        }
+    };
+    let _ = out.finish(output, written)?;
+    Ok(())
+}
+
+/// Random values for generated data, not meant to be unpredictable
+struct SeedRandom {
+    hasher: std::collections::hash_map::RandomState,
+    counter: u64,
+}
+
+impl SeedRandom {
+    fn new() -> Self {
+        Self {
+            hasher: std::collections::hash_map::RandomState::new(),
+            counter: 0,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        use std::hash::BuildHasher;
+
+        self.counter += 1;
+        self.hasher.hash_one(self.counter)
+    }
+
+    fn alphanumeric(&mut self, len: usize) -> String {
+        const CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+        (0..len)
+            .map(|_| CHARSET[(self.next_u64() % CHARSET.len() as u64) as usize] as char)
+            .collect()
+    }
+
+    /// A version 4 UUID
+    fn uuid(&mut self) -> String {
+        let high = (self.next_u64() & !0xf000) | 0x4000;
+        let low = (self.next_u64() & !(0b11 << 62)) | (0b10 << 62);
+        format!(
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            high >> 32,
+            (high >> 16) & 0xffff,
+            high & 0xffff,
+            low >> 48,
+            low & 0xffff_ffff_ffff
+        )
+    }
+}
+
+fn preview_seed(options: &SeedOptions) {
+    let mut random = SeedRandom::new();
+    if !options.key_template.is_unique() {
+        eprintln!("Warning: the key template has no {{seq}} or {{uuid}}, keys may be overwritten");
+    }
+    println!(
+        "{} {} keys would be written, such as:",
+        options.count,
+        options.value_type.name()
+    );
+    for seq in 0..options.count.min(5) {
+        println!("  {}", options.key_template.render(seq, &mut random));
+    }
+}
+
+async fn seed(client: &Client, options: &SeedOptions) -> color_eyre::Result<()> {
+    let mut random = SeedRandom::new();
+    if !options.key_template.is_unique() {
+        eprintln!("Warning: the key template has no {{seq}} or {{uuid}}, keys may be overwritten");
+    }
+
+    let message = format!("Writing {} keys", options.value_type.name());
+    let pb = keys_progress(options.count as usize, message)?;
+    let start = std::time::Instant::now();
+    let expiration = options
+        .ttl
+        .map(|ttl| Expiration::PX(ttl.as_millis() as i64));
+    let mut seq = 0;
+    while seq < options.count {
+        let batch = options.batch_size.min(options.count - seq);
+        let pipe = client.pipeline();
+        for _ in 0..batch {
+            let key = options.key_template.render(seq, &mut random);
+            seq += 1;
+            match options.value_type {
+                SeedType::String => {
+                    let value = random.alphanumeric(options.value_size);
+                    pipe.set::<(), _, _>(&key, value, expiration.clone(), None, false)
+                        .await?;
+                }
+                SeedType::Hash => {
+                    let fields: Vec<_> = (0..options.fields)
+                        .map(|field| {
+                            (
+                                format!("field{field}"),
+                                random.alphanumeric(options.value_size),
+                            )
+                        })
+                        .collect();
+                    pipe.hset::<(), _, _>(&key, fields).await?;
+                }
+                SeedType::Set => {
+                    let members: Vec<_> = (0..options.fields)
+                        .map(|_| random.alphanumeric(options.value_size))
+                        .collect();
+                    pipe.sadd::<(), _, _>(&key, members).await?;
+                }
+            }
+            if let (Some(ttl), SeedType::Hash | SeedType::Set) = (options.ttl, options.value_type) {
+                pipe.pexpire::<(), _>(&key, ttl.as_millis() as i64, None)
+                    .await?;
+            }
+        }
+        pipe.all::<()>().await?;
+        pb.inc(batch);
+    }
+    pb.finish();
+    let elapsed = start.elapsed();
+    eprintln!(
+        "Wrote {} keys in {}, {:.0} keys/s",
+        options.count,
+        humantime::format_duration(Duration::from_millis(elapsed.as_millis() as u64)),
+        options.count as f64 / elapsed.as_secs_f64()
+    );
+    Ok(())
+}
+
+async fn check_exists(
+    client: &Client,
+    key_file: &KeyFileOptions,
+    print: Option<Existence>,
+) -> color_eyre::Result<()> {
+    let keys = key_file.read_keys()?;
+    let pb = if print.is_some() {
+        ProgressBar::hidden()
+    } else {
+        keys_progress(keys.len(), "Checking keys".to_owned())?
+    };
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    let mut present = 0;
+    for chunk in keys.chunks(1000) {
+        // The pipeline routes each EXISTS to the node owning its slot
+        let pipe = client.pipeline();
+        for key in chunk {
+            let _: () = pipe.exists(key).await?;
+        }
+        let replies: Vec<i64> = pipe.all().await?;
+        for (key, exists) in chunk.iter().zip(replies) {
+            let existence = if exists > 0 {
+                present += 1;
+                Existence::Present
+            } else {
+                Existence::Missing
+            };
+            if print == Some(existence) {
+                out.write_all(key.as_bytes())?;
+                out.write_all(&[key_file.terminator()])?;
+            }
+        }
+        pb.inc(chunk.len() as u64);
+    }
+    pb.finish_and_clear();
+    out.flush()?;
+    let summary = format!("{present} present, {} missing", keys.len() - present);
+    if print.is_some() {
+        eprintln!("{summary}");
+    } else {
+        println!("{summary}");
+    }
+    Ok(())
+}
+
+async fn del_from_file(
+    client: &Client,
+    key_file: &KeyFileOptions,
+    options: &BatchOptions,
+    unlink: bool,
+) -> color_eyre::Result<()> {
+    let keys = key_file.read_keys()?;
+    let source = match &key_file.file {
+        Some(path) if path != Path::new("-") => path.display().to_string(),
+        _ => "stdin".to_owned(),
+    };
+    if options.dry_run {
+        println!("{} keys listed in {source} would be deleted", keys.len());
+        print_sample(&keys);
+        return Ok(());
+    }
+    if !keys.is_empty() && !options.yes {
+        eprintln!("{source} lists {} keys, such as:", keys.len());
+        print_sample(&keys);
+        if !ask_confirmation("delete them")? {
+            return Err(eyre!("Deletion aborted"));
+        }
+    }
+    let supported = client
+        .server_version()
+        .is_none_or(|version| version.major >= 4);
+    if unlink && !supported {
+        eprintln!("Warning: UNLINK requires Redis 4.0, falling back to DEL");
+    }
+    let unlink = unlink && supported;
+    let pb = keys_progress(keys.len(), format!("Deleting keys listed in {source}"))?;
+    let mut pacing = Pacing::default();
+    let mut deleted = 0;
+    for batch in delete_batches(&keys, options.batch_size as usize, client.is_clustered()) {
+        pacing.wait(options, batch.keys.len()).await;
+        deleted += delete_batch(client, &batch.commands, unlink).await?;
+        pb.inc(batch.keys.len() as u64);
+    }
+    pb.finish();
+    eprintln!(
+        "Deleted {deleted} keys, {} were already missing",
+        keys.len() as u64 - deleted
+    );
+    Ok(())
+}
+
+struct SetRecord {
+    key: Vec<u8>,
+    value: Vec<u8>,
+    ttl: Option<Duration>,
+}
+
+impl SetRecord {
+    fn from_fields(fields: &[&[u8]], encoding: Encoding) -> color_eyre::Result<Self> {
+        let (key, value, ttl) = match fields {
+            [key, value] | [key, value, []] => (key, value, None),
+            [key, value, ttl] => (key, value, Some(parse_ttl(std::str::from_utf8(ttl)?)?)),
+            _ => return Err(eyre!("expected 2 or 3 columns, found {}", fields.len())),
+        };
+        let value = match encoding {
+            Encoding::Utf8 | Encoding::Raw => value.to_vec(),
+            _ => encoding.decode(std::str::from_utf8(value)?)?,
+        };
+        Ok(Self {
+            key: key.to_vec(),
+            value,
+            ttl,
+        })
+    }
+
+    fn from_json(line: &str, encoding: Encoding) -> color_eyre::Result<Self> {
+        let record: serde_json::Value = serde_json::from_str(line)?;
+        let key = record["key"]
+            .as_str()
+            .ok_or_else(|| eyre!("record without a key"))?;
+        let value = record["value"]
+            .as_str()
+            .ok_or_else(|| eyre!("record without a value"))?;
+        let ttl = match &record["ttl"] {
+            serde_json::Value::Null => None,
+            serde_json::Value::Number(millis) => Some(Duration::from_millis(
+                millis
+                    .as_u64()
+                    .ok_or_else(|| eyre!("invalid ttl {millis}"))?,
+            )),
+            serde_json::Value::String(ttl) => Some(parse_ttl(ttl)?),
+            ttl => return Err(eyre!("invalid ttl {ttl}")),
+        };
+        Ok(Self {
+            key: key.as_bytes().to_vec(),
+            value: encoding.decode(value)?,
+            ttl,
+        })
+    }
+}
+
+/// A TTL in milliseconds, or a human readable duration
+fn parse_ttl(ttl: &str) -> color_eyre::Result<Duration> {
+    match ttl.parse() {
+        Ok(millis) => Ok(Duration::from_millis(millis)),
+        Err(_) => Ok(humantime::parse_duration(ttl)?),
+    }
+}
+
+/// The line number and error of a malformed record
+type Malformed = (u64, color_eyre::Report);
+
+/// Read the records of a file, with the malformed ones
+fn read_set_records(
+    input: Box<dyn BufRead>,
+    format: RecordFormat,
+    encoding: Encoding,
+) -> color_eyre::Result<(Vec<SetRecord>, Vec<Malformed>)> {
+    let mut records = Vec::new();
+    let mut malformed = Vec::new();
+    let delimiter = match format {
+        RecordFormat::Tsv => b'\t',
+        RecordFormat::Csv => b',',
+        RecordFormat::Jsonl => {
+            for (index, line) in input.lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match SetRecord::from_json(&line, encoding) {
+                    Ok(record) => records.push(record),
+                    Err(error) => malformed.push((index as u64 + 1, error)),
+                }
+            }
+            return Ok((records, malformed));
+        }
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(input);
+    for row in reader.byte_records() {
+        let row = match row {
+            Ok(row) => row,
+            Err(error) if error.is_io_error() => return Err(error.into()),
+            Err(error) => {
+                let line = error.position().map_or(0, |position| position.line());
+                malformed.push((line, error.into()));
+                continue;
+            }
+        };
+        let line = row.position().map_or(0, |position| position.line());
+        if line == 1 && row.get(0) == Some(b"key".as_slice()) {
+            continue;
+        }
+        let fields: Vec<&[u8]> = row.iter().collect();
+        match SetRecord::from_fields(&fields, encoding) {
+            Ok(record) => records.push(record),
+            Err(error) => malformed.push((line, error)),
+        }
+    }
+    Ok((records, malformed))
+}
+
+async fn set_from_file(client: &Client, options: &SetFromFileOptions) -> color_eyre::Result<()> {
+    let input = open_input(options.file.as_deref())?;
+    let (records, malformed) = read_set_records(input, options.format, options.encoding)?;
+    for (line, error) in malformed.iter().take(10) {
+        eprintln!("Skipping line {line}: {error}");
+    }
+    if malformed.len() > 10 {
+        eprintln!("... and {} more malformed lines", malformed.len() - 10);
+    }
+    let prefix = options.key_prefix.as_deref().unwrap_or_default().as_bytes();
+    let keys: Vec<Key> = records
+        .iter()
+        .map(|record| Key::from([prefix, &record.key].concat().into_boxed_slice()))
+        .collect();
+    let batch = &options.batch;
+    if batch.dry_run {
+        println!(
+            "{} keys would be written, {} lines are malformed",
+            keys.len(),
+            malformed.len()
+        );
+        print_sample(&keys);
+        return Ok(());
+    }
+    if !keys.is_empty() && !batch.yes {
+        eprintln!("About to write {} keys, such as:", keys.len());
+        print_sample(&keys);
+        if !ask_confirmation("write them")? {
+            return Err(eyre!("Load aborted"));
+        }
+    }
+
+    let pb = keys_progress(keys.len(), "Writing keys".to_owned())?;
+    let start = std::time::Instant::now();
+    let set_options = options.nx.then_some(SetOptions::NX);
+    let mut pacing = Pacing::default();
+    let mut written = 0;
+    for (keys, records) in keys
+        .chunks(batch.batch_size as usize)
+        .zip(records.chunks(batch.batch_size as usize))
+    {
+        pacing.wait(batch, keys.len()).await;
+        let pipe = client.pipeline();
+        for (key, record) in keys.iter().zip(records) {
+            let expiration = record.ttl.map(|ttl| Expiration::PX(ttl.as_millis() as i64));
+            let _: () = pipe
+                .set(
+                    key,
+                    record.value.as_slice(),
+                    expiration,
+                    set_options.clone(),
+                    false,
+                )
+                .await?;
+        }
+        // SET NX replies nil for the keys that already exist
+        let replies: Vec<Option<String>> = pipe.all().await?;
+        written += replies.iter().flatten().count();
+        pb.inc(keys.len() as u64);
+    }
+    pb.finish();
+    let elapsed = start.elapsed();
+    eprintln!(
+        "Wrote {written} keys in {}, {:.0} keys/s",
+        humantime::format_duration(Duration::from_millis(elapsed.as_millis() as u64)),
+        keys.len() as f64 / elapsed.as_secs_f64()
+    );
+    if keys.len() > written {
+        eprintln!("{} keys already existed", keys.len() - written);
+    }
+    if !malformed.is_empty() {
+        eprintln!("{} malformed lines were skipped", malformed.len());
+    }
+    Ok(())
+}
+
+async fn no_ttl(
+    client: &Client,
+    scan_options: &ScanOptions,
+    options: &NoTtlOptions,
+) -> color_eyre::Result<()> {
+    let output = options.output;
+    let listing = !options.count && !options.by_prefix;
+    let mut out = RecordSink::new(std::io::stdout().lock(), output);
+    if listing {
+        if options.with_size {
+            out.write_csv(["key", "size"])?;
+        } else {
+            out.write_csv(["key"])?;
+        }
+    }
+    let pb = if listing {
+        ProgressBar::hidden()
+    } else {
+        stats_progress()?
+    };
+
+    // Sorting needs all the keys, which are then measured in pages
+    let (mut scanner, mut sorted_keys) = if scan_options.sorted || scan_options.reversed {
+        (None, Some(scan(client, scan_options).await?.into_iter()))
+    } else {
+        (Some(KeyScanner::new(client, scan_options)?), None)
+    };
+    let mut groups = BTreeMap::<String, (u64, u64)>::new();
+    let (mut total, mut total_bytes, mut written) = (0, 0, 0);
+    loop {
+        let keys = match (&mut scanner, &mut sorted_keys) {
+            (Some(scanner), _) => scanner.next_page().await?,
+            (_, Some(keys)) => {
+                Some(keys.by_ref().take(1000).collect::<Vec<_>>()).filter(|keys| !keys.is_empty())
+            }
+            (None, None) => None,
+        };
+        let Some(keys) = keys else {
+            break;
+        };
+        pb.inc(keys.len() as u64);
+        let sizes: Vec<Option<u64>> = if options.with_size {
+            let pipe = client.pipeline();
+            for key in &keys {
+                pipe.memory_usage::<(), _>(key, None).await?;
+            }
+            pipe.try_all::<Option<u64>>()
+                .await
+                .into_iter()
+                .map(|size| size.ok().flatten())
+                .collect()
+        } else {
+            vec![None; keys.len()]
+        };
+        for (key, size) in keys.iter().zip(sizes) {
+            total += 1;
+            total_bytes += size.unwrap_or_default();
+            if options.by_prefix {
+                let group = key_group(key, "", &options.delimiter, options.depth as usize, &[])
+                    .unwrap_or_default();
+                let (count, bytes) = groups.entry(group).or_default();
+                *count += 1;
+                *bytes += size.unwrap_or_default();
+            }
+            if !listing {
+                continue;
+            }
+            let size = size.map(|size| size.to_string()).unwrap_or_default();
+            match output {
+                OutputFormat::Plain => {
+                    let out = out.text();
+                    out.write_all(key.as_bytes())?;
+                    if options.with_size {
+                        write!(out, "\t{size}")?;
+                    }
+                    writeln!(out)?;
+                }
+                OutputFormat::Csv | OutputFormat::Tsv if options.with_size => {
+                    out.write_csv([key.as_bytes(), size.as_bytes()])?;
+                }
+                OutputFormat::Csv | OutputFormat::Tsv => out.write_csv([key.as_bytes()])?,
+                _ => {
+                    let mut record = serde_json::json!({ "key": key.as_str_lossy() });
+                    if options.with_size {
+                        record["size"] = size.parse::<u64>().ok().into();
+                    }
+                    write_json_record(out.text(), output, written, &record)?;
+                }
+            }
+            written += 1;
+        }
+        out.flush()?;
+    }
+    pb.finish_and_clear();
+
+    if options.count {
+        if options.with_size {
+            println!(
+                "{total} keys without TTL, using {}",
+                HumanBytes(total_bytes)
+            );
+        } else {
+            println!("{total}");
+        }
+        return Ok(());
+    }
+    if options.by_prefix {
+        let mut groups: Vec<_> = groups.into_iter().collect();
+        groups.sort_by(|(a, (a_count, _)), (b, (b_count, _))| b_count.cmp(a_count).then(a.cmp(b)));
+        let mut header = vec!["group", "keys"];
+        if options.with_size {
+            header.push("memory");
+        }
+        match output {
+            OutputFormat::Plain => {
+                let mut b = tabled::builder::Builder::with_capacity(groups.len() + 2, 3);
+                b.push_record(header);
+                let totals = ("(total)".to_owned(), (total, total_bytes));
+                for (group, (count, bytes)) in groups.iter().chain([&totals]) {
+                    let mut record = vec![group.clone(), count.to_string()];
+                    if options.with_size {
+                        record.push(HumanBytes(*bytes).to_string());
+                    }
+                    b.push_record(record);
+                }
+                let mut table = b.build();
+                table.with(tabled::settings::Style::psql());
+                writeln!(out.text(), "{table}")?;
+            }
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                out.write_csv(&header)?;
+                for (group, (count, bytes)) in &groups {
+                    let mut record = vec![group.clone(), count.to_string()];
+                    if options.with_size {
+                        record.push(bytes.to_string());
+                    }
+                    out.write_csv(record)?;
+                }
+            }
+            _ => {
+                for (index, (group, (count, bytes))) in groups.iter().enumerate() {
+                    let mut record = serde_json::json!({ "group": group, "keys": count });
+                    if options.with_size {
+                        record["memory"] = (*bytes).into();
+                    }
+                    write_json_record(out.text(), output, index, &record)?;
+                }
+                written = groups.len();
+            }
+        }
+    }
+    let _ = out.finish(output, written)?;
+    if listing {
+        eprintln!("{total} keys without TTL");
+    }
+    Ok(())
+}
+
+/// Describe the spread of a count over nodes, from the ratio between the largest and smallest
+fn imbalance(counts: &[(String, u64)]) -> Option<String> {
+    let (max_node, max) = counts.iter().max_by_key(|(_, count)| *count)?;
+    let (min_node, min) = counts.iter().min_by_key(|(_, count)| *count)?;
+    Some(if *min == 0 {
+        format!("{max_node} has {max}, {min_node} has none")
+    } else {
+        format!(
+            "max/min ratio {:.2}, {max_node} has {max} and {min_node} {min}",
+            *max as f64 / *min as f64
+        )
+    })
+}
+
+async fn cluster_slots(client: &Client, pattern: Option<&str>) -> color_eyre::Result<()> {
+    let routing = node_routing(client)?;
+    let mut nodes = BTreeMap::<Server, (String, Vec<(u16, u16)>)>::new();
+    for range in routing.slots() {
+        let (id, ranges) = nodes.entry(range.primary.clone()).or_default();
+        *id = range.id.to_string();
+        ranges.push((range.start, range.end));
+    }
+
+    let mut matching = HashMap::<Server, u64>::new();
+    if let Some(pattern) = pattern {
+        let pb = stats_progress()?;
+        let mut pages = scan_stream(client, pattern, 10_000, None)?;
+        while let Some(mut page) = pages.next().await.transpose()? {
+            let keys = page.take_results().unwrap_or_default();
+            pb.inc(keys.len() as u64);
+            for key in keys {
+                let slot = fred::util::redis_keyslot(key.as_bytes());
+                if let Some(server) = routing.get_server(slot) {
+                    *matching.entry(server.clone()).or_default() += 1;
+                }
+            }
+        }
+        pb.finish_and_clear();
+    }
+
+    let mut header = vec!["id", "address", "replicas", "slot ranges", "slots", "keys"];
+    if pattern.is_some() {
+        header.push("matching");
+    }
+    let mut b = tabled::builder::Builder::with_capacity(nodes.len() + 1, header.len());
+    b.push_record(header);
+    let mut key_counts = Vec::with_capacity(nodes.len());
+    let mut matching_counts = Vec::with_capacity(nodes.len());
+    for (server, (id, mut ranges)) in nodes {
+        let node = client.with_cluster_node(server.clone());
+        let keys: u64 = node.dbsize().await?;
+        let info: String = node.info(Some(InfoKind::Replication)).await?;
+        let replicas = parse_info(&info)
+            .get("connected_slaves")
+            .copied()
+            .unwrap_or_default()
+            .to_owned();
+        ranges.sort_unstable();
+        let slots: u64 = ranges
+            .iter()
+            .map(|(start, end)| u64::from(end - start) + 1)
+            .sum();
+        let ranges = ranges
+            .iter()
+            .map(|(start, end)| {
+                if start == end {
+                    start.to_string()
+                } else {
+                    format!("{start}-{end}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut record = vec![
+            id,
+            server.to_string(),
+            replicas,
+            ranges,
+            slots.to_string(),
+            keys.to_string(),
+        ];
+        key_counts.push((server.to_string(), keys));
+        if pattern.is_some() {
+            let count = matching.get(&server).copied().unwrap_or_default();
+            record.push(count.to_string());
+            matching_counts.push((server.to_string(), count));
+        }
+        b.push_record(record);
+    }
+    let mut table = b.build();
+    table.with(tabled::settings::Style::psql());
+    println!("{table}");
+    if let Some(imbalance) = imbalance(&key_counts) {
+        println!("Keys: {imbalance}");
+    }
+    if let Some(imbalance) = imbalance(&matching_counts) {
+        println!("Matching keys: {imbalance}");
+    }
+    Ok(())
+}
+
+/// Percentiles reported by the latency command, as a name and a percentage
+const LATENCY_PERCENTILES: [(&str, f64); 3] = [("p50", 50.0), ("p95", 95.0), ("p99", 99.0)];
+
+/// A latency event recorded by the server
+struct LatencyEvent {
+    name: String,
+    time: i64,
+    latest_ms: i64,
+    max_ms: i64,
+    /// Timestamps and latencies of the recent occurrences, from LATENCY HISTORY
+    history: Vec<(i64, i64)>,
+}
+
+async fn latency_events(
+    node: &fred::clients::WithOptions<Client>,
+) -> color_eyre::Result<(Vec<LatencyEvent>, String)> {
+    let latest: Vec<Vec<Value>> = node
+        .custom(latency_command(), vec![Value::from("LATEST")])
+        .await?;
+    let mut events = Vec::with_capacity(latest.len());
+    for event in latest {
+        let number = |index: usize| event.get(index).and_then(Value::as_i64).unwrap_or_default();
+        let name = event
+            .first()
+            .and_then(|name| name.as_string())
+            .unwrap_or_default();
+        let history: Vec<(i64, i64)> = node
+            .custom(
+                latency_command(),
+                vec![Value::from("HISTORY"), name.clone().into()],
+            )
+            .await?;
+        events.push(LatencyEvent {
+            time: number(1),
+            latest_ms: number(2),
+            max_ms: number(3),
+            name,
+            history,
+        });
+    }
+    let doctor: String = node
+        .custom(latency_command(), vec![Value::from("DOCTOR")])
+        .await?;
+    Ok((events, doctor))
+}
+
+async fn latency(
+    client: &Client,
+    interval: Duration,
+    duration: Duration,
+    server_events: bool,
+    output: OutputFormat,
+) -> color_eyre::Result<()> {
+    let nodes: Vec<_> = primary_nodes(client)
+        .into_iter()
+        .map(|node| {
+            let name = node.as_ref().map(|server| server.to_string());
+            let node = client.with_options(&fred::types::config::Options {
+                cluster_node: node,
+                ..Default::default()
+            });
+            (name, node)
+        })
+        .collect();
+    let mut samples = vec![Vec::new(); nodes.len()];
+    let pb = ProgressBar::new(duration.as_millis() as u64).with_style(
+        ProgressStyle::with_template("{spinner} Pinging [{bar:40}] {elapsed}/{duration}")?,
+    );
+    let start = tokio::time::Instant::now();
+    let deadline = start + duration;
+    let mut ticks = tokio::time::interval(interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            _ = tokio::time::sleep_until(deadline) => break,
+        }
+        for ((_, node), samples) in nodes.iter().zip(&mut samples) {
+            let sent = std::time::Instant::now();
+            let _: String = node.ping(None).await?;
+            samples.push(sent.elapsed());
+        }
+        pb.set_position(start.elapsed().as_millis() as u64);
+    }
+    pb.finish_and_clear();
+
+    let micros = |duration: Duration| duration.as_micros() as u64;
+    let mut rows = Vec::with_capacity(nodes.len());
+    for ((name, node), mut samples) in nodes.iter().zip(samples) {
+        samples.sort_unstable();
+        let percentile = |percent: f64| {
+            let index = (percent / 100.0 * (samples.len() - 1) as f64).round() as usize;
+            samples[index]
+        };
+        let stats = if samples.is_empty() {
+            None
+        } else {
+            let mut stats = vec![("min", samples[0])];
+            stats.extend(
+                LATENCY_PERCENTILES
+                    .iter()
+                    .map(|(name, percent)| (*name, percentile(*percent))),
+            );
+            stats.push(("max", samples[samples.len() - 1]));
+            Some(stats)
+        };
+        let events = if server_events {
+            Some(latency_events(node).await?)
+        } else {
+            None
+        };
+        rows.push((name, samples.len(), stats, events));
+    }
+
+    let mut out = RecordSink::new(std::io::stdout().lock(), output);
+    let mut header = vec!["pings", "min", "p50", "p95", "p99", "max"];
+    if client.is_clustered() {
+        header.insert(0, "node");
+    }
+    match output {
+        OutputFormat::Plain => {
+            let mut b = tabled::builder::Builder::with_capacity(rows.len() + 1, header.len());
+            b.push_record(header);
+            for (name, pings, stats, _) in &rows {
+                let durations = stats.iter().flatten().map(|(_, duration)| {
+                    humantime::format_duration(Duration::from_micros(micros(*duration))).to_string()
+                });
+                b.push_record(
+                    name.iter()
+                        .cloned()
+                        .chain([pings.to_string()])
+                        .chain(durations),
+                );
+            }
+            let mut table = b.build();
+            table.with(tabled::settings::Style::psql());
+            let out = out.text();
+            writeln!(out, "{table}")?;
+            for (name, _, _, events) in &rows {
+                let Some((events, doctor)) = events else {
+                    continue;
+                };
+                if let Some(name) = name {
+                    writeln!(out, "\nNode {name}:")?;
+                }
+                let mut b = tabled::builder::Builder::with_capacity(events.len() + 1, 4);
+                b.push_record(["event", "time", "latest", "max"]);
+                for event in events {
+                    let time =
+                        std::time::UNIX_EPOCH + Duration::from_secs(event.time.max(0) as u64);
+                    b.push_record([
+                        event.name.clone(),
+                        humantime::format_rfc3339_seconds(time).to_string(),
+                        format!("{}ms", event.latest_ms),
+                        format!("{}ms", event.max_ms),
+                    ]);
+                }
+                let mut table = b.build();
+                table.with(tabled::settings::Style::psql());
+                writeln!(out, "{table}\n{}", doctor.trim_end())?;
+            }
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            for (index, (name, pings, stats, events)) in rows.iter().enumerate() {
+                let mut record = serde_json::json!({ "pings": pings });
+                for (stat, duration) in stats.iter().flatten() {
+                    record[format!("{stat}_us")] = micros(*duration).into();
+                }
+                if let Some(name) = name {
+                    record["node"] = name.clone().into();
+                }
+                if let Some((events, doctor)) = events {
+                    let events: Vec<_> = events
+                        .iter()
+                        .map(|event| {
+                            serde_json::json!({
+                                "event": event.name,
+                                "time": event.time,
+                                "latest_ms": event.latest_ms,
+                                "max_ms": event.max_ms,
+                                "history": event.history,
+                            })
+                        })
+                        .collect();
+                    record["events"] = events.into();
+                    record["doctor"] = doctor.clone().into();
+                }
+                write_json_record(out.text(), output, index, &record)?;
+            }
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            out.write_csv(header.iter().map(|field| match *field {
+                "node" | "pings" => field.to_string(),
+                stat => format!("{stat}_us"),
+            }))?;
+            for (name, pings, stats, _) in &rows {
+                let durations = stats
+                    .iter()
+                    .flatten()
+                    .map(|(_, duration)| micros(*duration).to_string());
+                out.write_csv(
+                    name.iter()
+                        .cloned()
+                        .chain([pings.to_string()])
+                        .chain(durations),
+                )?;
+            }
+        }
+    }
+    let _ = out.finish(output, rows.len())?;
+    Ok(())
+}
+
+/// Columns of the clients table, as CLIENT LIST fields
+const CLIENT_FIELDS: [&str; 7] = ["id", "addr", "name", "age", "idle", "cmd", "tot-mem"];
+
+/// Parse the "field=value" pairs of a CLIENT LIST line
+fn parse_client(line: &str) -> HashMap<&str, &str> {
+    line.split_whitespace()
+        .filter_map(|field| field.split_once('='))
+        .collect()
+}
+
+async fn clients(client: &Client, options: &ClientOptions) -> color_eyre::Result<()> {
+    let mut listed = Vec::new();
+    for node in primary_nodes(client) {
+        let name = node.as_ref().map(|server| server.to_string());
+        let node_client = client.with_options(&fred::types::config::Options {
+            cluster_node: node,
+            ..Default::default()
+        });
+        let own_id: i64 = node_client.client_id().await?;
+        let reply: String = node_client.client_list::<String, ()>(None, None).await?;
+        for line in reply.lines() {
+            let fields = parse_client(line);
+            if fields.get("id") == Some(&own_id.to_string().as_str()) || !options.matches(&fields) {
+                continue;
+            }
+            let fields: HashMap<String, String> = fields
+                .into_iter()
+                .map(|(field, value)| (field.to_owned(), value.to_owned()))
+                .collect();
+            listed.push((
+                node_client.options().cluster_node.clone(),
+                name.clone(),
+                fields,
+            ));
+        }
+    }
+
+    let output = options.output;
+    let mut out = RecordSink::new(std::io::stdout().lock(), output);
+    let mut header = CLIENT_FIELDS.to_vec();
+    if client.is_clustered() {
+        header.insert(0, "node");
+    }
+    let record = |node: &Option<String>, fields: &HashMap<String, String>, raw: bool| {
+        let field = |name: &str| fields.get(name).cloned().unwrap_or_default();
+        let seconds = |name: &str| match field(name).parse() {
+            Ok(seconds) if !raw => {
+                humantime::format_duration(Duration::from_secs(seconds)).to_string()
+            }
+            _ => field(name),
+        };
+        let memory = match field("tot-mem").parse() {
+            Ok(bytes) if !raw => HumanBytes(bytes).to_string(),
+            _ => field("tot-mem"),
+        };
+        node.iter()
+            .cloned()
+            .chain([
+                field("id"),
+                field("addr"),
+                field("name"),
+                seconds("age"),
+                seconds("idle"),
+                field("cmd"),
+                memory,
+            ])
+            .collect::<Vec<_>>()
+    };
+    match output {
+        OutputFormat::Plain => {
+            let mut b = tabled::builder::Builder::with_capacity(listed.len() + 1, header.len());
+            b.push_record(header);
+            for (_, node, fields) in &listed {
+                b.push_record(record(node, fields, false));
+            }
+            let mut table = b.build();
+            table.with(tabled::settings::Style::psql());
+            writeln!(out.text(), "{table}")?;
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            for (index, (_, node, fields)) in listed.iter().enumerate() {
+                let mut record = serde_json::Map::new();
+                if let Some(node) = node {
+                    record.insert("node".to_owned(), node.clone().into());
+                }
+                for (field, value) in fields {
+                    // Counters and durations are numbers, the other fields strings
+                    let value = match value.parse::<i64>() {
+                        Ok(number) if field != "name" => number.into(),
+                        _ => value.clone().into(),
+                    };
+                    record.insert(field.clone(), value);
+                }
+                write_json_record(out.text(), output, index, &record.into())?;
+            }
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            out.write_csv(&header)?;
+            for (_, node, fields) in &listed {
+                out.write_csv(record(node, fields, true))?;
+            }
+        }
+    }
+    let _ = out.finish(output, listed.len())?;
+
+    if !options.kill {
+        return Ok(());
+    }
+    if options.dry_run {
+        eprintln!("{} clients would be killed", listed.len());
+        return Ok(());
+    }
+    if listed.is_empty() {
+        return Ok(());
+    }
+    if !options.yes && !ask_confirmation(&format!("kill these {} clients", listed.len()))? {
+        return Err(eyre!("Kill aborted"));
+    }
+    let (mut killed, mut failed) = (0, 0);
+    let mut first_error = None;
+    for (server, _, fields) in &listed {
+        let node_client = client.with_options(&fred::types::config::Options {
+            cluster_node: server.clone(),
+            ..Default::default()
+        });
+        let id = fields.get("id").cloned().unwrap_or_default();
+        // Clients that disconnected in the meantime are not found, and counted as 0
+        let reply: FredResult<i64> = node_client
+            .client_kill(vec![ClientKillFilter::ID(id.clone())])
+            .await;
+        match reply {
+            Ok(count) => killed += count,
+            Err(error) if error.details().contains("No such client") => {}
+            Err(error) => {
+                failed += 1;
+                first_error.get_or_insert((id, error));
+            }
+        }
+    }
+    eprintln!("Killed {killed} clients, {failed} failed");
+    if let Some((id, error)) = first_error {
+        return Err(eyre!("Could not kill client {id}: {error}"));
+    }
+    Ok(())
+}
+
+/// Keys of a type using an encoding, and the bytes used by the measured ones
+#[derive(Debug, Default)]
+struct EncodingCount {
+    keys: usize,
+    memory: Measured,
+}
+
+async fn encoding_stats(
+    client: &Client,
+    scan_options: &ScanOptions,
+    sample_rate: f64,
+    output: OutputFormat,
+) -> color_eyre::Result<()> {
+    let mut counts = BTreeMap::<(String, String), EncodingCount>::new();
+    let pb = stats_progress()?;
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    while let Some(keys) = scanner.next_page().await? {
+        pb.inc(keys.len() as u64);
+        let key_types = key_types(client, &keys).await?;
+        let pipe = client.pipeline();
+        for key in &keys {
+            let _: () = pipe
+                .custom(
+                    object_command(),
+                    vec![Value::from("ENCODING"), key.clone().into()],
+                )
+                .await?;
+        }
+        let encodings = pipe.try_all::<Option<String>>().await;
+        let mut found = HashMap::with_capacity(keys.len());
+        for ((key, key_type), encoding) in keys.iter().zip(key_types).zip(encodings) {
+            // Deleted since it was scanned
+            let Ok(Some(encoding)) = encoding else {
+                continue;
+            };
+            if key_type == "none" {
+                continue;
+            }
+            let group = (key_type, encoding);
+            counts.entry(group.clone()).or_default().keys += 1;
+            found.insert(key, group);
+        }
+        if sample_rate > 0.0 {
+            let measured: Vec<&Key> = found.keys().copied().collect();
+            for (key, size) in sample_sizes(client, &measured, sample_rate, false).await? {
+                if let Some(count) = counts.get_mut(&found[key]) {
+                    count.memory.add(Measured {
+                        keys: 1,
+                        bytes: size,
+                    });
+                }
+            }
+        }
+        pb.set_message(format!("{} encodings", counts.len()));
+    }
+    pb.finish_and_clear();
+
+    let type_keys = counts.iter().fold(
+        HashMap::<&str, usize>::new(),
+        |mut totals, ((key_type, _), count)| {
+            *totals.entry(key_type.as_str()).or_default() += count.keys;
+            totals
+        },
+    );
+    let average = |count: &EncodingCount| {
+        (count.memory.keys > 0).then(|| count.memory.bytes / count.memory.keys as u64)
+    };
+    let mut out = RecordSink::new(std::io::stdout().lock(), output);
+    let header = [
+        "type",
+        "encoding",
+        "keys",
+        "share of type",
+        "measured",
+        "average memory",
+    ];
+    match output {
+        OutputFormat::Plain => {
+            let mut b = tabled::builder::Builder::with_capacity(counts.len() + 1, header.len());
+            b.push_record(header);
+            for ((key_type, encoding), count) in &counts {
+                let share = count.keys as f64 * 100.0 / type_keys[key_type.as_str()] as f64;
+                b.push_record([
+                    key_type.clone(),
+                    encoding.clone(),
+                    count.keys.to_string(),
+                    format!("{share:.1}%"),
+                    count.memory.keys.to_string(),
+                    average(count)
+                        .map_or_else(|| "-".to_owned(), |bytes| HumanBytes(bytes).to_string()),
+                ]);
+            }
+            let mut table = b.build();
+            table.with(tabled::settings::Style::psql());
+            writeln!(out.text(), "{table}")?;
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            for (index, ((key_type, encoding), count)) in counts.iter().enumerate() {
+                let record = serde_json::json!({
+                    "type": key_type,
+                    "encoding": encoding,
+                    "keys": count.keys,
+                    "type_keys": type_keys[key_type.as_str()],
+                    "measured": count.memory.keys,
+                    "average_memory": average(count),
+                });
+                write_json_record(out.text(), output, index, &record)?;
+            }
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            out.write_csv(header)?;
+            for ((key_type, encoding), count) in &counts {
+                let share = count.keys as f64 / type_keys[key_type.as_str()] as f64;
+                out.write_csv([
+                    key_type.clone(),
+                    encoding.clone(),
+                    count.keys.to_string(),
+                    format!("{share:.4}"),
+                    count.memory.keys.to_string(),
+                    average(count)
+                        .map(|bytes| bytes.to_string())
+                        .unwrap_or_default(),
+                ])?;
+            }
+        }
+    }
+    let _ = out.finish(output, counts.len())?;
+    Ok(())
+}
+
+/// Commands refused by foreach without --allow-dangerous, as they don't act on a single key
+const DANGEROUS_COMMANDS: [&str; 10] = [
+    "FLUSHALL", "FLUSHDB", "KEYS", "SHUTDOWN", "DEBUG", "CONFIG", "SWAPDB", "SCRIPT", "FUNCTION",
+    "CLUSTER",
+];
+
+/// Placeholder of a foreach command replaced by the key
+const KEY_PLACEHOLDER: &str = "{}";
+
+/// Command run by foreach, with placeholders standing for the key
+#[derive(Debug)]
+struct CommandTemplate {
+    name: String,
+    args: Vec<String>,
+    /// Index of the first argument with a placeholder, used to route the command in a cluster
+    key_index: usize,
+}
+
+impl CommandTemplate {
+    fn new(mut command: Vec<String>, allow_dangerous: bool) -> color_eyre::Result<Self> {
+        let name = command.remove(0).to_uppercase();
+        if !allow_dangerous && DANGEROUS_COMMANDS.contains(&name.as_str()) {
+            return Err(eyre!(
+                "{name} is a dangerous command, use --allow-dangerous to run it anyway"
+            ));
+        }
+        let key_index = command
+            .iter()
+            .position(|arg| arg.contains(KEY_PLACEHOLDER))
+            .ok_or_else(|| eyre!("the command must contain {KEY_PLACEHOLDER} for the key"))?;
+        Ok(CommandTemplate {
+            name,
+            args: command,
+            key_index,
+        })
+    }
+
+    fn command(&self) -> CustomCommand {
+        CustomCommand::new(
+            self.name.clone(),
+            ClusterHash::Offset(self.key_index),
+            false,
+        )
+    }
+
+    /// The arguments for a key, which is inserted as is even when it isn't UTF-8
+    fn render(&self, key: &Key) -> Vec<Value> {
+        self.args
+            .iter()
+            .map(|arg| {
+                let mut rendered = Vec::with_capacity(arg.len());
+                for (index, part) in arg.split(KEY_PLACEHOLDER).enumerate() {
+                    if index > 0 {
+                        rendered.extend_from_slice(key.as_bytes());
+                    }
+                    rendered.extend_from_slice(part.as_bytes());
+                }
+                Value::Bytes(rendered.into())
+            })
+            .collect()
+    }
+
+    /// The command for a key, as it would be typed in redis-cli
+    fn display(&self, key: &Key) -> String {
+        let key = key.as_str_lossy();
+        let args = self.args.iter().map(|arg| {
+            let arg = arg.replace(KEY_PLACEHOLDER, &key);
+            if arg.is_empty() || arg.contains(char::is_whitespace) || arg.contains('"') {
+                format!("{arg:?}")
+            } else {
+                arg
+            }
+        });
+        std::iter::once(self.name.clone())
+            .chain(args)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Number of commands shown by the foreach dry run
+const FOREACH_PREVIEW: usize = 20;
+
+async fn foreach(
+    client: &Client,
+    scan_options: &ScanOptions,
+    options: &BatchOptions,
+    template: &CommandTemplate,
+) -> color_eyre::Result<()> {
+    let keys = scan(client, scan_options).await?;
+    if options.dry_run {
+        println!(
+            "{} keys matched, {} commands would be run:",
+            keys.len(),
+            keys.len()
+        );
+        for key in keys.iter().take(FOREACH_PREVIEW) {
+            println!("{}", template.display(key));
+        }
+        if keys.len() > FOREACH_PREVIEW {
+            println!("...");
+        }
+        return Ok(());
+    }
+    let action = format!("run {} on them", template.name);
+    if !keys.is_empty() && !options.yes && !confirm_keys(&scan_options.pattern, &keys, &action)? {
+        return Err(eyre!("Foreach aborted"));
+    }
+    let message = format!(
+        "Running {} on pattern {}",
+        template.name, scan_options.pattern
+    );
+    let pb = keys_progress(keys.len(), message)?;
+    let mut pacing = Pacing::default();
+    let (mut succeeded, mut failed) = (0, 0);
+    let mut first_error = None;
+    for chunk in keys.chunks(options.batch_size as usize) {
+        pacing.wait(options, chunk.len()).await;
+        let pipe = client.pipeline();
+        for key in chunk {
+            let _: () = pipe
+                .custom(template.command(), template.render(key))
+                .await?;
+        }
+        for (key, reply) in chunk.iter().zip(pipe.try_all::<Value>().await) {
+            match reply {
+                Ok(_) => succeeded += 1,
+                Err(error) => {
+                    failed += 1;
+                    first_error.get_or_insert_with(|| (template.display(key), error));
+                }
+            }
+        }
+        pb.inc(chunk.len() as u64);
+    }
+    pb.finish();
+    eprintln!("Ran {succeeded} commands, {failed} failed");
+    if let Some((command, error)) = first_error {
+        eprintln!("First error, for {command}: {error}");
+    }
+    Ok(())
+}
+
+/// The part of a key hashed to find its cluster slot
+///
+/// That is the content of the first "{...}" when it isn't empty, and the whole key otherwise.
+fn hash_tag(key: &[u8]) -> &[u8] {
+    let Some(start) = key.iter().position(|&byte| byte == b'{') else {
+        return key;
+    };
+    match key[start + 1..].iter().position(|&byte| byte == b'}') {
+        Some(len) if len > 0 => &key[start + 1..start + 1 + len],
+        _ => key,
+    }
+}
+
+async fn hash_tag_stats(
+    client: &Client,
+    scan_options: &ScanOptions,
+    top: usize,
+    output: OutputFormat,
+) -> color_eyre::Result<()> {
+    let mut tags = HashMap::<Vec<u8>, u64>::new();
+    let mut total = 0;
+    let pb = stats_progress()?;
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    while let Some(keys) = scanner.next_page().await? {
+        pb.inc(keys.len() as u64);
+        total += keys.len();
+        for key in &keys {
+            let tag = hash_tag(key.as_bytes());
+            match tags.get_mut(tag) {
+                Some(count) => *count += 1,
+                None => {
+                    tags.insert(tag.to_vec(), 1);
+                }
+            }
+        }
+        pb.set_message(format!("{} tags", tags.len()));
+    }
+    pb.finish_and_clear();
+
+    let distinct = tags.len();
+    let mut largest = TopKeys::new(top);
+    for (tag, count) in tags {
+        let slot = fred::util::redis_keyslot(&tag);
+        largest.push(count, Key::from(tag.as_slice()), slot);
+    }
+    let largest = largest.into_sorted_vec();
+    let routing = client
+        .cached_cluster_state()
+        .filter(|_| client.is_clustered());
+    let node = |slot: u16| {
+        routing
+            .as_ref()
+            .and_then(|routing| routing.get_server(slot))
+            .map(|server| server.to_string())
+    };
+
+    let mut out = RecordSink::new(std::io::stdout().lock(), output);
+    let mut header = vec!["tag", "keys", "share", "slot"];
+    if routing.is_some() {
+        header.push("node");
+    }
+    let share = |count: u64| count as f64 / total.max(1) as f64;
+    match output {
+        OutputFormat::Plain => {
+            let mut b = tabled::builder::Builder::with_capacity(largest.len() + 1, header.len());
+            b.push_record(header);
+            for tag in &largest {
+                b.push_record(
+                    [
+                        tag.key.as_str_lossy().into_owned(),
+                        tag.score.to_string(),
+                        format!("{:.1}%", share(tag.score) * 100.0),
+                        tag.details.to_string(),
+                    ]
+                    .into_iter()
+                    .chain(node(tag.details)),
+                );
+            }
+            let mut table = b.build();
+            table.with(tabled::settings::Style::psql());
+            let out = out.text();
+            writeln!(out, "{table}")?;
+            writeln!(out, "{total} keys, {distinct} distinct tags")?;
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            for (index, tag) in largest.iter().enumerate() {
+                let mut record = serde_json::json!({
+                    "tag": tag.key.as_str_lossy(),
+                    "keys": tag.score,
+                    "share": share(tag.score),
+                    "slot": tag.details,
+                });
+                if let Some(node) = node(tag.details) {
+                    record["node"] = node.into();
+                }
+                write_json_record(out.text(), output, index, &record)?;
+            }
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            out.write_csv(&header)?;
+            for tag in &largest {
+                out.write_csv(
+                    [
+                        tag.key.as_str_lossy().into_owned(),
+                        tag.score.to_string(),
+                        format!("{:.4}", share(tag.score)),
+                        tag.details.to_string(),
+                    ]
+                    .into_iter()
+                    .chain(node(tag.details)),
+                )?;
+            }
+        }
+    }
+    let _ = out.finish(output, largest.len())?;
+    Ok(())
+}
+
+/// Indexes of the logical databases of the server
+async fn database_indexes(client: &Client) -> color_eyre::Result<Vec<u8>> {
+    let config: FredResult<HashMap<String, String>> = client.config_get("databases").await;
+    if let Some(databases) = config.ok().and_then(|config| config.into_values().next()) {
+        let databases: u16 = databases.parse()?;
+        return Ok((0..databases.min(256)).map(|db| db as u8).collect());
+    }
+    eprintln!("Warning: CONFIG GET failed, only listing the databases from INFO keyspace");
+    let info: String = client.info(Some(InfoKind::Keyspace)).await?;
+    Ok(parse_info(&info)
+        .keys()
+        .filter_map(|name| name.strip_prefix("db")?.parse().ok())
+        .collect())
+}
+
+async fn dbsize_all(client: &Client, all: bool, pattern: Option<&str>) -> color_eyre::Result<()> {
+    if client.is_clustered() {
+        return Err(eyre!("cluster nodes only have database 0"));
+    }
+    let mut databases = database_indexes(client).await?;
+    databases.sort_unstable();
+
+    let mut header = vec!["db", "keys"];
+    if pattern.is_some() {
+        header.push("matching");
+    }
+    let mut b = tabled::builder::Builder::with_capacity(databases.len() + 1, header.len());
+    b.push_record(header);
+    let (mut total, mut total_matching) = (0, 0);
+    for db in databases {
+        // SELECT would change the database of the shared connection, so each gets its own client
+        let mut config = client.client_config();
+        config.database = Some(db);
+        let db_client = Client::new(
+            config,
+            Some(client.perf_config()),
+            Some(client.connection_config().clone()),
+            None,
+        );
+        db_client.init().await?;
+        let keys: u64 = db_client.dbsize().await?;
+        total += keys;
+        if keys == 0 && !all {
+            db_client.quit().await?;
+            continue;
+        }
+        let mut record = vec![db.to_string(), keys.to_string()];
+        if let Some(pattern) = pattern {
+            let mut matching = 0;
+            if keys > 0 {
+                let mut pages = scan_stream(&db_client, pattern, 10_000, None)?;
+                while let Some(mut page) = pages.next().await.transpose()? {
+                    matching += page.take_results().unwrap_or_default().len() as u64;
+                }
+            }
+            total_matching += matching;
+            record.push(matching.to_string());
+        }
+        b.push_record(record);
+        db_client.quit().await?;
+    }
+    let mut record = vec!["(total)".to_owned(), total.to_string()];
+    if pattern.is_some() {
+        record.push(total_matching.to_string());
+    }
+    b.push_record(record);
+    let mut table = b.build();
+    table.with(tabled::settings::Style::psql());
+    println!("{table}");
+    Ok(())
+}
+
+/// Check run by verify-values on each string value
+enum ValueValidator {
+    Regex(Regex),
+    Schema(Box<jsonschema::Validator>),
+}
+
+impl ValueValidator {
+    fn new(check: &ValueCheck) -> color_eyre::Result<Self> {
+        if let Some(regex) = &check.value_regex {
+            return Ok(ValueValidator::Regex(regex.clone()));
+        }
+        let path = check
+            .json_schema
+            .as_ref()
+            .ok_or_else(|| eyre!("--value-regex or --json-schema is required"))?;
+        let schema: serde_json::Value = serde_json::from_reader(File::open(path)?)?;
+        let validator = jsonschema::validator_for(&schema)
+            .map_err(|e| eyre!("invalid JSON schema {}: {e}", path.display()))?;
+        Ok(ValueValidator::Schema(Box::new(validator)))
+    }
+
+    /// Why the value fails the check, if it does
+    fn failure(&self, value: &[u8]) -> Option<String> {
+        match self {
+            ValueValidator::Regex(regex) => {
+                (!regex.is_match(value)).then(|| format!("does not match {}", regex.as_str()))
+            }
+            ValueValidator::Schema(validator) => {
+                let document: serde_json::Value = match serde_json::from_slice(value) {
+                    Ok(document) => document,
+                    Err(e) => return Some(format!("invalid JSON: {e}")),
+                };
+                let error = validator.validate(&document).err()?;
+                let path = error.instance_path().to_string();
+                Some(match path.as_str() {
+                    "" => error.to_string(),
+                    path => format!("{path}: {error}"),
+                })
+            }
+        }
+    }
+}
+
+async fn verify_values(
+    client: &Client,
+    scan_options: &ScanOptions,
+    check: &ValueCheck,
+    limit_failures: Option<u64>,
+) -> color_eyre::Result<()> {
+    let validator = ValueValidator::new(check)?;
+    let mut out = std::io::stdout().lock();
+    let (mut passed, mut failed, mut skipped) = (0u64, 0u64, 0u64);
+    let pb = stats_progress()?;
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    'pages: while let Some(keys) = scanner.next_page().await? {
+        pb.inc(keys.len() as u64);
+        let key_types = key_types(client, &keys).await?;
+        let strings: Vec<&Key> = keys
+            .iter()
+            .zip(&key_types)
+            .filter(|(_, key_type)| *key_type == "string")
+            .map(|(key, _)| key)
+            .collect();
+        // Other types are skipped, while deleted keys are not counted at all
+        skipped += key_types
+            .iter()
+            .filter(|key_type| !matches!(key_type.as_str(), "string" | "none"))
+            .count() as u64;
+        if strings.is_empty() {
+            continue;
+        }
+        let pipe = client.pipeline();
+        for key in &strings {
+            let _: () = pipe.get(*key).await?;
+        }
+        let values = pipe.try_all::<Option<Vec<u8>>>().await;
+        for (key, value) in strings.into_iter().zip(values) {
+            // Deleted or replaced since its type was read
+            let Ok(Some(value)) = value else {
+                continue;
+            };
+            let Some(reason) = validator.failure(&value) else {
+                passed += 1;
+                continue;
+            };
+            failed += 1;
+            pb.suspend(|| writeln!(out, "{}\t{reason}", key.as_str_lossy()))?;
+            if limit_failures.is_some_and(|limit| failed >= limit) {
+                break 'pages;
+            }
+        }
+        pb.set_message(format!("{passed} passed, {failed} failed"));
+    }
+    pb.finish_and_clear();
+    let stopped = if limit_failures.is_some_and(|limit| failed >= limit) {
+        ", stopped at the failure limit"
+    } else {
+        ""
+    };
+    eprintln!("{passed} passed, {failed} failed, {skipped} not strings skipped{stopped}");
+    Ok(())
+}
+
+/// Number of keys of a duplicates group shown in the plain output
+const DUPLICATES_SHOWN: usize = 3;
+
+/// Keys holding the same value, and its size
+#[derive(Debug, Default)]
+struct Duplicates {
+    size: u64,
+    keys: Vec<Key>,
+}
+
+impl Duplicates {
+    /// Bytes used by the copies of the value
+    fn total(&self) -> u64 {
+        self.size * self.keys.len() as u64
+    }
+
+    /// Bytes saved by keeping a single copy of the value
+    fn reclaimable(&self) -> u64 {
+        self.size * (self.keys.len() as u64).saturating_sub(1)
+    }
+}
+
+async fn find_duplicates(
+    client: &Client,
+    scan_options: &ScanOptions,
+    min_size: u64,
+    top: usize,
+    output: OutputFormat,
+) -> color_eyre::Result<()> {
+    let mut values = HashMap::<u128, Duplicates>::new();
+    let pb = stats_progress()?;
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    while let Some(keys) = scanner.next_page().await? {
+        pb.inc(keys.len() as u64);
+        // Sizes come first so that small values are not fetched at all
+        let pipe = client.pipeline();
+        for key in &keys {
+            let _: () = pipe.strlen(key).await?;
+        }
+        let lengths = pipe.try_all::<u64>().await;
+        let large: Vec<Key> = keys
+            .into_iter()
+            .zip(lengths)
+            // Keys of other types fail with WRONGTYPE, and missing ones have no length
+            .filter(|(_, len)| len.as_ref().is_ok_and(|&len| len > 0 && len >= min_size))
+            .map(|(key, _)| key)
+            .collect();
+        if large.is_empty() {
+            continue;
+        }
+        let pipe = client.pipeline();
+        for key in &large {
+            let _: () = pipe.get(key).await?;
+        }
+        let fetched = pipe.try_all::<Option<Vec<u8>>>().await;
+        for (key, value) in large.into_iter().zip(fetched) {
+            // Deleted or replaced since it was measured
+            let Ok(Some(value)) = value else {
+                continue;
+            };
+            let duplicates = values
+                .entry(xxhash_rust::xxh3::xxh3_128(&value))
+                .or_default();
+            duplicates.size = value.len() as u64;
+            duplicates.keys.push(key);
+        }
+        pb.set_message(format!("{} distinct values", values.len()));
+    }
+    pb.finish_and_clear();
+
+    let mut groups: Vec<Duplicates> = values
+        .into_values()
+        .filter(|duplicates| duplicates.keys.len() > 1)
+        .collect();
+    let duplicated_groups = groups.len();
+    let reclaimable: u64 = groups.iter().map(Duplicates::reclaimable).sum();
+    groups.sort_by_key(|duplicates| std::cmp::Reverse(duplicates.total()));
+    groups.truncate(top);
+    for duplicates in &mut groups {
+        duplicates.keys.sort();
+    }
+
+    let mut out = RecordSink::new(std::io::stdout().lock(), output);
+    let header = ["keys", "value size", "reclaimable", "key names"];
+    let names = |keys: &[Key], limit: usize| {
+        let mut names: Vec<String> = keys
+            .iter()
+            .take(limit)
+            .map(|key| key.as_str_lossy().into_owned())
+            .collect();
+        if keys.len() > limit {
+            names.push(format!("(and {} more)", keys.len() - limit));
+        }
+        names.join(" ")
+    };
+    match output {
+        OutputFormat::Plain => {
+            let mut b = tabled::builder::Builder::with_capacity(groups.len() + 1, header.len());
+            b.push_record(header);
+            for duplicates in &groups {
+                b.push_record([
+                    duplicates.keys.len().to_string(),
+                    HumanBytes(duplicates.size).to_string(),
+                    HumanBytes(duplicates.reclaimable()).to_string(),
+                    names(&duplicates.keys, DUPLICATES_SHOWN),
+                ]);
+            }
+            let mut table = b.build();
+            table.with(tabled::settings::Style::psql());
+            let out = out.text();
+            writeln!(out, "{table}")?;
+            writeln!(
+                out,
+                "{duplicated_groups} duplicated values, {} reclaimable by deduplicating them",
+                HumanBytes(reclaimable)
+            )?;
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            for (index, duplicates) in groups.iter().enumerate() {
+                let keys: Vec<_> = duplicates
+                    .keys
+                    .iter()
+                    .map(|key| key.as_str_lossy())
+                    .collect();
+                let record = serde_json::json!({
+                    "keys": keys,
+                    "value_size": duplicates.size,
+                    "reclaimable": duplicates.reclaimable(),
+                });
+                write_json_record(out.text(), output, index, &record)?;
+            }
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            out.write_csv(header)?;
+            for duplicates in &groups {
+                out.write_csv([
+                    duplicates.keys.len().to_string(),
+                    duplicates.size.to_string(),
+                    duplicates.reclaimable().to_string(),
+                    names(&duplicates.keys, usize::MAX),
+                ])?;
+            }
+        }
+    }
+    let _ = out.finish(output, groups.len())?;
+    if output != OutputFormat::Plain {
+        eprintln!(
+            "{duplicated_groups} duplicated values, {} reclaimable by deduplicating them",
+            HumanBytes(reclaimable)
+        );
+    }
+    Ok(())
+}
+
+/// Bytes shown on each side of a match by grep-values --show-match
+const MATCH_CONTEXT: usize = 40;
+
+/// The line around a match, cut to [MATCH_CONTEXT] bytes on each side
+fn match_snippet(value: &[u8], start: usize, end: usize) -> String {
+    let line_start = value[..start]
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    let line_end = value[end..]
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(value.len(), |newline| end + newline);
+    let from = line_start.max(start.saturating_sub(MATCH_CONTEXT));
+    let to = line_end.min(end + MATCH_CONTEXT);
+    let mut snippet = String::from_utf8_lossy(&value[from..to]).into_owned();
+    if from > line_start {
+        snippet.insert_str(0, "...");
+    }
+    if to < line_end {
+        snippet.push_str("...");
+    }
+    // Keep one match per output line
+    snippet.replace(['\r', '\t'], " ")
+}
+
+async fn grep_values(
+    client: &Client,
+    scan_options: &ScanOptions,
+    regex: &Regex,
+    options: &GrepOptions,
+) -> color_eyre::Result<()> {
+    let mut out = std::io::stdout().lock();
+    let (mut searched, mut matched, mut too_large) = (0u64, 0u64, 0u64);
+    let pb = stats_progress()?;
+    let mut scanner = KeyScanner::new(client, scan_options)?;
+    'pages: while let Some(keys) = scanner.next_page().await? {
+        pb.inc(keys.len() as u64);
+        // Sizes come first so that large values are not fetched
+        let pipe = client.pipeline();
+        for key in &keys {
+            let _: () = pipe.strlen(key).await?;
+        }
+        let lengths = pipe.try_all::<u64>().await;
+        let mut fetched = Vec::with_capacity(keys.len());
+        for (key, len) in keys.into_iter().zip(lengths) {
+            // Keys of other types fail with WRONGTYPE
+            let Ok(len) = len else {
+                continue;
+            };
+            if options.max_value_size.is_some_and(|max| len > max) {
+                too_large += 1;
+            } else {
+                fetched.push(key);
+            }
+        }
+        if fetched.is_empty() {
+            continue;
+        }
+        let pipe = client.pipeline();
+        for key in &fetched {
+            let _: () = pipe.get(key).await?;
+        }
+        let values = pipe.try_all::<Option<Vec<u8>>>().await;
+        for (key, value) in fetched.iter().zip(values) {
+            // Deleted or replaced since it was measured
+            let Ok(Some(value)) = value else {
+                continue;
+            };
+            searched += 1;
+            let Some(found) = regex.find(&value) else {
+                continue;
+            };
+            matched += 1;
+            let key = key.as_str_lossy();
+            pb.suspend(|| {
+                if options.show_match {
+                    let snippet = match_snippet(&value, found.start(), found.end());
+                    writeln!(out, "{key}\t{snippet}")
+                } else {
+                    writeln!(out, "{key}")
+                }
+            })?;
+            if options.limit_matches.is_some_and(|limit| matched >= limit) {
+                break 'pages;
+            }
+        }
+        pb.set_message(format!("{searched} values searched, {matched} matching"));
+    }
+    pb.finish_and_clear();
+    eprintln!("{matched} of {searched} values matched, {too_large} too large skipped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(count: usize, template: &str) -> Vec<Key> {
+        (0..count)
+            .map(|index| Key::from(template.replace("{}", &index.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn glob_match_follows_redis() {
+        let cases: [(&[u8], &[u8], bool); 31] = [
+            // Runs of stars
+            (b"*", b"", true),
+            (b"***", b"", true),
+            (b"a**b", b"axxb", true),
+            (b"a*b*c", b"abc", true),
+            (b"a*b*c", b"acb", false),
+            (b"*:*", b"user:1", true),
+            (b"*:*", b"user", false),
+            // Empty key
+            (b"", b"", true),
+            (b"?", b"", false),
+            (b"a", b"", false),
+            (b"[a]", b"", false),
+            // A dash before the closing bracket starts a range ending with "]", as in Redis
+            (b"[a-]", b"a", true),
+            (b"[a-]", b"]", true),
+            (b"[a-]", b"_", true),
+            (b"[a-]", b"-", false),
+            (b"[a-]", b"b", false),
+            (b"[a-c]", b"b", true),
+            (b"[c-a]", b"b", true),
+            // Negated sets
+            (b"[^x]", b"y", true),
+            (b"[^x]", b"x", false),
+            (b"[^x]", b"", false),
+            // Escapes
+            (b"a\\*", b"a*", true),
+            (b"a\\*", b"ab", false),
+            (b"[\\]]", b"]", true),
+            // An unclosed set ends at the end of the pattern
+            (b"[ab", b"a", true),
+            (b"[ab", b"c", false),
+            (b"x[ab", b"xb", true),
+            // Bytes that aren't UTF-8
+            (b"\xff*", b"\xff\xfe", true),
+            (b"[\x80-\xff]", b"\x90", true),
+            (b"[\x80-\xff]", b"a", false),
+            (b"?\xfe", b"\xff\xfe", true),
+        ];
+        for (pattern, key, expected) in cases {
+            assert_eq!(
+                glob_match(pattern, key),
+                expected,
+                "{} against {}",
+                String::from_utf8_lossy(pattern),
+                String::from_utf8_lossy(key)
+            );
+        }
+    }
+
+    #[test]
+    fn export_files_round_trip() {
+        let header = ExportHeader {
+            server_version: Some("7.2.4".to_owned()),
+            exported_at_ms: 1_700_000_000_000,
+        };
+        let entries = [
+            ExportEntry {
+                key: b"user:1".to_vec(),
+                expire_at_ms: Some(1_700_000_060_000),
+                payload: vec![0, 1, 2, 255],
+            },
+            // Not UTF-8, so written as base64 in JSON lines
+            ExportEntry {
+                key: vec![b'k', 0xff, 0xfe],
+                expire_at_ms: None,
+                payload: Vec::new(),
+            },
+            ExportEntry {
+                key: Vec::new(),
+                expire_at_ms: Some(0),
+                payload: b"payload".to_vec(),
+            },
+        ];
+        for format in [ExportFormat::Binary, ExportFormat::Jsonl] {
+            let mut writer = ExportWriter::new(Vec::new(), format, &header).unwrap();
+            for entry in &entries {
+                writer.write(entry).unwrap();
+            }
+            let bytes = writer.bytes;
+            let file = writer.into_inner();
+            assert_eq!(bytes, file.len() as u64);
+
+            let mut reader = ExportReader::new(file.as_slice()).unwrap();
+            assert_eq!(reader.format, format);
+            assert_eq!(reader.header.server_version, header.server_version);
+            assert_eq!(reader.header.exported_at_ms, header.exported_at_ms);
+            let mut read = Vec::new();
+            while let Some(entry) = reader.next_entry().unwrap() {
+                read.push(entry);
+            }
+            assert_eq!(read, entries);
+        }
+    }
+
+    #[test]
+    fn read_chunk_rejects_truncated_chunks() {
+        // A length of 4 GiB followed by 3 bytes
+        let mut input: &[u8] = &[0xff, 0xff, 0xff, 0xff, 1, 2, 3];
+        let error = read_chunk(&mut input).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        let mut input: &[u8] = &[2, 0, 0, 0, 1, 2, 3];
+        assert_eq!(read_chunk(&mut input).unwrap(), [1, 2]);
+        assert_eq!(input, [3]);
+    }
+
+    #[test]
+    fn delete_batches_send_a_command_per_batch() {
+        let keys = keys(2500, "user:{}");
+        for batch_size in [1, 7, 1000, 2500, 10_000] {
+            let batches: Vec<_> = delete_batches(&keys, batch_size, false).collect();
+            assert_eq!(batches.len(), keys.len().div_ceil(batch_size));
+            for batch in &batches {
+                assert!(batch.keys.len() <= batch_size);
+                assert_eq!(batch.commands, [batch.keys.to_vec()]);
+            }
+            let deleted: Vec<Key> = batches
+                .into_iter()
+                .flat_map(|batch| batch.commands)
+                .flatten()
+                .collect();
+            assert_eq!(deleted, keys);
+        }
+    }
+
+    #[test]
+    fn delete_batches_group_cluster_commands_by_slot() {
+        // Keys sharing a hash tag belong to the same slot, and are batched as without a cluster
+        let tagged = keys(2500, "{tenant}:{}");
+        let commands: Vec<Vec<Key>> = delete_batches(&tagged, 1000, true)
+            .flat_map(|batch| batch.commands)
+            .collect();
+        assert_eq!(
+            commands.iter().map(Vec::len).collect::<Vec<_>>(),
+            [1000, 1000, 500]
+        );
+        assert_eq!(commands.concat(), tagged);
+
+        let keys = keys(2500, "user:{}");
+        let batches: Vec<_> = delete_batches(&keys, 1000, true).collect();
+        assert_eq!(batches.len(), 3);
+        for batch in &batches {
+            assert!(batch.keys.len() <= 1000);
+            for command in &batch.commands {
+                let slot = fred::util::redis_keyslot(command[0].as_bytes());
+                assert!(
+                    command
+                        .iter()
+                        .all(|key| fred::util::redis_keyslot(key.as_bytes()) == slot)
+                );
+            }
+            let mut deleted = batch.commands.concat();
+            deleted.sort();
+            let mut expected = batch.keys.to_vec();
+            expected.sort();
+            assert_eq!(deleted, expected);
+        }
+    }
 }